@@ -0,0 +1,463 @@
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use crate::marks::Marks;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+}
+
+/// A logical position: line index and character offset inside the line.
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Debug, Default)]
+pub struct Position {
+    pub line: usize,
+    pub col: usize,
+}
+
+impl Position {
+    pub fn new(line: usize, col: usize) -> Self {
+        Self { line, col }
+    }
+}
+
+/// Lines replaced since the view last caught up with the text: `removed` lines from `first` in the
+/// text the view shows are now `added` lines.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct ChangedLines {
+    pub first: usize,
+    pub removed: usize,
+    pub added: usize,
+}
+
+impl ChangedLines {
+    /// One change covering this one and `next`, which was made after it.
+    fn then(self, next: ChangedLines) -> ChangedLines {
+        let first = self.first.min(next.first);
+        // End of both changes in the text between them, which maps back past the end of this one
+        let end = (self.first + self.added).max(next.first + next.removed);
+        ChangedLines { first, removed: end + self.removed - self.added - first, added: end + next.added - next.removed - first }
+    }
+}
+
+/// Undo steps kept; the oldest are dropped first.
+const MAX_UNDO_STEPS: usize = 1000;
+
+/// Lines `first..first + inserted.len()` that replaced `removed`. Applying the reverse edit undoes it.
+struct LineEdit {
+    first: usize,
+    removed: Vec<String>,
+    inserted: Vec<String>,
+}
+
+/// Edits undone and redone together, with the state around them that is restored along with the text.
+struct UndoStep {
+    edits: Vec<LineEdit>,
+    version: usize,
+    cursor: Position,
+    marks: Marks,
+}
+
+/// The text of the open file as logical lines, plus what is needed to write it back unchanged.
+pub struct Buffer {
+    path: PathBuf,
+    lines: Vec<String>,
+    line_ending: LineEnding,
+    saved_line_ending: LineEnding,
+    trailing_newline: bool,
+    /// Identifies the current text; equal to `saved_version` when nothing changed since the last save.
+    version: usize,
+    saved_version: usize,
+    next_version: usize,
+    undo_stack: Vec<UndoStep>,
+    redo_stack: Vec<UndoStep>,
+    /// Named marks and the jump list, kept here so every edit can move them along with the text.
+    marks: Marks,
+    changed: Option<ChangedLines>,
+}
+
+impl Buffer {
+    pub fn from_text(path: PathBuf, text: &str) -> Self {
+        let line_ending = match text.find('\n') {
+            Some(i) if text[..i].ends_with('\r') => LineEnding::CrLf,
+            _ => LineEnding::Lf,
+        };
+        let mut lines: Vec<String> = text.lines().map(str::to_string).collect();
+        if lines.is_empty() {
+            lines.push(String::new());
+        }
+        Self {
+            path,
+            lines,
+            line_ending,
+            saved_line_ending: line_ending,
+            trailing_newline: text.ends_with('\n'),
+            version: 0,
+            saved_version: 0,
+            next_version: 1,
+            undo_stack: Vec::new(),
+            redo_stack: Vec::new(),
+            marks: Marks::default(),
+            changed: None,
+        }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn file_name(&self) -> String {
+        self.path.file_name().map_or_else(|| self.path.display().to_string(), |name| name.to_string_lossy().into_owned())
+    }
+
+    /// Lines joined with `\n`, the form the wrapper and tokenizer work on.
+    pub fn text(&self) -> String {
+        self.lines.join("\n")
+    }
+
+    /// Lines changed since the last call, so the view only has to rewrap and tokenize those.
+    pub fn take_changed_lines(&mut self) -> Option<ChangedLines> {
+        self.changed.take()
+    }
+
+    pub fn marks(&self) -> &Marks {
+        &self.marks
+    }
+
+    pub fn marks_mut(&mut self) -> &mut Marks {
+        &mut self.marks
+    }
+
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.line_ending = line_ending;
+    }
+
+    /// Identifies the current text; it changes with every edit, undo and redo.
+    pub fn version(&self) -> usize {
+        self.version
+    }
+
+    pub fn is_modified(&self) -> bool {
+        self.version != self.saved_version || self.line_ending != self.saved_line_ending
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn line(&self, line: usize) -> &str {
+        self.lines.get(line).map_or("", String::as_str)
+    }
+
+    pub fn line_len(&self, line: usize) -> usize {
+        self.line(line).chars().count()
+    }
+
+    /// Clamps a position to the text that exists.
+    pub fn clamp(&self, position: Position) -> Position {
+        let line = position.line.min(self.lines.len() - 1);
+        Position::new(line, position.col.min(self.line_len(line)))
+    }
+
+    pub fn end(&self) -> Position {
+        let line = self.lines.len() - 1;
+        Position::new(line, self.line_len(line))
+    }
+
+    /// Character offset of a position in `text()`, counting each line break as one character.
+    pub fn offset_of(&self, position: Position) -> usize {
+        let position = self.clamp(position);
+        self.lines[..position.line].iter().map(|l| l.chars().count() + 1).sum::<usize>() + position.col
+    }
+
+    pub fn position_of(&self, offset: usize) -> Position {
+        let mut remaining = offset;
+        for (line, text) in self.lines.iter().enumerate() {
+            let len = text.chars().count();
+            if remaining <= len {
+                return Position::new(line, remaining);
+            }
+            remaining -= len + 1;
+        }
+        self.end()
+    }
+
+    /// Text between two positions; the order of the arguments does not matter.
+    pub fn slice(&self, a: Position, b: Position) -> String {
+        let (start, end) = (self.clamp(a.min(b)), self.clamp(a.max(b)));
+        if start.line == end.line {
+            return self.line(start.line).chars().skip(start.col).take(end.col - start.col).collect();
+        }
+        let mut text: String = self.line(start.line).chars().skip(start.col).collect();
+        for line in start.line + 1..end.line {
+            text.push('\n');
+            text.push_str(self.line(line));
+        }
+        text.push('\n');
+        text.extend(self.line(end.line).chars().take(end.col));
+        text
+    }
+
+    /// Starts a new undo step: the edits from here to the next checkpoint are undone together.
+    pub fn checkpoint(&mut self, cursor: Position) {
+        let step = self.step(cursor);
+        self.undo_stack.push(step);
+        if self.undo_stack.len() > MAX_UNDO_STEPS {
+            self.undo_stack.remove(0);
+        }
+        self.redo_stack.clear();
+    }
+
+    /// Inserts text, which may contain line breaks, and returns the position just after it.
+    pub fn insert(&mut self, at: Position, text: &str) -> Position {
+        let at = self.clamp(at);
+        let line = &self.lines[at.line];
+        let split = byte_index(line, at.col);
+        let tail = line[split..].to_string();
+        let head = line[..split].to_string();
+
+        let mut inserted: Vec<String> = text.split('\n').map(str::to_string).collect();
+        let last = inserted.len() - 1;
+        let end = Position::new(at.line + last, if last == 0 { at.col } else { 0 } + inserted[last].chars().count());
+        inserted[0].insert_str(0, &head);
+        inserted[last].push_str(&tail);
+        self.edit(at.line, 1, inserted);
+        self.marks.inserted(at, end);
+        end
+    }
+
+    /// Removes the text between two positions and returns it.
+    pub fn delete(&mut self, a: Position, b: Position) -> String {
+        let (start, end) = (self.clamp(a.min(b)), self.clamp(a.max(b)));
+        let removed = self.slice(start, end);
+        if removed.is_empty() {
+            return removed;
+        }
+        let head: String = self.line(start.line).chars().take(start.col).collect();
+        let tail: String = self.line(end.line).chars().skip(end.col).collect();
+        self.edit(start.line, end.line - start.line + 1, vec![head + &tail]);
+        self.marks.deleted(start, end);
+        removed
+    }
+
+    /// Replaces whole lines `first..=last` with `replacement`.
+    pub fn replace_lines(&mut self, first: usize, last: usize, mut replacement: Vec<String>) {
+        let last = last.min(self.lines.len() - 1);
+        if replacement.is_empty() && first == 0 && last == self.lines.len() - 1 {
+            replacement.push(String::new());
+        }
+        self.marks.replaced_lines(first, last, replacement.len());
+        self.edit(first, last - first + 1, replacement);
+    }
+
+    /// Restores the text, marks and version before the last checkpoint and returns where the cursor was.
+    pub fn undo(&mut self, cursor: Position) -> Option<Position> {
+        let step = self.undo_stack.pop()?;
+        let (redo, cursor) = self.revert(step, cursor);
+        self.redo_stack.push(redo);
+        Some(cursor)
+    }
+
+    pub fn redo(&mut self, cursor: Position) -> Option<Position> {
+        let step = self.redo_stack.pop()?;
+        let (undo, cursor) = self.revert(step, cursor);
+        self.undo_stack.push(undo);
+        Some(cursor)
+    }
+
+    fn step(&self, cursor: Position) -> UndoStep {
+        UndoStep { edits: Vec::new(), version: self.version, cursor, marks: self.marks.clone() }
+    }
+
+    /// Reverses the edits of a step and returns the step that reverses them again, recorded with
+    /// `cursor`, along with where the cursor was when `step` was recorded.
+    fn revert(&mut self, step: UndoStep, cursor: Position) -> (UndoStep, Position) {
+        let mut reverted = self.step(cursor);
+        for edit in step.edits.into_iter().rev() {
+            reverted.edits.push(self.splice(edit.first, edit.inserted.len(), edit.removed));
+        }
+        self.marks = step.marks;
+        self.version = step.version;
+        (reverted, step.cursor)
+    }
+
+    /// Replaces `count` lines from `first` with `lines` as part of the current undo step.
+    fn edit(&mut self, first: usize, count: usize, lines: Vec<String>) {
+        let edit = self.splice(first, count, lines);
+        if self.undo_stack.is_empty() {
+            let step = self.step(Position::new(first, 0));
+            self.undo_stack.push(step);
+        }
+        let step = self.undo_stack.last_mut().expect("an undo step was just made");
+        match step.edits.last_mut() {
+            // Typing keeps changing the same line: one edit from its first to its last text is enough
+            Some(last) if last.first == edit.first && last.inserted == edit.removed => last.inserted = edit.inserted,
+            _ => step.edits.push(edit),
+        }
+        self.redo_stack.clear();
+        self.touch();
+    }
+
+    fn splice(&mut self, first: usize, count: usize, lines: Vec<String>) -> LineEdit {
+        let added = lines.len();
+        let removed = self.lines.splice(first..first + count, lines).collect();
+        let change = ChangedLines { first, removed: count, added };
+        self.changed = Some(self.changed.map_or(change, |changed| changed.then(change)));
+        LineEdit { first, removed, inserted: self.lines[first..first + added].to_vec() }
+    }
+
+    fn touch(&mut self) {
+        self.version = self.next_version;
+        self.next_version += 1;
+    }
+
+    /// Text as it is written to disk, with the file's own line endings.
+    pub fn to_file_text(&self) -> String {
+        let ending = self.line_ending.as_str();
+        let mut text = self.lines.join(ending);
+        if self.trailing_newline {
+            text.push_str(ending);
+        }
+        text
+    }
+
+    pub fn save(&mut self) -> io::Result<()> {
+        fs::write(&self.path, self.to_file_text())?;
+        self.saved_version = self.version;
+        self.saved_line_ending = self.line_ending;
+        Ok(())
+    }
+}
+
+/// Byte index of the character at `col`, or the end of the line.
+pub fn byte_index(line: &str, col: usize) -> usize {
+    line.char_indices().nth(col).map_or(line.len(), |(i, _)| i)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn keeps_crlf_and_trailing_newline() {
+        let buffer = Buffer::from_text(PathBuf::from("a.txt"), "one\r\ntwo\r\n");
+        assert_eq!(buffer.line_ending(), LineEnding::CrLf);
+        assert_eq!(buffer.text(), "one\ntwo");
+        assert_eq!(buffer.to_file_text(), "one\r\ntwo\r\n");
+    }
+
+    #[test]
+    fn converting_line_endings_marks_the_buffer_modified() {
+        let mut buffer = Buffer::from_text(PathBuf::from("a.txt"), "one\ntwo");
+        buffer.set_line_ending(LineEnding::Lf);
+        assert!(!buffer.is_modified());
+        buffer.set_line_ending(LineEnding::CrLf);
+        assert!(buffer.is_modified());
+        assert_eq!(buffer.to_file_text(), "one\r\ntwo");
+    }
+
+    #[test]
+    fn insert_and_delete_across_lines() {
+        let mut buffer = Buffer::from_text(PathBuf::from("a.txt"), "héllo\nworld");
+        let end = buffer.insert(Position::new(0, 2), "X\nY");
+        assert_eq!(end, Position::new(1, 1));
+        assert_eq!(buffer.text(), "héX\nYllo\nworld");
+
+        let removed = buffer.delete(Position::new(1, 1), Position::new(2, 2));
+        assert_eq!(removed, "llo\nwo");
+        assert_eq!(buffer.text(), "héX\nYrld");
+    }
+
+    #[test]
+    fn offsets_count_line_breaks() {
+        let buffer = Buffer::from_text(PathBuf::from("a.txt"), "ab\ncd");
+        assert_eq!(buffer.offset_of(Position::new(1, 1)), 4);
+        assert_eq!(buffer.position_of(4), Position::new(1, 1));
+        assert_eq!(buffer.position_of(2), Position::new(0, 2));
+    }
+
+    #[test]
+    fn undo_and_redo_restore_text_and_cursor() {
+        let mut buffer = Buffer::from_text(PathBuf::from("a.txt"), "abc");
+        buffer.checkpoint(Position::new(0, 1));
+        buffer.insert(Position::new(0, 1), "XY");
+        assert!(buffer.is_modified());
+
+        assert_eq!(buffer.undo(Position::new(0, 3)), Some(Position::new(0, 1)));
+        assert_eq!(buffer.text(), "abc");
+        assert!(!buffer.is_modified());
+
+        assert_eq!(buffer.redo(Position::new(0, 1)), Some(Position::new(0, 3)));
+        assert_eq!(buffer.text(), "aXYbc");
+        assert_eq!(buffer.undo(Position::new(0, 0)), Some(Position::new(0, 1)));
+        assert_eq!(buffer.undo(Position::new(0, 0)), None);
+    }
+
+    #[test]
+    fn undo_steps_keep_only_the_edited_lines_and_restore_marks() {
+        let mut buffer = Buffer::from_text(PathBuf::from("a.txt"), "one\ntwo\nthree\nfour");
+        buffer.marks_mut().set('a', Position::new(3, 1));
+        buffer.checkpoint(Position::new(1, 0));
+        for (col, c) in "abc".chars().enumerate() {
+            buffer.insert(Position::new(1, col), &c.to_string());
+        }
+        buffer.delete(Position::new(0, 3), Position::new(2, 0));
+        let step = buffer.undo_stack.last().unwrap();
+        assert_eq!(step.edits.len(), 2);
+        assert_eq!(step.edits[0].removed, ["two"]);
+        assert_eq!(step.edits[0].inserted, ["abctwo"]);
+        assert_eq!(buffer.marks().get('a'), Some(Position::new(1, 1)));
+
+        assert_eq!(buffer.undo(Position::default()), Some(Position::new(1, 0)));
+        assert_eq!(buffer.text(), "one\ntwo\nthree\nfour");
+        assert_eq!(buffer.marks().get('a'), Some(Position::new(3, 1)));
+        buffer.redo(Position::new(1, 0));
+        assert_eq!(buffer.text(), "onethree\nfour");
+        assert_eq!(buffer.marks().get('a'), Some(Position::new(1, 1)));
+
+        for _ in 0..MAX_UNDO_STEPS + 5 {
+            buffer.checkpoint(Position::default());
+            buffer.insert(Position::default(), "x");
+        }
+        assert_eq!(buffer.undo_stack.len(), MAX_UNDO_STEPS);
+    }
+
+    #[test]
+    fn changed_lines_add_up_across_edits() {
+        let mut buffer = Buffer::from_text(PathBuf::from("a.txt"), "a\nb\nc\nd\ne");
+        buffer.insert(Position::new(3, 0), "x\ny");
+        buffer.delete(Position::new(0, 1), Position::new(1, 0));
+        assert_eq!(buffer.take_changed_lines(), Some(ChangedLines { first: 0, removed: 4, added: 4 }));
+        assert_eq!(buffer.text(), "ab\nc\nx\nyd\ne");
+        assert_eq!(buffer.take_changed_lines(), None);
+    }
+
+    #[test]
+    fn empty_file_has_one_line() {
+        let mut buffer = Buffer::from_text(PathBuf::from("a.txt"), "");
+        assert_eq!(buffer.line_count(), 1);
+        buffer.insert(Position::default(), "x");
+        assert_eq!(buffer.to_file_text(), "x");
+    }
+}
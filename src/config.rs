@@ -0,0 +1,488 @@
+use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::SystemTime;
+use crate::keymap::{Command, KeyChord};
+use crate::language::Language;
+
+const LOCAL_CONFIG_NAME: &str = ".fumaedit";
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum WrapMode {
+    Soft,
+    None,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Theme {
+    Default,
+    Dark,
+    Light,
+}
+
+/// Which set of key bindings drives the editor.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum KeymapPreset {
+    Default,
+    Vi,
+    Emacs,
+}
+
+/// Settings from a `[languages.<name>]` section; unset ones keep the language's defaults.
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct LanguageSettings {
+    pub pairs: Option<Vec<(char, char)>>,
+    /// Characters besides letters and digits that are part of words.
+    pub word_chars: Option<String>,
+}
+
+#[derive(Clone, PartialEq, Debug)]
+pub struct Settings {
+    pub tab_width: usize,
+    pub wrap: WrapMode,
+    pub line_numbers: bool,
+    pub theme: Theme,
+    pub scroll_margin: usize,
+    /// Column paragraphs are reflowed to.
+    pub text_width: usize,
+    pub keymap: KeymapPreset,
+    /// Type closing brackets and quotes along with the opening ones.
+    pub auto_pairs: bool,
+    /// `[languages.<name>]` sections by language name.
+    pub languages: Vec<(String, LanguageSettings)>,
+    /// Overrides from the `[keys]` section, applied on top of the default keymap in file order.
+    /// A `None` command removes the binding.
+    pub key_bindings: Vec<(Vec<KeyChord>, Option<Command>)>,
+}
+
+impl Default for Settings {
+    fn default() -> Self {
+        Self {
+            tab_width: 4,
+            wrap: WrapMode::Soft,
+            line_numbers: false,
+            theme: Theme::Default,
+            scroll_margin: 0,
+            text_width: 80,
+            keymap: KeymapPreset::Default,
+            auto_pairs: true,
+            languages: Vec::new(),
+            key_bindings: Vec::new(),
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct ConfigError {
+    pub file: PathBuf,
+    pub line: usize,
+    pub key: Option<String>,
+    pub message: String,
+}
+
+impl fmt::Display for ConfigError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match &self.key {
+            Some(key) => write!(f, "{}:{}: `{}`: {}", self.file.display(), self.line, key, self.message),
+            None => write!(f, "{}:{}: {}", self.file.display(), self.line, self.message),
+        }
+    }
+}
+
+struct ConfigSource {
+    path: PathBuf,
+    modified: Option<SystemTime>,
+}
+
+/// Settings merged from the user config and the closest project-local `.fumaedit`, in that order.
+#[derive(Default)]
+pub struct Config {
+    pub settings: Settings,
+    pub errors: Vec<ConfigError>,
+    file_path: PathBuf,
+    sources: Vec<ConfigSource>,
+}
+
+impl Config {
+    pub fn load(file_path: &Path) -> Self {
+        let mut config = Config {
+            file_path: file_path.to_path_buf(),
+            ..Config::default()
+        };
+
+        for path in config_paths(file_path) {
+            let Ok(text) = fs::read_to_string(&path) else {
+                continue;
+            };
+            let modified = fs::metadata(&path).and_then(|m| m.modified()).ok();
+            config.errors.extend(parse_into(&mut config.settings, &text, &path));
+            config.sources.push(ConfigSource { path, modified });
+        }
+        config
+    }
+
+    pub fn reload(&self) -> Self {
+        Config::load(&self.file_path)
+    }
+
+    /// True when a config file was edited, created or deleted since it was loaded.
+    pub fn is_stale(&self) -> bool {
+        let current: Vec<PathBuf> = config_paths(&self.file_path).into_iter().filter(|p| p.is_file()).collect();
+        if current.len() != self.sources.len() {
+            return true;
+        }
+        current.iter().zip(&self.sources).any(|(path, source)| {
+            *path != source.path || fs::metadata(path).and_then(|m| m.modified()).ok() != source.modified
+        })
+    }
+}
+
+fn config_paths(file_path: &Path) -> Vec<PathBuf> {
+    let mut paths = Vec::new();
+    if let Some(config_dir) = dirs::config_dir() {
+        paths.push(config_dir.join("fumaedit").join("config.toml"));
+    }
+    if let Some(local) = file_path.parent()
+        .and_then(|dir| dir.ancestors().map(|d| d.join(LOCAL_CONFIG_NAME)).find(|p| p.is_file())) {
+        paths.push(local);
+    }
+    paths
+}
+
+#[derive(Debug)]
+enum Value {
+    Str(String),
+    Int(i64),
+    Bool(bool),
+}
+
+impl Value {
+    fn kind(&self) -> &'static str {
+        match self {
+            Value::Str(_) => "a string",
+            Value::Int(_) => "an integer",
+            Value::Bool(_) => "a boolean",
+        }
+    }
+}
+
+#[derive(Clone, PartialEq)]
+enum Section {
+    Settings,
+    Keys,
+    /// `[languages.<name>]`
+    Language(String),
+    /// After a header that was reported as unknown: its keys are skipped until the next header.
+    Ignored,
+}
+
+/// Parses the subset of TOML the editor understands (`key = value`, the `[keys]` and `[languages.<name>]`
+/// sections, comments, strings, integers and booleans) and applies every valid entry. Bad entries are reported and leave
+/// the setting as it was.
+fn parse_into(settings: &mut Settings, text: &str, path: &Path) -> Vec<ConfigError> {
+    let mut errors = Vec::new();
+    let mut section = Section::Settings;
+
+    for (index, raw_line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let error = |key: Option<&str>, message: String| ConfigError {
+            file: path.to_path_buf(),
+            line: line_number,
+            key: key.map(str::to_string),
+            message,
+        };
+
+        let line = strip_comment(raw_line).trim();
+        if line.is_empty() {
+            continue;
+        }
+        if line.starts_with('[') {
+            let name = line.strip_prefix("[languages.").and_then(|rest| rest.strip_suffix(']'));
+            match (line, name) {
+                ("[keys]", _) => section = Section::Keys,
+                (_, Some(name)) if Language::by_name(name).is_some() => section = Section::Language(name.to_string()),
+                (_, Some(name)) => {
+                    errors.push(error(None, format!("unknown language \"{}\"", name)));
+                    section = Section::Ignored;
+                }
+                _ => {
+                    errors.push(error(None, format!("unknown section {}", line)));
+                    section = Section::Ignored;
+                }
+            }
+            continue;
+        }
+        let Some((key, value)) = split_key_value(line) else {
+            errors.push(error(None, "expected `key = value`".to_string()));
+            continue;
+        };
+        let value = match parse_value(value) {
+            Ok(value) => value,
+            Err(message) => {
+                errors.push(error(Some(key), message));
+                continue;
+            }
+        };
+        let applied = match &section {
+            Section::Ignored => continue,
+            Section::Settings => apply_setting(settings, key, value),
+            Section::Keys => apply_key_binding(settings, key, value),
+            Section::Language(name) => apply_language_setting(settings, name, key, value),
+        };
+        if let Err(message) = applied {
+            errors.push(error(Some(key), message));
+        }
+    }
+    errors
+}
+
+fn strip_comment(line: &str) -> &str {
+    let mut in_string = false;
+    let mut escaped = false;
+    for (i, c) in line.char_indices() {
+        match c {
+            _ if escaped => escaped = false,
+            '\\' if in_string => escaped = true,
+            '"' => in_string = !in_string,
+            '#' if !in_string => return &line[..i],
+            _ => {}
+        }
+    }
+    line
+}
+
+/// Splits `key = value`, allowing quoted keys that contain `=` such as `"ctrl+=" = "quit"`.
+fn split_key_value(line: &str) -> Option<(&str, &str)> {
+    let (key, rest) = match line.strip_prefix('"') {
+        Some(quoted) => {
+            let end = quoted.find('"')?;
+            (&quoted[..end], &quoted[end + 1..])
+        }
+        None => {
+            let equals = line.find('=')?;
+            (line[..equals].trim(), &line[equals..])
+        }
+    };
+    let value = rest.trim_start().strip_prefix('=')?;
+    Some((key, value.trim()))
+}
+
+fn parse_value(raw: &str) -> Result<Value, String> {
+    if let Some(inner) = raw.strip_prefix('"') {
+        return unescape(inner).map(Value::Str).ok_or_else(|| format!("unterminated string {}", raw));
+    }
+    match raw {
+        "true" => return Ok(Value::Bool(true)),
+        "false" => return Ok(Value::Bool(false)),
+        "" => return Err("missing value".to_string()),
+        _ => {}
+    }
+    raw.parse::<i64>()
+        .map(Value::Int)
+        .map_err(|_| format!("cannot read value {} (strings need double quotes)", raw))
+}
+
+/// Reads the rest of a basic string after its opening quote, handling `\"` and `\\`.
+/// None unless the string ends with the line.
+fn unescape(inner: &str) -> Option<String> {
+    let mut text = String::new();
+    let mut chars = inner.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\\' => text.push(chars.next()?),
+            '"' => return chars.next().is_none().then_some(text),
+            c => text.push(c),
+        }
+    }
+    None
+}
+
+fn apply_setting(settings: &mut Settings, key: &str, value: Value) -> Result<(), String> {
+    match (key, value) {
+        ("tab_width", Value::Int(n)) if (1..=16).contains(&n) => settings.tab_width = n as usize,
+        ("tab_width", Value::Int(n)) => return Err(format!("must be between 1 and 16, got {}", n)),
+        ("scroll_margin", Value::Int(n)) if (0..=100).contains(&n) => settings.scroll_margin = n as usize,
+        ("scroll_margin", Value::Int(n)) => return Err(format!("must be between 0 and 100, got {}", n)),
+        ("text_width", Value::Int(n)) if (10..=1000).contains(&n) => settings.text_width = n as usize,
+        ("text_width", Value::Int(n)) => return Err(format!("must be between 10 and 1000, got {}", n)),
+        ("line_numbers", Value::Bool(b)) => settings.line_numbers = b,
+        ("auto_pairs", Value::Bool(b)) => settings.auto_pairs = b,
+        ("wrap", Value::Str(s)) => settings.wrap = match s.as_str() {
+            "soft" => WrapMode::Soft,
+            "none" => WrapMode::None,
+            _ => return Err(format!("expected \"soft\" or \"none\", got \"{}\"", s)),
+        },
+        ("theme", Value::Str(s)) => settings.theme = match s.as_str() {
+            "default" => Theme::Default,
+            "dark" => Theme::Dark,
+            "light" => Theme::Light,
+            _ => return Err(format!("expected \"default\", \"dark\" or \"light\", got \"{}\"", s)),
+        },
+        ("keymap", Value::Str(s)) => settings.keymap = match s.as_str() {
+            "default" => KeymapPreset::Default,
+            "vi" => KeymapPreset::Vi,
+            "emacs" => KeymapPreset::Emacs,
+            _ => return Err(format!("expected \"default\", \"vi\" or \"emacs\", got \"{}\"", s)),
+        },
+        ("tab_width" | "scroll_margin" | "text_width", value) => return Err(format!("expected an integer, got {}", value.kind())),
+        ("line_numbers" | "auto_pairs", value) => return Err(format!("expected a boolean, got {}", value.kind())),
+        ("wrap" | "theme" | "keymap", value) => return Err(format!("expected a string, got {}", value.kind())),
+        _ => return Err("unknown setting".to_string()),
+    }
+    Ok(())
+}
+
+impl Settings {
+    pub fn language(&self, name: &str) -> Option<&LanguageSettings> {
+        self.languages.iter().find(|(language, _)| language == name).map(|(_, settings)| settings)
+    }
+}
+
+fn apply_language_setting(settings: &mut Settings, language: &str, key: &str, value: Value) -> Result<(), String> {
+    let index = match settings.languages.iter().position(|(name, _)| name == language) {
+        Some(index) => index,
+        None => {
+            settings.languages.push((language.to_string(), LanguageSettings::default()));
+            settings.languages.len() - 1
+        }
+    };
+    let language = &mut settings.languages[index].1;
+    match (key, value) {
+        ("pairs", Value::Str(s)) => {
+            let pairs = s.split_whitespace()
+                .map(|pair| match pair.chars().collect::<Vec<_>>()[..] {
+                    [open, close] => Ok((open, close)),
+                    _ => Err(format!("pairs are two characters each, got \"{}\"", pair)),
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            language.pairs = Some(pairs);
+        }
+        ("word_chars", Value::Str(s)) => match s.chars().find(|c| c.is_whitespace()) {
+            Some(_) => return Err("word characters cannot include whitespace".to_string()),
+            None => language.word_chars = Some(s),
+        },
+        ("pairs" | "word_chars", value) => return Err(format!("expected a string, got {}", value.kind())),
+        _ => return Err("unknown language setting".to_string()),
+    }
+    Ok(())
+}
+
+fn apply_key_binding(settings: &mut Settings, keys: &str, value: Value) -> Result<(), String> {
+    let sequence = KeyChord::parse_sequence(keys)?;
+    let command = match value {
+        Value::Str(name) if name == "none" => None,
+        Value::Str(name) => match Command::from_name(&name) {
+            Some(command) => Some(command),
+            None => return Err(format!("unknown command \"{}\"", name)),
+        },
+        value => return Err(format!("expected a command name, got {}", value.kind())),
+    };
+    settings.key_bindings.push((sequence, command));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(text: &str) -> (Settings, Vec<ConfigError>) {
+        let mut settings = Settings::default();
+        let errors = parse_into(&mut settings, text, Path::new("config.toml"));
+        (settings, errors)
+    }
+
+    #[test]
+    fn reads_every_setting() {
+        let (settings, errors) = parse(
+            "# editor settings\ntab_width = 2\nwrap = \"none\"\nline_numbers = true # gutter\ntheme = \"dark\"\nscroll_margin = 3\ntext_width = 72\nkeymap = \"vi\"\nauto_pairs = false\n",
+        );
+        assert!(errors.is_empty());
+        assert_eq!(settings, Settings {
+            tab_width: 2,
+            wrap: WrapMode::None,
+            line_numbers: true,
+            theme: Theme::Dark,
+            scroll_margin: 3,
+            text_width: 72,
+            keymap: KeymapPreset::Vi,
+            auto_pairs: false,
+            languages: Vec::new(),
+            key_bindings: Vec::new(),
+        });
+    }
+
+    #[test]
+    fn reads_key_bindings() {
+        let (settings, errors) = parse("[keys]\n\"ctrl+k ctrl+c\" = \"quit\"\n\"ctrl+=\" = \"none\"\nf9 = \"describe-key\"\n");
+        assert!(errors.is_empty());
+        assert_eq!(settings.key_bindings, vec![
+            (KeyChord::parse_sequence("ctrl+k ctrl+c").unwrap(), Some(Command::Quit)),
+            (KeyChord::parse_sequence("ctrl+=").unwrap(), None),
+            (KeyChord::parse_sequence("f9").unwrap(), Some(Command::DescribeKey)),
+        ]);
+    }
+
+    #[test]
+    fn bad_key_bindings_report_the_key() {
+        let (_, errors) = parse("[keys]\n\"ctrl+x\" = \"explode\"\n\"super+x\" = \"quit\"\n[colors]\n");
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec![
+            "config.toml:2: `ctrl+x`: unknown command \"explode\"",
+            "config.toml:3: `super+x`: unknown modifier `super` in `super+x`",
+            "config.toml:4: unknown section [colors]",
+        ]);
+    }
+
+    #[test]
+    fn bad_values_report_file_line_and_key() {
+        let (settings, errors) = parse("tab_width = 4\n\ntab_width = 0\nwrap = soft\nfoo = 1\n");
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec![
+            "config.toml:3: `tab_width`: must be between 1 and 16, got 0",
+            "config.toml:4: `wrap`: cannot read value soft (strings need double quotes)",
+            "config.toml:5: `foo`: unknown setting",
+        ]);
+        assert_eq!(settings.tab_width, 4);
+        assert_eq!(settings.wrap, WrapMode::Soft);
+    }
+
+    #[test]
+    fn type_mismatch_names_the_expected_type() {
+        let (_, errors) = parse("line_numbers = \"yes\"\n");
+        assert_eq!(errors[0].to_string(), "config.toml:1: `line_numbers`: expected a boolean, got a string");
+    }
+
+    #[test]
+    fn reads_language_sections_with_escaped_quotes() {
+        let (settings, errors) = parse("[languages.rust]\npairs = \"() \\\"\\\"\" # no quotes\n[languages.cobol]\n");
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec!["config.toml:3: unknown language \"cobol\""]);
+        assert_eq!(settings.language("rust").and_then(|rust| rust.pairs.clone()), Some(vec![('(', ')'), ('"', '"')]));
+
+        let (settings, errors) = parse("[languages.css]\nword_chars = \"-_\"\n");
+        assert!(errors.is_empty());
+        assert_eq!(settings.language("css").and_then(|css| css.word_chars.as_deref()), Some("-_"));
+
+        let (_, errors) = parse("[languages.python]\npairs = \"(\"\n");
+        assert_eq!(errors[0].to_string(), "config.toml:2: `pairs`: pairs are two characters each, got \"(\"");
+    }
+
+    #[test]
+    fn keys_under_unknown_sections_are_skipped() {
+        let (settings, errors) = parse("[keys]\n[colors]\nf9 = \"quit\"\n[languages.cobol]\npairs = \"()\"\n[languages.rust]\nword_chars = \"_\"\n");
+        let messages: Vec<String> = errors.iter().map(|e| e.to_string()).collect();
+        assert_eq!(messages, vec!["config.toml:2: unknown section [colors]", "config.toml:4: unknown language \"cobol\""]);
+        assert!(settings.key_bindings.is_empty());
+        assert!(settings.language("cobol").is_none());
+        assert_eq!(settings.language("rust").and_then(|rust| rust.word_chars.as_deref()), Some("_"));
+    }
+
+    #[test]
+    fn later_files_override_earlier_ones() {
+        let mut settings = Settings::default();
+        parse_into(&mut settings, "tab_width = 8\ntheme = \"light\"", Path::new("global"));
+        parse_into(&mut settings, "tab_width = 2", Path::new(".fumaedit"));
+        assert_eq!(settings.tab_width, 2);
+        assert_eq!(settings.theme, Theme::Light);
+    }
+}
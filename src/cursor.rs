@@ -1,10 +1,8 @@
-﻿use std::io;
-use crossterm::cursor::{MoveTo, Show};
-use crossterm::execute;
-use std::io::{stdout, Write};
-use crossterm::style::Print;
-use crate::utils::debug::print_bool;
-use crate::utils::tokenizer::{TokenWithPos};
+﻿use std::cell::Cell;
+use std::ops::Range;
+use crate::language::MATCHED_BRACKETS;
+use crate::utils::content_wrapper::WrapResult;
+use crate::utils::tokenizer::{TokenCache, TokenType, TokenWithPos};
 use crate::utils::direction::Direction;
 
 
@@ -12,63 +10,82 @@ pub struct CursorPos {
     pub(crate) x: usize,
     pub(crate) y: usize,
     pub(crate) last_x: usize,
-    max_y: usize,
-    line_lengths: Vec<usize>, 
     pub(crate) vertical_offset: usize,
-    wrap_ids: Vec<usize>,
-    tokenized_words: Vec<TokenWithPos>,
+    visible_rows: usize,
+    scroll_margin: usize,
+    /// The text as wrapped on screen, which rows and columns refer to.
+    wrap: WrapResult,
+    tokens: TokenCache,
     last_token: TokenWithPos,
     last_fast_right: bool,
+    brackets: &'static [(char, char)],
+    /// Bracket match last found for drawing, keyed by cursor row, column and scroll offset.
+    bracket_match: Cell<Option<(BracketKey, Option<BracketMatch>)>>,
 }
 
+type BracketMatch = ((usize, usize), (usize, usize));
+type BracketKey = (usize, usize, usize);
+
+/// Rows beyond the visible ones searched for the partner of the bracket at the cursor when drawing.
+const BRACKET_SCAN_MARGIN: usize = 500;
+
 impl CursorPos {
-    pub fn new(contents: &str, wrap_ids: Vec<usize>, tokenized_words: Vec<TokenWithPos>) -> Self {
-        let lines: Vec<&str> = contents.lines().collect();
-        let line_lengths = lines.iter().map(|l| l.chars().count()).collect();
-        let max_y = lines.len().saturating_sub(1);
-        let last_token = tokenized_words[0].clone();
+    pub fn new(wrap: WrapResult, tokens: TokenCache, visible_rows: usize) -> Self {
+        let last_token = tokens.tokens_after(&wrap, 0).next().unwrap_or_default();
 
         Self {
             x: 0,
             y: 0,
             last_x: 0,
-            max_y,
-            line_lengths,
             vertical_offset: 0,
-            wrap_ids,
-            tokenized_words,
+            visible_rows,
+            scroll_margin: 0,
+            wrap,
+            tokens,
             last_token,
             last_fast_right: false,
+            brackets: MATCHED_BRACKETS,
+            bracket_match: Cell::new(None),
         }
     }
-    pub fn move_up(&mut self) -> bool {
+    /// The text as wrapped on screen.
+    pub fn wrap_result(&self) -> &WrapResult {
+        &self.wrap
+    }
+
+    /// Takes in an edit that replaced `removed` logical lines from `first` with `lines`, each
+    /// already wrapped by `wrap_line`. Only the new lines are tokenized. The cursor keeps its row
+    /// and column, to be placed again by the caller.
+    pub fn replace_lines(&mut self, first: usize, removed: usize, lines: Vec<Vec<String>>) {
+        let added = lines.len();
+        self.wrap.replace_lines(first, removed, lines);
+        self.tokens.replace_lines(&self.wrap, first, removed, added);
+        self.last_token = TokenWithPos::default();
+        self.bracket_match.set(None);
+    }
+
+    pub fn move_up(&mut self) {
         if self.y > 0 {
             self.y -= 1;
             self.clamp_x_to_current_line();
-            return self.ensure_visible();
-
         }
-        false
     }
 
-    pub fn move_down(&mut self) -> bool {
-        if self.y < self.max_y {
+    pub fn move_down(&mut self) {
+        if self.y < self.max_y() {
             self.y += 1;
             self.clamp_x_to_current_line();
-            return self.ensure_visible()
         }
-        false
     }
 
     pub fn move_right(&mut self) {
         let max_x = self.get_current_line_length();
 
-        if self.x + 1 <= max_x {
+        if self.x < max_x {
             if self.x + 1 == max_x && self.is_same_logical_line(self.y + 1) {
                 self.y += 1;
                 self.x = 0;
                 self.last_x = self.x;
-                return;
             } else {
                 self.x += 1;
                 self.last_x = self.x;
@@ -91,29 +108,37 @@ impl CursorPos {
         }
     }
 
+    /// Last wrapped row.
+    fn max_y(&self) -> usize {
+        self.wrap.row_count().saturating_sub(1)
+    }
+
     fn is_same_logical_line(&self, other_y: usize) -> bool {
-        self.wrap_ids.get(other_y) == self.wrap_ids.get(self.y)
+        self.wrap.line_of_row(other_y).is_some() && self.wrap.line_of_row(other_y) == self.wrap.line_of_row(self.y)
     }
 
     fn wrap_id_for_line(&self, line: usize) -> Option<usize> {
-        self.wrap_ids.get(line).copied()
+        self.wrap.line_of_row(line)
     }
 
     fn get_line_length(&self, line: usize) -> usize {
-        self.line_lengths.get(line).copied().unwrap_or(0)
+        self.wrap.row(line).map_or(0, |row| row.chars().count())
     }
 
     fn get_current_line_length(&self) -> usize {
-        self.line_lengths.get(self.y).copied().unwrap_or(0)
+        self.get_line_length(self.y)
+    }
+
+    /// Whether a logical line holds nothing but whitespace. Lines past the end count as blank.
+    fn is_blank_line(&self, line: usize) -> bool {
+        self.wrap.line_rows(line).iter().all(|row| row.trim().is_empty())
     }
     
     pub fn move_home(&mut self) {
         if let Some(current_wrap_id) = self.wrap_id_for_line(self.y) {
-            if let Some(first_line) = self.wrap_ids.iter().position(|&id| id == current_wrap_id) {
-                self.y = first_line;
-                self.x = 0;
-                self.last_x = self.x;
-            }
+            self.y = self.wrap.first_row(current_wrap_id);
+            self.x = 0;
+            self.last_x = self.x;
         } else {
             self.x = 0;
             self.last_x = self.x;
@@ -123,11 +148,10 @@ impl CursorPos {
 
     pub fn move_end(&mut self) {
         if let Some(current_wrap_id) = self.wrap_id_for_line(self.y) {
-            if let Some(last_line) = self.wrap_ids.iter().rposition(|&id| id == current_wrap_id) {
-                self.y = last_line;
-                self.x = self.get_line_length(last_line);
-                self.last_x = self.x;
-            }
+            let last_line = self.wrap.first_row(current_wrap_id + 1) - 1;
+            self.y = last_line;
+            self.x = self.get_line_length(last_line);
+            self.last_x = self.x;
         } else {
             self.x = self.get_current_line_length();
             self.last_x = self.x;
@@ -135,39 +159,196 @@ impl CursorPos {
     }
 
 
-    pub fn refresh(&self) -> io::Result<()> {
-        let screen_y = self.y.saturating_sub(self.vertical_offset) as u16;
+    /// Moves past the current paragraph of logical lines to the blank line after it (or before it when
+    /// going left), or to the end (start) of the text. Like `move_home`, rows sharing a `wrap_id`
+    /// count as one line.
+    pub fn move_paragraph(&mut self, direction: Direction) {
+        let Some(line) = self.wrap_id_for_line(self.y) else {
+            return;
+        };
+        let blank = |line: usize| self.is_blank_line(line);
+        let last = self.wrap.line_count().saturating_sub(1);
+        let target = match direction {
+            Direction::Right => {
+                let mut l = line;
+                while l <= last && blank(l) {
+                    l += 1;
+                }
+                while l <= last && !blank(l) {
+                    l += 1;
+                }
+                (l <= last).then_some(l)
+            }
+            Direction::Left => {
+                let mut l = line;
+                while l > 0 && blank(l) {
+                    l -= 1;
+                }
+                while l > 0 && !blank(l) {
+                    l -= 1;
+                }
+                Some(l)
+            }
+        };
+        match target {
+            Some(line) => self.jump_to((self.wrap.first_row(line), 0)),
+            None => self.move_to_end(),
+        }
+    }
 
-        execute!(
-            stdout(),
-            MoveTo(self.x as u16, screen_y),
-            Show
-        )?;
-        stdout().flush()?;
-        Ok(())
+    /// First and last logical line of the paragraph the cursor is in, or None on a blank line.
+    pub fn paragraph_lines(&self) -> Option<(usize, usize)> {
+        let line = self.wrap_id_for_line(self.y)?;
+        let blank = |line: usize| self.is_blank_line(line);
+        if blank(line) {
+            return None;
+        }
+        let line_count = self.wrap.line_count();
+        let first = (0..line).rev().find(|&l| blank(l)).map_or(0, |l| l + 1);
+        let last = (line + 1..line_count).find(|&l| blank(l)).map_or(line_count - 1, |l| l - 1);
+        Some((first, last))
     }
 
+    /// Logical line and character offset inside it, independent of how the text is wrapped.
+    pub fn logical_position(&self) -> (usize, usize) {
+        self.logical_position_of(self.y, self.x)
+    }
 
-    fn ensure_visible(&mut self) -> bool {
-        let (_, rows) = crossterm::terminal::size().unwrap();
-        let visible_rows = rows as usize;
-        let mut did_scroll = false;
+    /// Logical position of a wrapped row and column.
+    pub fn logical_position_of(&self, y: usize, x: usize) -> (usize, usize) {
+        let Some(line) = self.wrap_id_for_line(y) else {
+            return (0, 0);
+        };
+        let first_row = self.wrap.first_row(line);
+        let col = (first_row..y).map(|row| self.get_line_length(row)).sum::<usize>() + x;
+        (line, col)
+    }
+
+    /// Places the cursor on a logical position, clamping it to the text that exists.
+    pub fn set_logical_position(&mut self, line: usize, col: usize) {
+        let Some(last_line) = self.wrap.line_count().checked_sub(1) else {
+            return;
+        };
+        let rows = self.wrap.rows_of(line.min(last_line), 1);
+        let (first_row, last_row) = (rows.start, rows.end - 1);
+
+        let mut remaining = col;
+        for row in first_row..=last_row {
+            let length = self.get_line_length(row);
+            if remaining < length || row == last_row {
+                self.y = row;
+                self.x = remaining.min(length);
+                break;
+            }
+            remaining -= length;
+        }
+        self.last_x = self.x;
+    }
+
+    pub fn set_brackets(&mut self, brackets: &'static [(char, char)]) {
+        self.brackets = brackets;
+        self.bracket_match.set(None);
+    }
+
+    pub fn set_scroll_margin(&mut self, margin: usize) {
+        self.scroll_margin = margin;
+    }
+
+    /// Scrolls so the cursor stays on screen, keeping `scroll_margin` rows around it when the file allows.
+    pub fn ensure_visible(&mut self) {
+        let visible_rows = self.visible_rows.max(1);
+        let margin = self.margin();
 
         // Upward scroll
-        if self.y < self.vertical_offset {
-            self.vertical_offset = self.y;
-            did_scroll = true;
+        if self.y < self.vertical_offset + margin {
+            self.vertical_offset = self.y.saturating_sub(margin);
         }
         // Downward scroll
-        else if self.y >= self.vertical_offset + visible_rows {
-            self.vertical_offset = self.y - visible_rows + 1;
-            did_scroll = true;
+        else if self.y + margin >= self.vertical_offset + visible_rows {
+            self.vertical_offset = (self.y + margin + 1 - visible_rows).min(self.max_offset());
         }
-        did_scroll
-        
     }
 
-   
+    /// Moves to a wrapped row and column, as a mouse click does, clamping to the text.
+    pub fn place(&mut self, y: usize, x: usize) {
+        let y = y.min(self.max_y());
+        let mut max_x = self.get_line_length(y);
+        // A row that continues on the next one cannot hold the cursor after its last character
+        if self.wrap.line_of_row(y + 1).is_some() && self.wrap.line_of_row(y + 1) == self.wrap.line_of_row(y) {
+            max_x = max_x.saturating_sub(1);
+        }
+        self.jump_to((y, x.min(max_x)));
+    }
+
+    /// Scrolls the view by `rows` without moving the cursor, which may leave the screen.
+    pub fn scroll_by(&mut self, rows: isize) {
+        self.vertical_offset = self.vertical_offset.saturating_add_signed(rows).min(self.max_offset());
+    }
+
+    /// Moves a screenful down, keeping the cursor on the same screen row when the file allows.
+    pub fn page_down(&mut self) {
+        let screen_row = self.y.saturating_sub(self.vertical_offset);
+        self.y = (self.y + self.visible_rows.max(1)).min(self.max_y());
+        self.vertical_offset = self.y.saturating_sub(screen_row).min(self.max_offset());
+        self.clamp_x_to_current_line();
+    }
+
+    /// Moves a screenful up, keeping the cursor on the same screen row when the file allows.
+    pub fn page_up(&mut self) {
+        let screen_row = self.y.saturating_sub(self.vertical_offset);
+        self.y = self.y.saturating_sub(self.visible_rows.max(1));
+        self.vertical_offset = self.y.saturating_sub(screen_row);
+        self.clamp_x_to_current_line();
+    }
+
+    pub fn move_to_start(&mut self) {
+        self.jump_to((0, 0));
+    }
+
+    pub fn move_to_end(&mut self) {
+        self.jump_to((self.max_y(), self.get_line_length(self.max_y())));
+    }
+
+    /// Scrolls the view one row up. The cursor only moves if it would leave the screen or the scroll margin.
+    pub fn scroll_up(&mut self) {
+        self.vertical_offset = self.vertical_offset.saturating_sub(1);
+        let last_allowed = self.vertical_offset + self.visible_rows.max(1) - 1 - self.margin();
+        if self.y > last_allowed {
+            self.y = last_allowed;
+            self.clamp_x_to_current_line();
+        }
+    }
+
+    /// Scrolls the view one row down. The cursor only moves if it would leave the screen or the scroll margin.
+    pub fn scroll_down(&mut self) {
+        self.vertical_offset = (self.vertical_offset + 1).min(self.max_offset());
+        let first_allowed = (self.vertical_offset + self.margin()).min(self.max_y());
+        if self.y < first_allowed {
+            self.y = first_allowed;
+            self.clamp_x_to_current_line();
+        }
+    }
+
+    /// Scroll margin, limited so it fits on screen.
+    fn margin(&self) -> usize {
+        self.scroll_margin.min((self.visible_rows.max(1) - 1) / 2)
+    }
+
+    /// Largest `vertical_offset` that still fills the screen.
+    fn max_offset(&self) -> usize {
+        self.wrap.row_count().saturating_sub(self.visible_rows.max(1))
+    }
+
+    /// Scrolls so the cursor is on `screen_row`, or as close to it as the screen and the file allow.
+    pub fn scroll_to_screen_row(&mut self, screen_row: usize) {
+        let screen_row = screen_row.min(self.visible_rows.max(1) - 1);
+        self.vertical_offset = self.y.saturating_sub(screen_row).min(self.max_offset());
+    }
+
+    /// Scrolls so the cursor row is in the middle of the screen, as far as the file allows.
+    pub fn center_on_cursor(&mut self) {
+        self.vertical_offset = self.y.saturating_sub(self.visible_rows.max(1) / 2).min(self.max_offset());
+    }
 
     fn clamp_x_to_current_line(&mut self) {
         let max_x = self.get_current_line_length();
@@ -179,7 +360,8 @@ impl CursorPos {
     }
     
     pub fn get_token_on_cursor(& self) -> Option<TokenWithPos>{
-        let token = self.tokenized_words.iter()
+        let line = self.wrap_id_for_line(self.y)?;
+        let token = self.tokens.line_tokens(&self.wrap, line)
             .find(|t| {
                 // Single-line tokens
                 (t.row_start == Some(self.y) && t.row_end == Some(self.y) &&
@@ -190,19 +372,23 @@ impl CursorPos {
                 (t.row_start < Some(self.y) && t.row_end == Some(self.y)) && t.col_end >= Some(self.x)
             });
         if token.is_some(){
-            return token.cloned();
+            return token;
         }
         None
     }
     
     fn get_token(&mut self, direction: Direction) -> Option<TokenWithPos> {
         let mut buffer: isize = 0;
-        let current_wrap_id = self.wrap_ids.get(self.y).copied();
+        // Tokens never cross a logical line, so only the cursor's line is searched
+        let tokens: Vec<TokenWithPos> = match self.wrap_id_for_line(self.y) {
+            Some(line) => self.tokens.line_tokens(&self.wrap, line).collect(),
+            None => Vec::new(),
+        };
 
         loop {
             let col_search = self.x.saturating_add_signed(buffer);
             
-            if let Some(token) = self.tokenized_words.iter().find(|t| {
+            if let Some(token) = tokens.iter().find(|t| {
                 // Single-line tokens
                 (t.row_start <= Some(self.y) && t.row_end >= Some(self.y) &&
                 t.col_start <= Some(col_search) && t.col_end >= Some(col_search)) ||
@@ -220,23 +406,19 @@ impl CursorPos {
 
             //If it doesn't return a token, we check the direction of the move,
             //and if the next/previous row is part of the same logical line to force (or not) a jump
-            if next_search_col >= self.line_lengths[self.y] as isize || next_search_col < 0 {
+            if next_search_col >= self.get_current_line_length() as isize || next_search_col < 0 {
                 match direction {
-                    Direction::Right if self.y < self.wrap_ids.len().saturating_sub(1) => {
-                        if current_wrap_id == self.wrap_ids.get(self.y + 1).copied() {
-                            self.y += 1;
-                            self.x = 0;
-                            buffer = 0;
-                            continue;
-                        }
+                    Direction::Right if self.is_same_logical_line(self.y + 1) => {
+                        self.y += 1;
+                        self.x = 0;
+                        buffer = 0;
+                        continue;
                     },
-                    Direction::Left if self.y > 0 => {
-                        if current_wrap_id == self.wrap_ids.get(self.y - 1).copied() {
-                            self.y -= 1;
-                            self.x = self.line_lengths[self.y];
-                            buffer = 0;
-                            continue;
-                        }
+                    Direction::Left if self.y > 0 && self.is_same_logical_line(self.y - 1) => {
+                        self.y -= 1;
+                        self.x = self.get_current_line_length();
+                        buffer = 0;
+                        continue;
                     },
                     _ => ()
                 }
@@ -246,6 +428,112 @@ impl CursorPos {
         None
     }
     
+    /// Wrapped (row, col) of the first and last character of every token from the cursor's line
+    /// on, in text order, or back to the start of the text, last first.
+    fn token_bounds(&self, direction: Direction) -> impl Iterator<Item = ((usize, usize), (usize, usize))> + '_ {
+        let line = self.wrap_id_for_line(self.y).unwrap_or(0);
+        let tokens: Box<dyn Iterator<Item = TokenWithPos>> = match direction {
+            Direction::Right => Box::new(self.tokens.tokens_after(&self.wrap, line)),
+            Direction::Left => Box::new(self.tokens.tokens_before(&self.wrap, line)),
+        };
+        tokens.filter_map(|t| Some(((t.row_start?, t.col_start?), (t.row_end?, t.col_end?))))
+    }
+
+    fn jump_to(&mut self, (y, x): (usize, usize)) {
+        self.y = y;
+        self.x = x;
+        self.last_x = x;
+    }
+
+    /// Moves to the first character of the next token (vi `w`), or to the end of the text.
+    pub fn move_word_start_forward(&mut self) {
+        let here = (self.y, self.x);
+        let next = self.token_bounds(Direction::Right).map(|(start, _)| start).find(|&start| start > here);
+        match next {
+            Some(start) => self.jump_to(start),
+            None => self.move_to_end(),
+        }
+    }
+
+    /// Moves to the first character of the previous token (vi `b`), or to the start of the text.
+    pub fn move_word_start_backward(&mut self) {
+        let here = (self.y, self.x);
+        let start = self.token_bounds(Direction::Left).map(|(start, _)| start).find(|&start| start < here);
+        self.jump_to(start.unwrap_or((0, 0)));
+    }
+
+    /// Moves to the last character of the current or next token (vi `e`).
+    pub fn move_word_end_forward(&mut self) {
+        let here = (self.y, self.x);
+        let next = self.token_bounds(Direction::Right).map(|(_, end)| end).find(|&end| end > here);
+        if let Some(end) = next {
+            self.jump_to(end);
+        }
+    }
+
+    /// Bracket under the cursor, or just before it, and its partner, as wrapped `(row, col)` pairs,
+    /// for highlighting. Only rows near the screen are searched, and the result is kept until the
+    /// cursor, the scroll offset or the text changes.
+    pub fn matching_bracket(&self) -> Option<BracketMatch> {
+        let key = (self.y, self.x, self.vertical_offset);
+        if let Some((cached, found)) = self.bracket_match.get() && cached == key {
+            return found;
+        }
+        let first = self.vertical_offset.min(self.y).saturating_sub(BRACKET_SCAN_MARGIN);
+        let end = (self.vertical_offset + self.visible_rows).max(self.y + 1) + BRACKET_SCAN_MARGIN;
+        let found = self.find_matching_bracket(first..end);
+        self.bracket_match.set(Some((key, found)));
+        found
+    }
+
+    /// Bracket at the cursor and its partner, searched for among the tokens on `rows`.
+    /// Brackets in strings and comments still count: nothing marks those yet.
+    fn find_matching_bracket(&self, rows: Range<usize>) -> Option<BracketMatch> {
+        let line = self.wrap_id_for_line(self.y)?;
+        let row: Vec<TokenWithPos> = self.tokens.line_tokens(&self.wrap, line).filter(|t| t.row_start == Some(self.y)).collect();
+        let symbol_at = |x: usize| row.iter().find(|t| {
+            t.col_start == Some(x)
+                && t.token.as_ref().is_some_and(|token| matches!(token.token_type, TokenType::Symbol) && bracket_pair(&token.value, self.brackets).is_some())
+        });
+        let here = symbol_at(self.x).or_else(|| symbol_at(self.x.checked_sub(1)?))?;
+        let here_at = (here.row_start, here.col_start);
+        let (open, close, opens) = bracket_pair(&here.token.as_ref()?.value, self.brackets)?;
+        let in_rows = |t: &TokenWithPos| t.row_start.is_some_and(|row| rows.contains(&row));
+
+        let mut depth = 0;
+        let mut scan = |t: &TokenWithPos| {
+            let value = t.token.as_ref().map_or("", |token| token.value.as_str());
+            if value.chars().eq([open]) {
+                depth += if opens { 1 } else { -1 };
+            } else if value.chars().eq([close]) {
+                depth += if opens { -1 } else { 1 };
+            }
+            depth == 0
+        };
+        let partner = match opens {
+            true => self.tokens.tokens_after(&self.wrap, line)
+                .skip_while(|t| (t.row_start, t.col_start) < here_at)
+                .take_while(in_rows)
+                .find(|t| scan(t)),
+            false => self.tokens.tokens_before(&self.wrap, line)
+                .skip_while(|t| (t.row_start, t.col_start) > here_at)
+                .take_while(in_rows)
+                .find(|t| scan(t)),
+        }?;
+        Some(((here.row_start?, here.col_start?), (partner.row_start?, partner.col_start?)))
+    }
+
+    /// Moves to the bracket matching the one under the cursor. Returns false when there is none.
+    pub fn jump_to_matching_bracket(&mut self) -> bool {
+        match self.find_matching_bracket(0..usize::MAX) {
+            Some((_, partner)) => {
+                self.jump_to(partner);
+                true
+            }
+            None => false,
+        }
+    }
+
     fn cursor_in_last_token(&self) -> bool {
         let token = &self.last_token;
         let col_start = token.col_start.unwrap_or(0).saturating_sub(1);
@@ -294,8 +582,233 @@ impl CursorPos {
                 }
             }
             self.last_x = self.x;
-        } else {
-            return
         }
     }
 }
+
+
+/// Opening and closing bracket of the pair in `brackets` that `value` belongs to, and whether it is
+/// the opening one.
+fn bracket_pair(value: &str, brackets: &[(char, char)]) -> Option<(char, char, bool)> {
+    let mut chars = value.chars();
+    let (Some(c), None) = (chars.next(), chars.next()) else { return None };
+    brackets.iter().find(|&&(open, close)| c == open || c == close).map(|&(open, close)| (open, close, c == open))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::content_wrapper::wrap_content;
+
+    fn cursor_for(text: &str, width: usize) -> CursorPos {
+        let wrap_result = wrap_content(text, width);
+        let tokens = TokenCache::new(&wrap_result, "_", false);
+        CursorPos::new(wrap_result, tokens, 10)
+    }
+
+    #[test]
+    fn move_token_right_stops_after_each_word() {
+        let mut cursor = cursor_for("hello world", 40);
+        cursor.move_token(Direction::Right);
+        assert_eq!((cursor.x, cursor.y), (5, 0));
+        cursor.move_token(Direction::Right);
+        assert_eq!((cursor.x, cursor.y), (11, 0));
+    }
+
+    #[test]
+    fn move_token_left_returns_to_word_starts() {
+        let mut cursor = cursor_for("hello world", 40);
+        cursor.move_token(Direction::Right);
+        cursor.move_token(Direction::Right);
+        cursor.move_token(Direction::Left);
+        assert_eq!((cursor.x, cursor.y), (5, 0));
+        cursor.move_token(Direction::Left);
+        assert_eq!((cursor.x, cursor.y), (0, 0));
+    }
+
+    #[test]
+    fn move_token_treats_symbols_as_tokens() {
+        let mut cursor = cursor_for("a.b", 40);
+        cursor.move_token(Direction::Right);
+        assert_eq!(cursor.x, 1);
+        cursor.move_token(Direction::Right);
+        assert_eq!(cursor.x, 2);
+        cursor.move_token(Direction::Right);
+        assert_eq!(cursor.x, 3);
+    }
+
+    #[test]
+    fn move_token_follows_a_word_across_wrapped_rows() {
+        let mut cursor = cursor_for("abcdefgh", 6);
+        cursor.move_token(Direction::Right);
+        assert_eq!((cursor.x, cursor.y), (4, 1));
+    }
+
+    #[test]
+    fn move_token_does_not_jump_to_the_next_logical_line() {
+        let mut cursor = cursor_for("ab  \ncd", 40);
+        cursor.move_token(Direction::Right);
+        cursor.move_token(Direction::Right);
+        assert_eq!(cursor.y, 0);
+    }
+
+    #[test]
+    fn empty_file_does_not_panic() {
+        let mut cursor = cursor_for("", 40);
+        cursor.move_token(Direction::Right);
+        cursor.move_token(Direction::Left);
+        cursor.move_right();
+        assert_eq!((cursor.x, cursor.y), (0, 0));
+    }
+
+    #[test]
+    fn move_right_and_left_cross_wrapped_rows_of_one_line() {
+        let mut cursor = cursor_for("abcdef\nxy", 6);
+        for _ in 0..4 {
+            cursor.move_right();
+        }
+        assert_eq!((cursor.x, cursor.y), (0, 1));
+        cursor.move_left();
+        assert_eq!((cursor.x, cursor.y), (3, 0));
+    }
+
+    #[test]
+    fn home_and_end_cover_the_whole_logical_line() {
+        let mut cursor = cursor_for("abcdefghij\nxy", 6);
+        cursor.move_end();
+        assert_eq!((cursor.x, cursor.y), (2, 2));
+        cursor.move_home();
+        assert_eq!((cursor.x, cursor.y), (0, 0));
+    }
+
+    #[test]
+    fn logical_position_round_trips_through_wrapped_rows() {
+        let mut cursor = cursor_for("abcdefghij\nxy", 6);
+        cursor.set_logical_position(0, 6);
+        assert_eq!((cursor.x, cursor.y), (2, 1));
+        assert_eq!(cursor.logical_position(), (0, 6));
+
+        cursor.set_logical_position(1, 99);
+        assert_eq!((cursor.x, cursor.y), (2, 3));
+        assert_eq!(cursor.logical_position(), (1, 2));
+    }
+
+    #[test]
+    fn vi_word_motions_visit_token_starts_and_ends() {
+        let mut cursor = cursor_for("let x = f(y);\nnext", 40);
+        cursor.move_word_start_forward();
+        assert_eq!((cursor.x, cursor.y), (4, 0));
+        cursor.move_word_end_forward();
+        assert_eq!((cursor.x, cursor.y), (6, 0));
+        for _ in 0..6 {
+            cursor.move_word_start_forward();
+        }
+        assert_eq!((cursor.x, cursor.y), (0, 1));
+        cursor.move_word_start_backward();
+        assert_eq!((cursor.x, cursor.y), (12, 0));
+    }
+
+    #[test]
+    fn paging_keeps_the_screen_row() {
+        let text: Vec<String> = (0..30).map(|i| i.to_string()).collect();
+        let mut cursor = cursor_for(&text.join("\n"), 10);
+        cursor.y = 3;
+        cursor.page_down();
+        assert_eq!((cursor.y, cursor.vertical_offset), (13, 10));
+        cursor.page_down();
+        cursor.page_down();
+        assert_eq!((cursor.y, cursor.vertical_offset), (29, 20));
+        cursor.page_up();
+        assert_eq!((cursor.y, cursor.vertical_offset), (19, 10));
+    }
+
+    #[test]
+    fn scrolling_to_a_screen_row_stays_within_the_file() {
+        let text: Vec<String> = (0..30).map(|i| i.to_string()).collect();
+        let mut cursor = cursor_for(&text.join("\n"), 10);
+        cursor.y = 15;
+        cursor.scroll_to_screen_row(4);
+        assert_eq!(cursor.vertical_offset, 11);
+        cursor.scroll_to_screen_row(40);
+        assert_eq!(cursor.vertical_offset, 6);
+        cursor.y = 28;
+        cursor.scroll_to_screen_row(0);
+        assert_eq!(cursor.vertical_offset, 20);
+
+        let mut cursor = cursor_for("a\nb\nc", 10);
+        cursor.y = 2;
+        cursor.scroll_to_screen_row(0);
+        assert_eq!(cursor.vertical_offset, 0);
+    }
+
+    #[test]
+    fn scrolling_moves_the_cursor_only_when_it_leaves_the_screen() {
+        let text: Vec<String> = (0..30).map(|i| i.to_string()).collect();
+        let mut cursor = cursor_for(&text.join("\n"), 10);
+        cursor.y = 5;
+        cursor.scroll_down();
+        assert_eq!((cursor.y, cursor.vertical_offset), (5, 1));
+        for _ in 0..5 {
+            cursor.scroll_down();
+        }
+        assert_eq!((cursor.y, cursor.vertical_offset), (6, 6));
+        cursor.center_on_cursor();
+        assert_eq!(cursor.vertical_offset, 1);
+    }
+
+    #[test]
+    fn brackets_match_across_nesting_and_rows() {
+        let mut cursor = cursor_for("f(a[0], (b))", 100);
+        cursor.x = 1;
+        assert_eq!(cursor.matching_bracket(), Some(((0, 1), (0, 11))));
+        cursor.jump_to_matching_bracket();
+        assert_eq!((cursor.y, cursor.x), (0, 11));
+        cursor.jump_to((0, 10));
+        assert_eq!(cursor.matching_bracket(), Some(((0, 10), (0, 8))));
+        cursor.jump_to((0, 0));
+        assert_eq!(cursor.matching_bracket(), None);
+
+        // Just after a bracket counts too
+        let mut cursor = cursor_for("a {\n  b\n}", 100);
+        cursor.jump_to((2, 1));
+        assert_eq!(cursor.matching_bracket(), Some(((2, 0), (0, 2))));
+    }
+
+    #[test]
+    fn angle_brackets_match_only_where_the_language_pairs_them() {
+        let mut cursor = cursor_for("f(x) -> y", 100);
+        cursor.jump_to((0, 6));
+        assert_eq!(cursor.matching_bracket(), None);
+
+        let mut cursor = cursor_for("<p>", 100);
+        cursor.set_brackets(&[('(', ')'), ('<', '>')]);
+        assert_eq!(cursor.matching_bracket(), Some(((0, 0), (0, 2))));
+    }
+
+    #[test]
+    fn highlighting_looks_for_partners_only_near_the_screen() {
+        let text = format!("{{{}\n}}", "\nx".repeat(BRACKET_SCAN_MARGIN + 20));
+        let mut cursor = cursor_for(&text, 100);
+        assert_eq!(cursor.matching_bracket(), None);
+        // Jumping looks through the whole text
+        assert!(cursor.jump_to_matching_bracket());
+        assert_eq!(cursor.y, BRACKET_SCAN_MARGIN + 21);
+    }
+
+    #[test]
+    fn paragraph_motions_skip_wrapped_rows_of_a_line() {
+        let mut cursor = cursor_for("one two three\nfour\n\n\nfive six", 8);
+        assert_eq!(cursor.paragraph_lines(), Some((0, 1)));
+        // "one two three" takes three rows
+        cursor.move_paragraph(Direction::Right);
+        assert_eq!((cursor.x, cursor.y), (0, 4));
+        assert_eq!(cursor.paragraph_lines(), None);
+        cursor.move_paragraph(Direction::Right);
+        assert_eq!((cursor.x, cursor.y), (2, 7));
+        assert_eq!(cursor.paragraph_lines(), Some((4, 4)));
+        cursor.move_paragraph(Direction::Left);
+        assert_eq!((cursor.x, cursor.y), (0, 5));
+        cursor.move_paragraph(Direction::Left);
+        assert_eq!((cursor.x, cursor.y), (0, 0));
+    }
+}
@@ -0,0 +1,107 @@
+use crate::buffer::Position;
+use super::Editor;
+
+impl Editor {
+    /// Comments out the selected lines, or uncomments them when every non-blank one is already
+    /// commented. Uses the language's line comment if it has one and wraps each line in its block
+    /// comment otherwise. Markers line up at the smallest indentation among the lines.
+    pub(super) fn toggle_comment(&mut self) {
+        let (open, close) = match (self.language.line_comment, self.language.block_comment) {
+            (Some(open), _) => (open, None),
+            (None, Some((open, close))) => (open, Some(close)),
+            (None, None) => {
+                self.message = Some(format!("No comment syntax for {}", self.language.name));
+                return;
+            }
+        };
+        let (first, last) = self.selected_lines();
+        let lines = self.lines(first, last);
+        let blank = |line: &String| line.trim().is_empty();
+        let commented = |line: &String| {
+            let text = line.trim();
+            text.starts_with(open) && close.is_none_or(|close| text.len() >= open.len() + close.len() && text.ends_with(close))
+        };
+        if lines.iter().all(blank) {
+            return;
+        }
+        let uncomment = lines.iter().filter(|line| !blank(line)).all(commented);
+        let indent = lines.iter()
+            .filter(|line| !blank(line))
+            .map(|line| line.chars().take_while(|c| c.is_whitespace()).count())
+            .min()
+            .unwrap_or(0);
+
+        self.begin_edit();
+        // Each line's edit as the column it starts at and how many characters it adds or removes there
+        let mut edits = Vec::new();
+        let lines: Vec<String> = lines.into_iter()
+            .map(|line| {
+                if blank(&line) {
+                    edits.push((0, 0));
+                    return line;
+                }
+                let (edited, edit) = match uncomment {
+                    true => remove_comment(&line, open, close),
+                    false => add_comment(&line, indent, open, close),
+                };
+                edits.push(edit);
+                edited
+            })
+            .collect();
+        self.buffer.replace_lines(first, last, lines);
+
+        let shift = |at: Position| match at.line.checked_sub(first).and_then(|i| edits.get(i)) {
+            Some(&(col, by)) if at.col >= col => Position::new(at.line, at.col.saturating_add_signed(by).max(col)),
+            _ => at,
+        };
+        if let Some((anchor, kind)) = self.selection {
+            self.selection = Some((shift(anchor), kind));
+        }
+        self.rebuild_view(shift(self.position()));
+    }
+}
+
+/// Puts the markers around `line`, the opening one after `indent` characters.
+fn add_comment(line: &str, indent: usize, open: &str, close: Option<&str>) -> (String, (usize, isize)) {
+    let (head, text): (String, String) = (line.chars().take(indent).collect(), line.chars().skip(indent).collect());
+    let mut commented = format!("{}{} {}", head, open, text);
+    if let Some(close) = close {
+        commented.truncate(commented.trim_end().len());
+        commented.push_str(&format!(" {}", close));
+    }
+    (commented, (indent, open.chars().count() as isize + 1))
+}
+
+/// Takes the markers off a commented `line`, along with the space next to each.
+fn remove_comment(line: &str, open: &str, close: Option<&str>) -> (String, (usize, isize)) {
+    let indent = line.len() - line.trim_start().len();
+    let mut text = &line[indent + open.len()..];
+    let removed = open.chars().count() + usize::from(text.starts_with(' '));
+    text = text.strip_prefix(' ').unwrap_or(text);
+    let mut text = text.trim_end();
+    if let Some(close) = close {
+        text = text.strip_suffix(close).unwrap_or(text);
+        text = text.strip_suffix(' ').unwrap_or(text);
+    }
+    let col = line[..indent].chars().count();
+    (format!("{}{}", &line[..indent], text), (col, -(removed as isize)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn line_comments_go_after_the_given_indentation() {
+        assert_eq!(add_comment("    x", 2, "//", None), ("  //   x".to_string(), (2, 3)));
+        assert_eq!(remove_comment("  // x", "//", None), ("  x".to_string(), (2, -3)));
+        assert_eq!(remove_comment("#x", "#", None), ("x".to_string(), (0, -1)));
+    }
+
+    #[test]
+    fn block_comments_wrap_the_line() {
+        assert_eq!(add_comment("  <p>", 2, "<!--", Some("-->")).0, "  <!-- <p> -->");
+        assert_eq!(remove_comment("  <!-- <p> -->", "<!--", Some("-->")).0, "  <p>");
+        assert_eq!(remove_comment("/*x*/", "/*", Some("*/")).0, "x");
+    }
+}
@@ -0,0 +1,239 @@
+use crate::buffer::Position;
+use crate::language::{closing_bracket, opening_bracket};
+use super::Editor;
+
+impl Editor {
+    /// Records an undo step before a change.
+    pub(super) fn begin_edit(&mut self) {
+        self.buffer.checkpoint(self.position());
+        self.typing = false;
+    }
+
+    /// Types one character; consecutive characters are undone together. Opening brackets and quotes
+    /// bring their closer along, or wrap the selection.
+    pub(super) fn insert_char(&mut self, c: char) {
+        if !self.typing {
+            self.auto_closers.clear();
+        }
+        let pair = self.pairs().into_iter().find(|&(open, _)| open == c);
+        if self.selection.is_none() && self.auto_closers.last() == Some(&self.position()) && self.char_at(self.position()) == Some(c) {
+            let at = self.auto_closers.pop().unwrap_or_default();
+            return self.set_position(Position::new(at.line, at.col + 1));
+        }
+        if let Some((open, close)) = pair
+            && self.selection.is_some() {
+            return self.wrap_selection(open, close);
+        }
+        if !self.typing || self.selection.is_some() {
+            self.begin_edit();
+        }
+        let mut at = self.take_selection().unwrap_or_else(|| self.position());
+        if let Some(indent) = self.dedent_for(c, at) {
+            self.buffer.delete(Position::new(at.line, 0), at);
+            at = self.buffer.insert(Position::new(at.line, 0), &indent);
+            self.auto_closers.clear();
+        }
+        self.shift_auto_closers(at);
+        let end = self.buffer.insert(at, c.encode_utf8(&mut [0; 4]));
+        if let Some((_, close)) = pair.filter(|&(open, close)| self.closes_automatically(open, close, end)) {
+            self.shift_auto_closers(end);
+            self.buffer.insert(end, close.encode_utf8(&mut [0; 4]));
+            self.auto_closers.push(end);
+        }
+        self.typing = true;
+        self.rebuild_view(end);
+    }
+
+    /// Moves the auto-inserted closers after `at` one column right, for a character inserted there.
+    fn shift_auto_closers(&mut self, at: Position) {
+        for closer in self.auto_closers.iter_mut().filter(|closer| closer.line == at.line && closer.col >= at.col) {
+            closer.col += 1;
+        }
+    }
+
+    /// Brackets and quotes typed in pairs: the language's, unless the configuration names others.
+    fn pairs(&self) -> Vec<(char, char)> {
+        let settings = &self.config.settings;
+        if !settings.auto_pairs {
+            return Vec::new();
+        }
+        match settings.language(self.language.name).and_then(|language| language.pairs.clone()) {
+            Some(pairs) => pairs,
+            None => self.language.pairs.to_vec(),
+        }
+    }
+
+    fn char_at(&self, at: Position) -> Option<char> {
+        self.buffer.line(at.line).chars().nth(at.col)
+    }
+
+    /// Whether an opener just typed before `at` gets its closer: only before whitespace, a closer or the end
+    /// of the line, and for quotes not right after a word, where they are more likely apostrophes.
+    fn closes_automatically(&self, open: char, close: char, at: Position) -> bool {
+        let pairs = self.pairs();
+        let next_ok = self.char_at(at).is_none_or(|next| next.is_whitespace() || pairs.iter().any(|&(o, c)| c == next && o != c));
+        let previous = at.col.checked_sub(2).and_then(|col| self.char_at(Position::new(at.line, col)));
+        let previous_ok = open != close || !previous.is_some_and(|previous| previous.is_alphanumeric() || previous == open);
+        next_ok && previous_ok
+    }
+
+    /// Puts `open` and `close` around the selection, which stays on the text inside.
+    fn wrap_selection(&mut self, open: char, close: char) {
+        let (Some((anchor, kind)), Some((start, end, _))) = (self.selection, self.selection_range()) else {
+            return;
+        };
+        self.begin_edit();
+        self.buffer.insert(end, close.encode_utf8(&mut [0; 4]));
+        self.buffer.insert(start, open.encode_utf8(&mut [0; 4]));
+        let shift = |at: Position| match at.line == start.line && at.col >= start.col {
+            true => Position::new(at.line, at.col + 1),
+            false => at,
+        };
+        self.selection = Some((shift(anchor), kind));
+        self.rebuild_view(shift(self.position()));
+    }
+
+    /// Indentation a closing bracket typed at `at` should get: that of the line with its opening
+    /// bracket. None unless only whitespace precedes it on the line.
+    fn dedent_for(&self, close: char, at: Position) -> Option<String> {
+        let open = opening_bracket(close)?;
+        let before: String = self.buffer.line(at.line).chars().take(at.col).collect();
+        if before.is_empty() || !before.chars().all(char::is_whitespace) {
+            return None;
+        }
+        let mut depth = 0;
+        for line in (0..=at.line).rev() {
+            let text = self.buffer.line(line);
+            let take = if line == at.line { at.col } else { usize::MAX };
+            for c in text.chars().take(take).collect::<Vec<_>>().into_iter().rev() {
+                if c == close {
+                    depth += 1;
+                } else if c == open {
+                    if depth == 0 {
+                        return Some(indentation(text).to_string());
+                    }
+                    depth -= 1;
+                }
+            }
+        }
+        // No opening bracket: take off one level
+        let unit = self.indent_unit(&before);
+        Some(before.strip_suffix(unit.as_str()).unwrap_or("").to_string())
+    }
+
+    /// Breaks the line, keeping its indentation and adding a level after an opening bracket or
+    /// the language's other indent triggers. Between a bracket pair the closer gets its own line.
+    pub(super) fn newline(&mut self) {
+        self.begin_edit();
+        let at = self.take_selection().unwrap_or_else(|| self.position());
+        let line = self.buffer.line(at.line);
+        let before: String = line.chars().take(at.col).collect();
+        let after: String = line.chars().skip(at.col).collect();
+        let indent = indentation(&before).to_string();
+
+        let trigger = before.trim_end().chars().last().filter(|c| self.language.indent_after.contains(c));
+        let mut text = format!("\n{}", indent);
+        if trigger.is_some() {
+            text.push_str(&self.indent_unit(&indent));
+        }
+        let closes_pair = trigger.and_then(closing_bracket).is_some_and(|close| after.trim_start().starts_with(close));
+
+        // Whitespace after the cursor would end up before the text on the new line
+        let blanks = after.chars().take_while(|c| c.is_whitespace()).count();
+        self.buffer.delete(at, Position::new(at.line, at.col + blanks));
+        let end = self.buffer.insert(at, &text);
+        if closes_pair {
+            self.buffer.insert(end, &format!("\n{}", indent));
+        }
+        self.rebuild_view(end);
+    }
+
+    /// One level of indentation: a tab if `indent` already uses tabs, `tab_width` spaces otherwise.
+    pub(super) fn indent_unit(&self, indent: &str) -> String {
+        match indent.starts_with('\t') {
+            true => "\t".to_string(),
+            false => " ".repeat(self.config.settings.tab_width),
+        }
+    }
+
+    /// Inserts text at the cursor, replacing the selection if there is one.
+    pub(super) fn insert_text(&mut self, text: &str) {
+        self.begin_edit();
+        let at = self.take_selection().unwrap_or_else(|| self.position());
+        let end = self.buffer.insert(at, text);
+        self.rebuild_view(end);
+    }
+
+    pub(super) fn delete_backward(&mut self) {
+        let end = self.position();
+        // Nothing to delete at the start of the text, and no undo step for it either
+        if self.selection.is_none() && end == Position::new(0, 0) {
+            return;
+        }
+        self.begin_edit();
+        if let Some(at) = self.take_selection() {
+            return self.rebuild_view(at);
+        }
+        let start = match end {
+            Position { line, col: 0 } => Position::new(line - 1, self.buffer.line_len(line - 1)),
+            Position { line, col } => Position::new(line, col - 1),
+        };
+        // Deleting the opener of an empty pair deletes its closer too
+        let empty_pair = self.char_at(start).zip(self.char_at(end)).is_some_and(|pair| self.pairs().contains(&pair));
+        let end = match empty_pair {
+            true => Position::new(end.line, end.col + 1),
+            false => end,
+        };
+        self.buffer.delete(start, end);
+        self.rebuild_view(start);
+    }
+
+    pub(super) fn delete_forward(&mut self) {
+        let start = self.position();
+        if self.selection.is_none() && start == self.buffer.end() {
+            return;
+        }
+        self.begin_edit();
+        if let Some(at) = self.take_selection() {
+            return self.rebuild_view(at);
+        }
+        let end = self.buffer.position_of(self.buffer.offset_of(start) + 1);
+        self.buffer.delete(start, end);
+        self.rebuild_view(start);
+    }
+
+    /// Deletes the selected text and returns where it started.
+    fn take_selection(&mut self) -> Option<Position> {
+        let (start, end, _) = self.selection_range()?;
+        self.selection = None;
+        self.buffer.delete(start, end);
+        Some(start)
+    }
+
+    pub(super) fn undo(&mut self) {
+        self.typing = false;
+        match self.buffer.undo(self.position()) {
+            Some(position) => {
+                self.selection = None;
+                self.rebuild_view(position);
+            }
+            None => self.message = Some("Nothing to undo".to_string()),
+        }
+    }
+
+    pub(super) fn redo(&mut self) {
+        self.typing = false;
+        match self.buffer.redo(self.position()) {
+            Some(position) => {
+                self.selection = None;
+                self.rebuild_view(position);
+            }
+            None => self.message = Some("Nothing to redo".to_string()),
+        }
+    }
+}
+
+/// Leading whitespace of a line.
+pub(super) fn indentation(line: &str) -> &str {
+    &line[..line.len() - line.trim_start().len()]
+}
@@ -0,0 +1,79 @@
+use crate::buffer::Position;
+use super::{Editor, SelectionKind};
+
+impl Editor {
+    pub(super) fn set_mark(&mut self) {
+        self.selection = Some((self.position(), SelectionKind::Exclusive));
+        self.message = Some("Mark set".to_string());
+    }
+
+    /// Kills to the end of the line, or the line break when already there.
+    pub(super) fn kill_line(&mut self, append: bool) {
+        let start = self.position();
+        let len = self.buffer.line_len(start.line);
+        let end = if start.col < len {
+            Position::new(start.line, len)
+        } else if start.line + 1 < self.buffer.line_count() {
+            Position::new(start.line + 1, 0)
+        } else {
+            self.message = Some("End of buffer".to_string());
+            return;
+        };
+        self.begin_edit();
+        self.selection = None;
+        let text = self.buffer.delete(start, end);
+        match append {
+            true => self.kill_ring.append(&text),
+            false => self.kill_ring.push(text),
+        }
+        self.rebuild_view(start);
+    }
+
+    /// Adds the text between mark and cursor to the kill ring, removing it when `delete` is set.
+    pub(super) fn kill_region(&mut self, delete: bool) {
+        let Some((start, end, _)) = self.selection_range() else {
+            self.message = Some("The mark is not set".to_string());
+            return;
+        };
+        self.selection = None;
+        let text = if delete {
+            self.begin_edit();
+            let text = self.buffer.delete(start, end);
+            self.rebuild_view(start);
+            text
+        } else {
+            self.buffer.slice(start, end)
+        };
+        self.kill_ring.push(text);
+    }
+
+    pub(super) fn yank(&mut self) {
+        self.kill_ring.reset_yank();
+        let Some(text) = self.kill_ring.current().map(str::to_string) else {
+            self.message = Some("Kill ring is empty".to_string());
+            return;
+        };
+        self.begin_edit();
+        self.selection = None;
+        let start = self.position();
+        let end = self.buffer.insert(start, &text);
+        self.yanked = Some((start, end));
+        self.rebuild_view(end);
+    }
+
+    /// Replaces the text just yanked with the kill before it.
+    pub(super) fn yank_pop(&mut self, after_yank: bool) {
+        let Some((start, end)) = self.yanked.filter(|_| after_yank) else {
+            self.message = Some("Previous command was not a yank".to_string());
+            return;
+        };
+        let Some(text) = self.kill_ring.rotate().map(str::to_string) else {
+            return;
+        };
+        self.begin_edit();
+        self.buffer.delete(start, end);
+        let end = self.buffer.insert(start, &text);
+        self.yanked = Some((start, end));
+        self.rebuild_view(end);
+    }
+}
@@ -0,0 +1,72 @@
+use crate::buffer::Position;
+use super::Editor;
+
+impl Editor {
+    /// Moves to the target typed in the go-to-line prompt and centers it on screen.
+    pub(super) fn goto(&mut self, input: &str) {
+        let current = self.position().line;
+        let Some((line, col)) = parse_target(input, current, self.buffer.line_count()) else {
+            self.message = Some(format!("Invalid line: {}", input.trim()));
+            return;
+        };
+        self.selection = None;
+        self.jump(|editor| editor.set_position(Position::new(line, col.unwrap_or(0))));
+        self.cursor.center_on_cursor();
+    }
+}
+
+/// Parses `line`, `line:col`, `+N`, `-N` or `N%` into a logical line and optional column, all counted from 0.
+/// Lines past the end of the file are clamped to the last one.
+fn parse_target(input: &str, current: usize, line_count: usize) -> Option<(usize, Option<usize>)> {
+    let input = input.trim();
+    let (line_part, col_part) = match input.split_once(':') {
+        Some((line, col)) => (line.trim(), Some(col.trim())),
+        None => (input, None),
+    };
+
+    let line = if let Some(percent) = line_part.strip_suffix('%') {
+        let percent: usize = percent.trim().parse().ok()?;
+        // 0% is the first line and 100% the last
+        (line_count.saturating_sub(1) * percent.min(100) + 50) / 100
+    } else if let Some(offset) = line_part.strip_prefix('+') {
+        current.saturating_add(offset.parse().ok()?)
+    } else if let Some(offset) = line_part.strip_prefix('-') {
+        current.saturating_sub(offset.parse().ok()?)
+    } else {
+        line_part.parse::<usize>().ok()?.saturating_sub(1)
+    };
+
+    let col = match col_part {
+        Some(col) => Some(col.parse::<usize>().ok()?.saturating_sub(1)),
+        None => None,
+    };
+    Some((line.min(line_count.saturating_sub(1)), col))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_absolute_and_relative_targets() {
+        assert_eq!(parse_target("12", 0, 100), Some((11, None)));
+        assert_eq!(parse_target("12:5", 0, 100), Some((11, Some(4))));
+        assert_eq!(parse_target("+3", 10, 100), Some((13, None)));
+        assert_eq!(parse_target("-30", 10, 100), Some((0, None)));
+        assert_eq!(parse_target("500", 0, 100), Some((99, None)));
+    }
+
+    #[test]
+    fn parses_percentages() {
+        assert_eq!(parse_target("0%", 5, 101), Some((0, None)));
+        assert_eq!(parse_target("50%", 5, 101), Some((50, None)));
+        assert_eq!(parse_target("100%", 5, 101), Some((100, None)));
+    }
+
+    #[test]
+    fn rejects_garbage() {
+        assert_eq!(parse_target("", 0, 10), None);
+        assert_eq!(parse_target("abc", 0, 10), None);
+        assert_eq!(parse_target("3:x", 0, 10), None);
+    }
+}
@@ -0,0 +1,85 @@
+use crossterm::event::{KeyCode, KeyModifiers};
+use crate::buffer::Position;
+use crate::keymap::{Command, KeyChord, KeyLookup};
+use crate::search::{find, Search};
+use super::Editor;
+
+impl Editor {
+    pub(super) fn start_search(&mut self, forward: bool) {
+        self.selection = None;
+        self.search = Some(Search::new(self.position(), forward));
+    }
+
+    /// Handles a key while searching. Returns false when the key ends the search and should then run as usual.
+    pub(super) fn handle_search_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> bool {
+        let Some(mut search) = self.search.take() else {
+            return false;
+        };
+        let typed = !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT);
+        match (code, self.keymap.lookup(&[KeyChord::new(code, modifiers)])) {
+            (_, KeyLookup::Command(Command::SearchForward)) => self.repeat_search(&mut search, true),
+            (_, KeyLookup::Command(Command::SearchBackward)) => self.repeat_search(&mut search, false),
+            (KeyCode::Esc, _) | (_, KeyLookup::Command(Command::Cancel)) => {
+                self.set_position(search.origin);
+                return true;
+            }
+            (KeyCode::Enter, _) => {
+                self.finish_search(search);
+                return true;
+            }
+            (KeyCode::Backspace, _) => {
+                search.query.pop();
+                let origin = search.origin;
+                self.update_search(&mut search, origin);
+            }
+            (KeyCode::Char(c), _) if typed => {
+                search.query.push(c);
+                // Extend the current match rather than jumping to the next one
+                let from = search.found.map_or(search.origin, |(start, _)| start);
+                self.update_search(&mut search, from);
+            }
+            _ => {
+                self.finish_search(search);
+                return false;
+            }
+        }
+        self.search = Some(search);
+        true
+    }
+
+    /// Moves to the next match in `forward`'s direction, or searches for the last query again.
+    fn repeat_search(&mut self, search: &mut Search, forward: bool) {
+        if search.query.is_empty() {
+            search.query = self.last_search.clone();
+        }
+        let from = match search.found {
+            Some((start, _)) if forward => self.buffer.position_of(self.buffer.offset_of(start) + 1),
+            Some((start, _)) => match self.buffer.offset_of(start) {
+                0 => self.buffer.end(),
+                offset => self.buffer.position_of(offset - 1),
+            },
+            None => self.position(),
+        };
+        search.forward = forward;
+        self.update_search(search, from);
+    }
+
+    fn update_search(&mut self, search: &mut Search, from: Position) {
+        search.found = find(&self.buffer, &search.query, from, search.forward);
+        match search.found {
+            Some((_, end)) if search.forward => self.set_position(end),
+            Some((start, _)) => self.set_position(start),
+            None if search.query.is_empty() => self.set_position(search.origin),
+            None => {}
+        }
+    }
+
+    pub(super) fn finish_search(&mut self, search: Search) {
+        if self.position() != search.origin {
+            self.record_jump(search.origin);
+        }
+        if !search.query.is_empty() {
+            self.last_search = search.query;
+        }
+    }
+}
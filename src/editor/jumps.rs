@@ -0,0 +1,54 @@
+use crate::buffer::Position;
+use super::Editor;
+
+impl Editor {
+    /// Runs a jump such as going to a line, and records where the cursor was in the jump list if it moved.
+    pub(super) fn jump<R>(&mut self, jump: impl FnOnce(&mut Self) -> R) -> R {
+        let from = self.position();
+        let result = jump(self);
+        if self.position() != from {
+            self.record_jump(from);
+        }
+        result
+    }
+
+    pub(super) fn record_jump(&mut self, from: Position) {
+        self.buffer.marks_mut().push_jump(from);
+    }
+
+    pub(super) fn jump_back(&mut self) {
+        let cursor = self.position();
+        match self.buffer.marks_mut().jump_back(cursor) {
+            Some(position) => self.set_position(position),
+            None => self.message = Some("No earlier jump".to_string()),
+        }
+    }
+
+    pub(super) fn jump_forward(&mut self) {
+        match self.buffer.marks_mut().jump_forward() {
+            Some(position) => self.set_position(position),
+            None => self.message = Some("No later jump".to_string()),
+        }
+    }
+
+    pub(super) fn set_named_mark(&mut self, name: char) {
+        let position = self.position();
+        self.buffer.marks_mut().set(name, position);
+        self.message = Some(format!("Mark {} set", name));
+    }
+
+    pub(super) fn goto_mark(&mut self, name: char) {
+        if let Some(position) = self.named_mark(name) {
+            self.jump(|editor| editor.set_position(position));
+        }
+    }
+
+    /// Where mark `name` is, or `None` with a message when it was never set.
+    pub(super) fn named_mark(&mut self, name: char) -> Option<Position> {
+        let position = self.buffer.marks().get(name).map(|position| self.buffer.clamp(position));
+        if position.is_none() {
+            self.message = Some(format!("Mark {} is not set", name));
+        }
+        position
+    }
+}
@@ -0,0 +1,92 @@
+use crate::buffer::Position;
+use super::Editor;
+
+impl Editor {
+    /// Lines the line commands work on: every line the selection touches, or the cursor's. A selection
+    /// ending at the start of a line leaves that line out. Lines are logical, whatever rows they wrap to.
+    pub(super) fn selected_lines(&self) -> (usize, usize) {
+        match self.selection_range() {
+            Some((start, end, _)) if end.line > start.line && end.col == 0 => (start.line, end.line - 1),
+            Some((start, end, _)) => (start.line, end.line),
+            None => (self.position().line, self.position().line),
+        }
+    }
+
+    pub(super) fn lines(&self, first: usize, last: usize) -> Vec<String> {
+        (first..=last).map(|line| self.buffer.line(line).to_string()).collect()
+    }
+
+    /// Moves the cursor and the selection anchor `by` lines, following the text they were on.
+    fn follow_lines(&mut self, by: isize) {
+        let shift = |at: Position| Position::new(at.line.saturating_add_signed(by), at.col);
+        if let Some((anchor, kind)) = self.selection {
+            self.selection = Some((shift(anchor), kind));
+        }
+        self.rebuild_view(shift(self.position()));
+    }
+
+    /// Swaps the selected lines with the line above or below them.
+    pub(super) fn move_lines(&mut self, down: bool) {
+        let (first, last) = self.selected_lines();
+        if (down && last + 1 >= self.buffer.line_count()) || (!down && first == 0) {
+            return;
+        }
+        self.begin_edit();
+        let mut lines = self.lines(first, last);
+        if down {
+            lines.insert(0, self.buffer.line(last + 1).to_string());
+            self.buffer.replace_lines(first, last + 1, lines);
+            self.follow_lines(1);
+        } else {
+            lines.push(self.buffer.line(first - 1).to_string());
+            self.buffer.replace_lines(first - 1, last, lines);
+            self.follow_lines(-1);
+        }
+    }
+
+    /// Copies the selected lines below themselves and moves to the copy.
+    pub(super) fn duplicate_lines(&mut self) {
+        let (first, last) = self.selected_lines();
+        self.begin_edit();
+        let lines = self.lines(first, last);
+        self.buffer.replace_lines(first, last, [lines.clone(), lines].concat());
+        self.follow_lines((last - first + 1) as isize);
+    }
+
+    pub(super) fn delete_lines(&mut self) {
+        let (first, last) = self.selected_lines();
+        self.begin_edit();
+        let col = self.position().col;
+        self.selection = None;
+        self.buffer.replace_lines(first, last, Vec::new());
+        self.rebuild_view(Position::new(first.min(self.buffer.line_count() - 1), col));
+    }
+
+    /// Joins the selected lines, or the cursor line with the next one, replacing the line break and
+    /// the indentation after it with a single space. The cursor goes to the last join.
+    pub(super) fn join_lines(&mut self) {
+        let (first, last) = match self.selected_lines() {
+            (first, last) if first == last => (first, last + 1),
+            lines => lines,
+        };
+        if last >= self.buffer.line_count() {
+            return;
+        }
+        self.begin_edit();
+        self.selection = None;
+        let mut lines = self.lines(first, last).into_iter();
+        let mut joined = lines.next().unwrap_or_default();
+        let mut col = 0;
+        for line in lines {
+            joined.truncate(joined.trim_end().len());
+            col = joined.chars().count();
+            let line = line.trim_start();
+            if !joined.is_empty() && !line.is_empty() {
+                joined.push(' ');
+            }
+            joined.push_str(line);
+        }
+        self.buffer.replace_lines(first, last, vec![joined]);
+        self.rebuild_view(Position::new(first, col));
+    }
+}
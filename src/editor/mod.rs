@@ -0,0 +1,558 @@
+mod comments;
+mod editing;
+mod emacs;
+mod goto;
+mod isearch;
+mod jumps;
+mod lines;
+mod mouse;
+mod prompt;
+mod prose;
+mod recovery;
+mod vi_mode;
+
+use std::io;
+use crossterm::event::{KeyCode, KeyModifiers};
+use crate::buffer::{Buffer, ChangedLines, LineEnding, Position};
+use crate::config::{Config, KeymapPreset, Settings, WrapMode};
+use crate::cursor::CursorPos;
+use crate::keymap::{sequence_to_string, Command, KeyChord, KeyLookup, Keymap};
+use crate::kill_ring::KillRing;
+use crate::language::Language;
+use crate::palette::{Palette, PaletteAction};
+use crate::screen::{gutter_width, Overlay, Screen, StatusLine, View};
+use crate::search::Search;
+use crate::swap::SwapFile;
+use crate::terminal::Terminal;
+use crate::utils::content_wrapper::{wrap_line, wrap_lines};
+use crate::utils::direction::Direction;
+use crate::utils::tokenizer::{subword_stop, TokenCache};
+use crate::vi::{Vi, ViKey, ViMode};
+use mouse::Click;
+use prompt::{Prompt, PromptKind};
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum SelectionKind {
+    /// Ends before the cursor, like the Emacs region.
+    Exclusive,
+    /// Includes the character under the cursor, like vi's visual mode.
+    Inclusive,
+    /// Whole lines from the anchor's line to the cursor's.
+    Lines,
+}
+
+/// Text kept by delete and yank, for put.
+#[derive(Default)]
+struct Register {
+    text: String,
+    /// Holds whole lines, which are put above or below the cursor line.
+    linewise: bool,
+}
+
+/// Editor state shared by the key handlers and the renderer.
+pub struct Editor {
+    buffer: Buffer,
+    language: &'static Language,
+    config: Config,
+    /// Width the text was wrapped at. Edits that change it, by adding a digit to the line numbers,
+    /// rewrap the whole text; others only the edited lines.
+    wrap_width: usize,
+    cursor: CursorPos,
+    screen: Screen,
+    keymap: Keymap,
+    pending_keys: Vec<KeyChord>,
+    describing_key: bool,
+    palette: Option<Palette>,
+    prompt: Option<Prompt>,
+    /// Set when the vi keymap is in use.
+    vi: Option<Vi>,
+    /// Where the selection started and how it extends to the cursor.
+    selection: Option<(Position, SelectionKind)>,
+    register: Register,
+    kill_ring: KillRing,
+    /// Range of the text inserted by the last yank, which yank-pop replaces.
+    yanked: Option<(Position, Position)>,
+    /// Command run by the previous key, so kills can append and yank-pop can follow a yank.
+    last_command: Option<Command>,
+    search: Option<Search>,
+    last_search: String,
+    last_click: Option<Click>,
+    /// Where a left-button drag started, so moving the mouse extends a selection from there.
+    drag_anchor: Option<Position>,
+    /// The last edit was typed text, so the next typed character joins its undo step.
+    typing: bool,
+    /// Closing brackets and quotes typed by auto-pairing in the current run of typing, innermost
+    /// last. Typing the closer over one of these steps past it instead of inserting another.
+    auto_closers: Vec<Position>,
+    /// Where unsaved changes are kept for recovery after a crash, once `open_swap` is called.
+    swap: Option<SwapFile>,
+    quit: bool,
+    /// A suspend command ran; the main loop hands the terminal back to the shell after the key.
+    suspend: bool,
+    message: Option<String>,
+    cols: u16,
+    rows: u16,
+}
+
+impl Editor {
+    pub fn new(buffer: Buffer, config: Config, cols: u16, rows: u16) -> Self {
+        let language = Language::for_path(buffer.path());
+        let cursor = build_view(&buffer, &config.settings, language, cols, rows);
+        let wrap_width = wrap_width(&buffer, &config.settings, cols);
+        let message = config_message(&config);
+        let keymap = build_keymap(&config.settings);
+        Self {
+            vi: build_vi(&config.settings),
+            language,
+            buffer,
+            config,
+            wrap_width,
+            cursor,
+            screen: Screen::new(),
+            keymap,
+            pending_keys: Vec::new(),
+            describing_key: false,
+            palette: None,
+            prompt: None,
+            selection: None,
+            register: Register::default(),
+            kill_ring: KillRing::default(),
+            yanked: None,
+            last_command: None,
+            search: None,
+            last_search: String::new(),
+            last_click: None,
+            drag_anchor: None,
+            typing: false,
+            auto_closers: Vec::new(),
+            swap: None,
+            quit: false,
+            suspend: false,
+            message,
+            cols,
+            rows,
+        }
+    }
+
+    /// Rewraps the text for the new terminal size, keeping the cursor on the same logical position
+    /// and, as far as the file allows, on the same screen row.
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        self.cols = cols;
+        self.rows = rows;
+        let position = self.position();
+        let screen_row = self.cursor.y.saturating_sub(self.cursor.vertical_offset);
+        self.build_view();
+        self.set_position(position);
+        self.cursor.scroll_to_screen_row(screen_row);
+        self.cursor.ensure_visible();
+    }
+
+    /// Whether a suspend command ran since the last call.
+    pub fn take_suspend_request(&mut self) -> bool {
+        std::mem::take(&mut self.suspend)
+    }
+
+    /// Picks up after the editor was suspended: the terminal may have been resized and drawn over by
+    /// the shell in the meantime, so the text is rewrapped and the next draw repaints everything.
+    pub fn resume(&mut self, cols: u16, rows: u16) {
+        self.resize(cols, rows);
+        self.screen.invalidate();
+    }
+
+    /// Rebuilds the wrapped view, keeping the cursor on the same logical position.
+    fn rewrap(&mut self) {
+        let position = self.position();
+        let vertical_offset = self.cursor.vertical_offset;
+        self.build_view();
+        self.cursor.vertical_offset = vertical_offset;
+        self.set_position(position);
+        self.cursor.ensure_visible();
+    }
+
+    /// Brings the wrapped view up to date after the text changed and puts the cursor at `position`.
+    fn rebuild_view(&mut self, position: Position) {
+        if let Some(changed) = self.buffer.take_changed_lines() {
+            match wrap_width(&self.buffer, &self.config.settings, self.cols) == self.wrap_width {
+                true => self.update_view(changed),
+                false => {
+                    let vertical_offset = self.cursor.vertical_offset;
+                    self.build_view();
+                    self.cursor.vertical_offset = vertical_offset;
+                }
+            }
+        }
+        self.set_position(position);
+        self.cursor.ensure_visible();
+    }
+
+    /// Rewraps and tokenizes the whole text, as after a resize or a settings change.
+    fn build_view(&mut self) {
+        self.cursor = build_view(&self.buffer, &self.config.settings, self.language, self.cols, self.rows);
+        self.wrap_width = wrap_width(&self.buffer, &self.config.settings, self.cols);
+        // The whole text is in the view now
+        self.buffer.take_changed_lines();
+    }
+
+    /// Rewraps and tokenizes only the changed lines.
+    fn update_view(&mut self, changed: ChangedLines) {
+        let lines = (changed.first..changed.first + changed.added)
+            .map(|line| wrap_line(self.buffer.line(line), self.wrap_width))
+            .collect();
+        self.cursor.replace_lines(changed.first, changed.removed, lines);
+    }
+
+    fn word_chars(&self) -> String {
+        word_chars(self.language, &self.config.settings)
+    }
+
+    fn position(&self) -> Position {
+        let (line, col) = self.cursor.logical_position();
+        Position::new(line, col)
+    }
+
+    fn set_position(&mut self, position: Position) {
+        let position = self.buffer.clamp(position);
+        self.cursor.set_logical_position(position.line, position.col);
+    }
+
+    /// Moves to the next or previous camelCase or snake_case part, going on to the next or previous
+    /// line from the end or the start of one.
+    fn move_subword(&mut self, direction: Direction) {
+        let Position { line, col } = self.position();
+        let chars: Vec<char> = self.buffer.line(line).chars().collect();
+        let target = match direction {
+            Direction::Right if col >= chars.len() && line + 1 < self.buffer.line_count() => Position::new(line + 1, 0),
+            Direction::Left if col == 0 && line > 0 => Position::new(line - 1, self.buffer.line_len(line - 1)),
+            _ => Position::new(line, subword_stop(&chars, col, direction, &self.word_chars())),
+        };
+        self.set_position(target);
+    }
+
+    /// The selected text as a `[start, end)` range, and whether it covers whole lines.
+    fn selection_range(&self) -> Option<(Position, Position, bool)> {
+        let (anchor, kind) = self.selection?;
+        let cursor = self.position();
+        let (start, end) = (anchor.min(cursor), anchor.max(cursor));
+        Some(match kind {
+            SelectionKind::Exclusive => (start, end, false),
+            SelectionKind::Inclusive => (start, self.buffer.position_of(self.buffer.offset_of(end) + 1), false),
+            SelectionKind::Lines => (Position::new(start.line, 0), Position::new(end.line, self.buffer.line_len(end.line)), true),
+        })
+    }
+
+    pub fn reload_config(&mut self) {
+        let preset = self.config.settings.keymap;
+        self.config = self.config.reload();
+        if self.config.settings.keymap != preset {
+            self.vi = build_vi(&self.config.settings);
+            self.selection = None;
+        }
+        self.keymap = build_keymap(&self.config.settings);
+        self.message = config_message(&self.config).or_else(|| Some("Configuration reloaded".to_string()));
+        self.rewrap();
+    }
+
+    /// Reloads the configuration if one of its files changed on disk. Returns true when it did.
+    pub fn reload_config_if_stale(&mut self) -> bool {
+        if !self.config.is_stale() {
+            return false;
+        }
+        self.reload_config();
+        true
+    }
+
+    /// Handles one key press. Returns false when the editor should quit.
+    pub fn handle_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> bool {
+        self.message = None;
+        if let Some(palette) = &mut self.palette {
+            match palette.handle_key(code, modifiers, &self.keymap) {
+                PaletteAction::None => {}
+                PaletteAction::Close => self.palette = None,
+                PaletteAction::Run(command) => {
+                    self.palette = None;
+                    self.execute(command);
+                    self.cursor.ensure_visible();
+                }
+            }
+            return !self.quit;
+        }
+
+        if self.prompt.is_some() {
+            self.handle_prompt_key(code, modifiers);
+            return !self.quit;
+        }
+
+        if self.search.is_some() && self.handle_search_key(code, modifiers) {
+            self.cursor.ensure_visible();
+            return true;
+        }
+
+        if !self.describing_key && self.pending_keys.is_empty()
+            && let Some(vi) = &mut self.vi {
+            match vi.handle_key(code, modifiers) {
+                ViKey::Pending => {
+                    self.message = Some(vi.pending());
+                    return true;
+                }
+                ViKey::Command(command) => {
+                    self.last_command = None;
+                    self.execute_vi(command);
+                    self.finish_key();
+                    return !self.quit;
+                }
+                ViKey::Unhandled => {}
+            }
+        }
+
+        self.pending_keys.push(KeyChord::new(code, modifiers));
+
+        let lookup = self.keymap.lookup(&self.pending_keys);
+        if let KeyLookup::Pending = lookup {
+            self.message = Some(format!("{}-", sequence_to_string(&self.pending_keys)));
+            return true;
+        }
+        let keys = std::mem::take(&mut self.pending_keys);
+
+        if self.describing_key {
+            self.describing_key = false;
+            self.message = Some(match lookup {
+                KeyLookup::Command(command) => format!("{} runs {}", sequence_to_string(&keys), command.name()),
+                _ => format!("{} is not bound", sequence_to_string(&keys)),
+            });
+            return true;
+        }
+
+        match lookup {
+            KeyLookup::Command(command) if command.edits_text() && !self.accepts_text() => {
+                self.message = Some(format!("{} edits text; press i to insert", sequence_to_string(&keys)));
+            }
+            KeyLookup::Command(command) => self.execute(command),
+            _ if keys.len() > 1 => self.message = Some(format!("{} is not bound", sequence_to_string(&keys))),
+            _ => match code {
+                KeyCode::Char(c) if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) && self.accepts_text() => {
+                    self.last_command = None;
+                    self.insert_char(c)
+                }
+                // Ctrl+Z suspends, as in a shell, unless it is bound to something else such as undo
+                KeyCode::Char('z') if modifiers == KeyModifiers::CONTROL => self.execute(Command::Suspend),
+                _ => {}
+            },
+        }
+        self.finish_key();
+        !self.quit
+    }
+
+    /// Whether unbound printable keys insert themselves; vi only does so in insert mode.
+    fn accepts_text(&self) -> bool {
+        self.vi.as_ref().is_none_or(|vi| vi.mode == ViMode::Insert)
+    }
+
+    fn finish_key(&mut self) {
+        if self.vi.as_ref().is_some_and(|vi| vi.mode != ViMode::Insert) {
+            self.clamp_to_last_char();
+        }
+        self.cursor.ensure_visible();
+    }
+
+    pub fn execute(&mut self, command: Command) {
+        self.typing = false;
+        let previous = self.last_command.replace(command);
+        match command {
+            // Quitting twice in a row discards unsaved changes
+            Command::Quit if self.buffer.is_modified() && previous != Some(Command::Quit) => {
+                self.message = Some("Quit again to discard unsaved changes".to_string());
+            }
+            Command::Quit => self.quit = true,
+            Command::Suspend if cfg!(unix) => self.suspend = true,
+            Command::Suspend => self.message = Some("Suspending is not supported on this system".to_string()),
+            Command::MoveUp => self.cursor.move_up(),
+            Command::MoveDown => self.cursor.move_down(),
+            Command::MoveLeft => self.cursor.move_left(),
+            Command::MoveRight => self.cursor.move_right(),
+            Command::TokenLeft => self.cursor.move_token(Direction::Left),
+            Command::TokenRight => self.cursor.move_token(Direction::Right),
+            Command::SubwordLeft => self.move_subword(Direction::Left),
+            Command::SubwordRight => self.move_subword(Direction::Right),
+            Command::SentenceBackward => self.move_sentence(Direction::Left),
+            Command::SentenceForward => self.move_sentence(Direction::Right),
+            Command::ParagraphBackward => self.cursor.move_paragraph(Direction::Left),
+            Command::ParagraphForward => self.cursor.move_paragraph(Direction::Right),
+            Command::SelectParagraph => self.select_paragraph(),
+            Command::ReflowParagraph => self.reflow_paragraph(),
+            Command::LineStart => self.cursor.move_home(),
+            Command::LineEnd => self.cursor.move_end(),
+            Command::PageUp => self.cursor.page_up(),
+            Command::PageDown => self.cursor.page_down(),
+            Command::FileStart => self.jump(|editor| editor.cursor.move_to_start()),
+            Command::FileEnd => self.jump(|editor| editor.cursor.move_to_end()),
+            Command::ScrollUp => self.cursor.scroll_up(),
+            Command::ScrollDown => self.cursor.scroll_down(),
+            Command::CenterCursor => self.cursor.center_on_cursor(),
+            Command::MatchingBracket => {
+                if !self.jump(|editor| editor.cursor.jump_to_matching_bracket()) {
+                    self.message = Some("No matching bracket".to_string());
+                }
+            }
+            Command::Newline => self.newline(),
+            Command::DeleteBackward => self.delete_backward(),
+            Command::DeleteForward => self.delete_forward(),
+            Command::InsertTab => self.insert_text("\t"),
+            Command::MoveLinesUp => self.move_lines(false),
+            Command::MoveLinesDown => self.move_lines(true),
+            Command::DuplicateLines => self.duplicate_lines(),
+            Command::DeleteLines => self.delete_lines(),
+            Command::JoinLines => self.join_lines(),
+            Command::ToggleComment => self.toggle_comment(),
+            Command::Undo => self.undo(),
+            Command::Redo => self.redo(),
+            Command::SetMark => self.set_mark(),
+            Command::KillLine => self.kill_line(previous == Some(Command::KillLine)),
+            Command::KillRegion => self.kill_region(true),
+            Command::CopyRegion => self.kill_region(false),
+            Command::Yank => self.yank(),
+            Command::YankPop => self.yank_pop(matches!(previous, Some(Command::Yank | Command::YankPop))),
+            Command::SearchForward => self.start_search(true),
+            Command::SearchBackward => self.start_search(false),
+            Command::Cancel => {
+                self.selection = None;
+                self.pending_keys.clear();
+            }
+            Command::GotoLine => self.prompt = Some(Prompt::new(PromptKind::GotoLine)),
+            Command::JumpBack => self.jump_back(),
+            Command::JumpForward => self.jump_forward(),
+            Command::SetNamedMark => self.prompt = Some(Prompt::new(PromptKind::SetMark)),
+            Command::GotoMark => self.prompt = Some(Prompt::new(PromptKind::GotoMark)),
+            Command::Save => self.save(),
+            Command::ReloadConfig => self.reload_config(),
+            Command::DescribeKey => {
+                self.describing_key = true;
+                self.message = Some("Describe key:".to_string());
+            }
+            Command::OpenPalette => self.palette = Some(Palette::new()),
+            Command::ToggleWrap => {
+                self.config.settings.wrap = match self.config.settings.wrap {
+                    WrapMode::Soft => WrapMode::None,
+                    WrapMode::None => WrapMode::Soft,
+                };
+                self.rewrap();
+            }
+            Command::ToggleLineNumbers => {
+                self.config.settings.line_numbers = !self.config.settings.line_numbers;
+                self.rewrap();
+            }
+            Command::LineEndingsLf => self.set_line_ending(LineEnding::Lf),
+            Command::LineEndingsCrlf => self.set_line_ending(LineEnding::CrLf),
+        }
+    }
+
+    fn save(&mut self) {
+        let name = self.buffer.file_name();
+        self.message = Some(match self.buffer.save() {
+            Ok(()) => format!("Saved {}", name),
+            Err(e) => format!("Cannot save {}: {}", name, e),
+        });
+    }
+
+    fn set_line_ending(&mut self, line_ending: LineEnding) {
+        self.buffer.set_line_ending(line_ending);
+        self.message = Some(format!("Line endings set to {}", line_ending.name()));
+    }
+
+    pub fn draw(&mut self, terminal: &mut impl Terminal) -> io::Result<()> {
+        let (line, col) = self.cursor.logical_position();
+        let position = format!("{}  {}  Ln {}, Col {}", self.language.name, self.buffer.line_ending().name(), line + 1, col + 1);
+        let mut file_label = format!("{}{}", self.buffer.file_name(), if self.buffer.is_modified() { " [+]" } else { "" });
+        if let Some(vi) = &self.vi {
+            file_label = format!("{}  {}", vi.mode.name(), file_label);
+        }
+        let search_prompt = self.search.as_ref().map(Search::prompt);
+        let status = StatusLine {
+            left: search_prompt.as_deref().or(self.message.as_deref()).unwrap_or(&file_label),
+            right: &position,
+        };
+
+        let palette_items: Vec<(String, String)>;
+        let overlay_prompt: String;
+        let overlay = match (&self.palette, &self.prompt) {
+            (Some(palette), _) => {
+                palette_items = palette.matches(&self.keymap).into_iter()
+                    .map(|(command, keys)| (command.name().to_string(), keys))
+                    .collect();
+                overlay_prompt = format!("> {}", palette.query());
+                Some(Overlay { prompt: &overlay_prompt, items: &palette_items, selected: palette.selected() })
+            }
+            (None, Some(prompt)) => {
+                overlay_prompt = prompt.text();
+                Some(Overlay { prompt: &overlay_prompt, items: &prompt.items, selected: prompt.selected })
+            }
+            (None, None) => None,
+        };
+        let view = View {
+            cursor: &self.cursor,
+            settings: &self.config.settings,
+            status,
+            overlay,
+            selection: match &self.search {
+                Some(search) => search.found,
+                None => self.selection_range().map(|(start, end, _)| (start, end)),
+            },
+            brackets: self.cursor.matching_bracket().map(|(here, partner)| {
+                let (line, col) = self.cursor.logical_position_of(here.0, here.1);
+                let (partner_line, partner_col) = self.cursor.logical_position_of(partner.0, partner.1);
+                (Position::new(line, col), Position::new(partner_line, partner_col))
+            }),
+        };
+        self.screen.draw_screen(terminal, &view)
+    }
+}
+
+fn build_view(buffer: &Buffer, settings: &Settings, language: &'static Language, cols: u16, rows: u16) -> CursorPos {
+    let lines = (0..buffer.line_count()).map(|line| buffer.line(line));
+    let wrap_result = wrap_lines(lines, wrap_width(buffer, settings, cols));
+    let tokens = TokenCache::new(&wrap_result, &word_chars(language, settings), language.prose);
+    // The last row is taken by the status line
+    let visible_rows = (rows as usize).saturating_sub(1);
+    let mut cursor = CursorPos::new(wrap_result, tokens, visible_rows);
+    cursor.set_scroll_margin(settings.scroll_margin);
+    cursor.set_brackets(language.brackets);
+    cursor
+}
+
+fn wrap_width(buffer: &Buffer, settings: &Settings, cols: u16) -> usize {
+    match settings.wrap {
+        WrapMode::Soft => (cols as usize).saturating_sub(gutter_width(buffer.line_count(), settings)),
+        WrapMode::None => usize::MAX,
+    }
+}
+
+/// Characters besides letters and digits that make up words: the configured ones, or the language's.
+fn word_chars(language: &Language, settings: &Settings) -> String {
+    settings.language(language.name)
+        .and_then(|language| language.word_chars.clone())
+        .unwrap_or_else(|| language.word_chars.to_string())
+}
+
+fn build_keymap(settings: &Settings) -> Keymap {
+    let mut keymap = match settings.keymap {
+        KeymapPreset::Emacs => Keymap::emacs(),
+        KeymapPreset::Vi => Keymap::vi(),
+        KeymapPreset::Default => Keymap::default(),
+    };
+    for (sequence, command) in &settings.key_bindings {
+        keymap.bind(sequence.clone(), *command);
+    }
+    keymap
+}
+
+fn build_vi(settings: &Settings) -> Option<Vi> {
+    (settings.keymap == KeymapPreset::Vi).then(Vi::default)
+}
+
+/// First configuration error, with a count of the others, for the status line.
+fn config_message(config: &Config) -> Option<String> {
+    let first = config.errors.first()?;
+    match config.errors.len() {
+        1 => Some(first.to_string()),
+        n => Some(format!("{} (+{} more)", first, n - 1)),
+    }
+}
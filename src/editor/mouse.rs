@@ -0,0 +1,91 @@
+use std::time::{Duration, Instant};
+use crossterm::event::{MouseButton, MouseEvent, MouseEventKind};
+use crate::buffer::Position;
+use super::{Editor, SelectionKind};
+
+/// Clicks on the same cell closer together than this count as double and triple clicks.
+const MULTI_CLICK_INTERVAL: Duration = Duration::from_millis(400);
+const WHEEL_ROWS: isize = 3;
+
+/// The last left click, to recognise double and triple clicks.
+pub(super) struct Click {
+    at: Instant,
+    col: u16,
+    row: u16,
+    count: u8,
+}
+
+impl Editor {
+    pub fn handle_mouse(&mut self, event: MouseEvent) {
+        if self.palette.is_some() || self.prompt.is_some() {
+            return;
+        }
+        match event.kind {
+            MouseEventKind::ScrollUp => self.cursor.scroll_by(-WHEEL_ROWS),
+            MouseEventKind::ScrollDown => self.cursor.scroll_by(WHEEL_ROWS),
+            MouseEventKind::Down(MouseButton::Left) => self.click(event.column, event.row),
+            MouseEventKind::Drag(MouseButton::Left) => self.drag(event.column, event.row),
+            _ => {}
+        }
+    }
+
+    fn click(&mut self, col: u16, row: u16) {
+        let Some((y, x)) = self.screen.text_position(&self.cursor, &self.config.settings, col, row) else {
+            return;
+        };
+        if let Some(search) = self.search.take() {
+            self.finish_search(search);
+        }
+        self.typing = false;
+        self.last_command = None;
+
+        let count = match &self.last_click {
+            Some(last) if (last.col, last.row) == (col, row) && last.at.elapsed() < MULTI_CLICK_INTERVAL => last.count % 3 + 1,
+            _ => 1,
+        };
+        self.last_click = Some(Click { at: Instant::now(), col, row, count });
+
+        self.cursor.place(y, x);
+        self.selection = None;
+        self.drag_anchor = None;
+        match count {
+            1 => self.drag_anchor = Some(self.position()),
+            2 => self.select_token(),
+            _ => self.select_line(),
+        }
+        self.finish_key();
+    }
+
+    fn drag(&mut self, col: u16, row: u16) {
+        let Some(anchor) = self.drag_anchor else {
+            return;
+        };
+        let Some((y, x)) = self.screen.text_position(&self.cursor, &self.config.settings, col, row) else {
+            return;
+        };
+        self.cursor.place(y, x);
+        self.selection = Some((anchor, SelectionKind::Exclusive));
+        self.cursor.ensure_visible();
+    }
+
+    fn select_token(&mut self) {
+        let Some(token) = self.cursor.get_token_on_cursor() else {
+            return;
+        };
+        let (Some(line), Some(start), Some(end)) = (token.line, token.line_col_start, token.line_col_end) else {
+            return;
+        };
+        self.selection = Some((Position::new(line, start), SelectionKind::Exclusive));
+        self.set_position(Position::new(line, end + 1));
+    }
+
+    fn select_line(&mut self) {
+        let line = self.position().line;
+        let end = match line + 1 < self.buffer.line_count() {
+            true => Position::new(line + 1, 0),
+            false => Position::new(line, self.buffer.line_len(line)),
+        };
+        self.selection = Some((Position::new(line, 0), SelectionKind::Exclusive));
+        self.set_position(end);
+    }
+}
@@ -0,0 +1,93 @@
+use crossterm::event::{KeyCode, KeyModifiers};
+use super::Editor;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub(super) enum PromptKind {
+    GotoLine,
+    SetMark,
+    GotoMark,
+    /// A swap file with unsaved changes was found; `newer` when it was written after the file was saved.
+    Recover { newer: bool },
+    /// Another running instance, with process id `pid`, is editing the file.
+    SwapInUse { pid: u32 },
+}
+
+impl PromptKind {
+    /// Whether the first typed character answers the prompt, without waiting for Enter.
+    fn takes_one_char(self) -> bool {
+        !matches!(self, PromptKind::GotoLine)
+    }
+
+    /// Questions about the swap file must be answered before editing, so they cannot be cancelled.
+    pub(super) fn is_swap_question(self) -> bool {
+        matches!(self, PromptKind::Recover { .. } | PromptKind::SwapInUse { .. })
+    }
+}
+
+/// A one-line question drawn over the text, such as the go-to-line prompt.
+pub(super) struct Prompt {
+    pub(super) kind: PromptKind,
+    pub(super) input: String,
+    /// Lines listed below the question, such as the changes in a swap file, with a right-aligned detail.
+    pub(super) items: Vec<(String, String)>,
+    /// Highlighted item, moved with the arrow keys to scroll through the list.
+    pub(super) selected: usize,
+}
+
+impl Prompt {
+    pub(super) fn new(kind: PromptKind) -> Self {
+        Self { kind, input: String::new(), items: Vec::new(), selected: 0 }
+    }
+
+    /// The question followed by what was typed so far.
+    pub(super) fn text(&self) -> String {
+        let label = match self.kind {
+            PromptKind::GotoLine => "Go to line (line, line:col, +N, -N, N%):".to_string(),
+            PromptKind::SetMark => "Set mark:".to_string(),
+            PromptKind::GotoMark => "Go to mark:".to_string(),
+            PromptKind::Recover { newer: true } => "Swap file found: r recover, d diff, x discard, q quit".to_string(),
+            PromptKind::Recover { newer: false } => "Outdated swap file: r recover, d diff, x discard, q quit".to_string(),
+            PromptKind::SwapInUse { pid } => format!("Open in FumaEdit process {}: e edit anyway, q quit", pid),
+        };
+        format!("{} {}", label, self.input)
+    }
+}
+
+impl Editor {
+    pub(super) fn handle_prompt_key(&mut self, code: KeyCode, modifiers: KeyModifiers) {
+        let Some(prompt) = &mut self.prompt else {
+            return;
+        };
+        let cancel = code == KeyCode::Esc || (code == KeyCode::Char('g') && modifiers.contains(KeyModifiers::CONTROL));
+        match code {
+            // Questions about the swap file have to be answered
+            _ if cancel && prompt.kind.is_swap_question() => {}
+            _ if cancel => self.prompt = None,
+            KeyCode::Up => prompt.selected = prompt.selected.saturating_sub(1),
+            KeyCode::Down => prompt.selected = (prompt.selected + 1).min(prompt.items.len().saturating_sub(1)),
+            KeyCode::Backspace => _ = prompt.input.pop(),
+            KeyCode::Char(c) if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => {
+                prompt.input.push(c);
+                if prompt.kind.takes_one_char() {
+                    self.answer_prompt();
+                }
+            }
+            KeyCode::Enter => self.answer_prompt(),
+            _ => {}
+        }
+    }
+
+    fn answer_prompt(&mut self) {
+        let Some(prompt) = self.prompt.take() else {
+            return;
+        };
+        match (prompt.kind, prompt.input.chars().next()) {
+            (PromptKind::GotoLine, _) => self.goto(&prompt.input),
+            (PromptKind::SetMark, Some(name)) => self.set_named_mark(name),
+            (PromptKind::GotoMark, Some(name)) => self.goto_mark(name),
+            (PromptKind::Recover { .. }, _) => self.answer_recovery(prompt),
+            (PromptKind::SwapInUse { .. }, _) => self.answer_swap_in_use(prompt),
+            (_, None) => {}
+        }
+    }
+}
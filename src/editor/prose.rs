@@ -0,0 +1,40 @@
+use crate::buffer::Position;
+use crate::utils::direction::Direction;
+use crate::utils::prose::{reflow, sentence_stop};
+use super::{Editor, SelectionKind};
+
+impl Editor {
+    pub(super) fn move_sentence(&mut self, direction: Direction) {
+        let text: Vec<char> = self.buffer.text().chars().collect();
+        let offset = sentence_stop(&text, self.buffer.offset_of(self.position()), direction);
+        self.set_position(self.buffer.position_of(offset));
+    }
+
+    /// Selects the lines of the paragraph at the cursor, up to the start of the line after it.
+    pub(super) fn select_paragraph(&mut self) {
+        let Some((first, last)) = self.cursor.paragraph_lines() else {
+            return;
+        };
+        let end = match last + 1 < self.buffer.line_count() {
+            true => Position::new(last + 1, 0),
+            false => Position::new(last, self.buffer.line_len(last)),
+        };
+        self.selection = Some((Position::new(first, 0), SelectionKind::Exclusive));
+        self.set_position(end);
+    }
+
+    /// Refills the paragraph at the cursor to `text_width` columns and moves to its end.
+    pub(super) fn reflow_paragraph(&mut self) {
+        let Some((first, last)) = self.cursor.paragraph_lines() else {
+            return;
+        };
+        self.begin_edit();
+        self.selection = None;
+        let lines = self.lines(first, last);
+        let lines: Vec<&str> = lines.iter().map(String::as_str).collect();
+        let filled = reflow(&lines, self.config.settings.text_width);
+        let end = Position::new(first + filled.len() - 1, filled.last().map_or(0, |line| line.chars().count()));
+        self.buffer.replace_lines(first, last, filled);
+        self.rebuild_view(end);
+    }
+}
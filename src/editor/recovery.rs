@@ -0,0 +1,146 @@
+use std::{fs, process};
+use crate::buffer::Position;
+use crate::swap::{emergency_path, is_running, SwapFile};
+use crate::utils::diff::{diff_lines, Change};
+use super::Editor;
+use super::prompt::{Prompt, PromptKind};
+
+impl Editor {
+    /// Starts keeping unsaved changes in `swap`. If a swap file is already there, asks whether to recover
+    /// it, or whether to edit anyway when another running instance wrote it; it is left alone until then.
+    pub fn open_swap(&mut self, swap: SwapFile) {
+        let found = swap.read();
+        self.swap = Some(swap);
+        let Some(found) = found else {
+            self.update_swap();
+            return;
+        };
+        if found.pid != process::id() && is_running(found.pid) {
+            self.prompt = Some(Prompt::new(PromptKind::SwapInUse { pid: found.pid }));
+        } else {
+            self.offer_recovery();
+        }
+    }
+
+    /// Writes the text to the swap file if it changed since the last write.
+    pub fn update_swap(&mut self) {
+        if self.prompt.as_ref().is_some_and(|prompt| prompt.kind.is_swap_question()) {
+            return;
+        }
+        let Some(swap) = &mut self.swap else {
+            return;
+        };
+        if let Err(e) = swap.write(&self.buffer.text(), self.buffer.version()) {
+            self.message = Some(format!("Cannot write swap file {}: {}", swap.path().display(), e));
+            // Keep the message from coming back on every write
+            self.swap = None;
+        }
+    }
+
+    /// Deletes the swap file when the editor exits normally.
+    pub fn close_swap(&mut self) {
+        if let Some(swap) = &mut self.swap {
+            swap.remove();
+        }
+    }
+
+    /// Writes the unsaved text next to the file when the editor has to exit without asking, such as after
+    /// a crash or when the terminal goes away. Returns a note on where it went, or `None` when nothing
+    /// was unsaved.
+    pub fn write_emergency_file(&mut self) -> Option<String> {
+        if !self.buffer.is_modified() {
+            return None;
+        }
+        self.update_swap();
+        let path = emergency_path(self.buffer.path());
+        Some(match fs::write(&path, self.buffer.to_file_text()) {
+            Ok(()) => format!("Unsaved changes to {} were written to {}", self.buffer.file_name(), path.display()),
+            Err(e) => format!("Cannot write unsaved changes to {}: {}", path.display(), e),
+        })
+    }
+
+    /// Asks what to do with a swap file whose text differs from the file's, or takes it over otherwise.
+    fn offer_recovery(&mut self) {
+        let Some(found) = self.swap.as_ref().and_then(SwapFile::read) else {
+            self.update_swap();
+            return;
+        };
+        if found.text == self.buffer.text() {
+            self.update_swap();
+            return;
+        }
+        let saved = fs::metadata(self.buffer.path()).and_then(|m| m.modified()).ok();
+        let newer = match (found.modified, saved) {
+            (Some(swap), Some(saved)) => swap >= saved,
+            _ => true,
+        };
+        self.prompt = Some(Prompt::new(PromptKind::Recover { newer }));
+    }
+
+    pub(super) fn answer_recovery(&mut self, mut prompt: Prompt) {
+        match prompt.input.chars().next() {
+            Some('r') => self.recover(),
+            Some('d') => {
+                prompt.items = self.swap_changes();
+                prompt.selected = 0;
+                prompt.input.clear();
+                self.prompt = Some(prompt);
+            }
+            Some('x') => {
+                self.message = Some("Discarded the swap file".to_string());
+                self.update_swap();
+            }
+            // The swap file is kept for the next time the file is opened
+            Some('q') => self.quit = true,
+            _ => {
+                prompt.input.clear();
+                self.prompt = Some(prompt);
+            }
+        }
+    }
+
+    pub(super) fn answer_swap_in_use(&mut self, mut prompt: Prompt) {
+        match prompt.input.chars().next() {
+            Some('e') => self.offer_recovery(),
+            Some('q') => {
+                // The swap file belongs to the other instance, so it is not removed on the way out
+                self.swap = None;
+                self.quit = true;
+            }
+            _ => {
+                prompt.input.clear();
+                self.prompt = Some(prompt);
+            }
+        }
+    }
+
+    /// Replaces the text with the swap file's, as one step that can be undone.
+    fn recover(&mut self) {
+        let Some(found) = self.swap.as_ref().and_then(SwapFile::read) else {
+            self.message = Some("Cannot read the swap file".to_string());
+            return;
+        };
+        self.begin_edit();
+        let last = self.buffer.line_count() - 1;
+        self.buffer.replace_lines(0, last, found.text.split('\n').map(str::to_string).collect());
+        self.rebuild_view(Position::default());
+        self.message = Some("Recovered unsaved changes; save to keep them".to_string());
+        self.update_swap();
+    }
+
+    /// Lines the swap file removes from and adds to the text, with their line numbers.
+    fn swap_changes(&self) -> Vec<(String, String)> {
+        let Some(found) = self.swap.as_ref().and_then(SwapFile::read) else {
+            return Vec::new();
+        };
+        let text = self.buffer.text();
+        let old: Vec<&str> = text.split('\n').collect();
+        let new: Vec<&str> = found.text.split('\n').collect();
+        diff_lines(&old, &new).into_iter()
+            .map(|change| match change {
+                Change::Removed(line, text) => (format!("- {}", text), format!("{}", line + 1)),
+                Change::Added(line, text) => (format!("+ {}", text), format!("{}", line + 1)),
+            })
+            .collect()
+    }
+}
@@ -0,0 +1,295 @@
+use crate::buffer::Position;
+use crate::utils::direction::Direction;
+use crate::vi::{bracket_object, word_object, InsertAt, Motion, Operator, Target, TextObject, ViCommand, ViMode};
+use super::editing::indentation;
+use super::{Editor, Register, SelectionKind};
+
+impl Editor {
+    pub(super) fn execute_vi(&mut self, command: ViCommand) {
+        if !matches!(command, ViCommand::Escape) {
+            self.typing = false;
+        }
+        match command {
+            ViCommand::Move(motion, count) if motion.is_jump() => {
+                self.jump(|editor| (0..count).for_each(|_| editor.apply_motion(motion)));
+            }
+            ViCommand::Move(motion, count) => {
+                for _ in 0..count {
+                    self.apply_motion(motion);
+                }
+            }
+            ViCommand::Operate(operator, target, count) => {
+                if let Some((start, end, linewise)) = self.target_range(operator, target, count) {
+                    self.operate(operator, start, end, linewise);
+                }
+            }
+            ViCommand::OperateSelection(operator) => {
+                if let Some((start, end, linewise)) = self.selection_range() {
+                    self.selection = None;
+                    self.set_mode(ViMode::Normal);
+                    self.operate(operator, start, end, linewise);
+                }
+            }
+            ViCommand::SelectObject(object) => {
+                if let Some((start, end)) = self.object_range(object)
+                    && end > start {
+                    self.selection = Some((start, SelectionKind::Inclusive));
+                    self.set_mode(ViMode::Visual);
+                    let last = self.buffer.position_of(self.buffer.offset_of(end) - 1);
+                    self.set_position(last);
+                }
+            }
+            ViCommand::Insert(at) => self.start_insert(at),
+            ViCommand::DeleteChar(count) => {
+                let start = self.position();
+                let end = Position::new(start.line, (start.col + count).min(self.buffer.line_len(start.line)));
+                if end > start {
+                    self.operate(Operator::Delete, start, end, false);
+                }
+            }
+            ViCommand::Put { before, count } => self.put(before, count),
+            ViCommand::Undo(count) => (0..count).for_each(|_| self.undo()),
+            ViCommand::Redo(count) => (0..count).for_each(|_| self.redo()),
+            ViCommand::Visual { line } => {
+                let (kind, mode) = match line {
+                    true => (SelectionKind::Lines, ViMode::VisualLine),
+                    false => (SelectionKind::Inclusive, ViMode::Visual),
+                };
+                match self.selection {
+                    Some(_) if self.mode() == Some(mode) => {
+                        self.selection = None;
+                        self.set_mode(ViMode::Normal);
+                    }
+                    Some((anchor, _)) => {
+                        self.selection = Some((anchor, kind));
+                        self.set_mode(mode);
+                    }
+                    None => {
+                        self.selection = Some((self.position(), kind));
+                        self.set_mode(mode);
+                    }
+                }
+            }
+            ViCommand::Center => self.cursor.center_on_cursor(),
+            ViCommand::SetMark(name) => self.set_named_mark(name),
+            ViCommand::GotoMark { name, linewise } => {
+                if let Some(position) = self.named_mark(name) {
+                    let target = if linewise { self.first_non_blank(position.line) } else { position };
+                    self.jump(|editor| editor.set_position(target));
+                }
+            }
+            ViCommand::Escape => {
+                if self.mode() == Some(ViMode::Insert) {
+                    self.typing = false;
+                    self.apply_motion(Motion::Left);
+                }
+                self.selection = None;
+                self.set_mode(ViMode::Normal);
+            }
+        }
+    }
+
+    fn mode(&self) -> Option<ViMode> {
+        self.vi.as_ref().map(|vi| vi.mode)
+    }
+
+    fn set_mode(&mut self, mode: ViMode) {
+        if let Some(vi) = &mut self.vi {
+            vi.mode = mode;
+        }
+    }
+
+    /// Outside insert mode the cursor sits on a character, never after the last one.
+    pub(super) fn clamp_to_last_char(&mut self) {
+        let position = self.position();
+        let len = self.buffer.line_len(position.line);
+        if len > 0 && position.col >= len {
+            self.set_position(Position::new(position.line, len - 1));
+        }
+    }
+
+    fn first_non_blank(&self, line: usize) -> Position {
+        let col = self.buffer.line(line).chars().take_while(|c| c.is_whitespace()).count();
+        Position::new(line, col)
+    }
+
+    fn apply_motion(&mut self, motion: Motion) {
+        let position = self.position();
+        let last_line = self.buffer.line_count() - 1;
+        match motion {
+            Motion::Left => self.set_position(Position::new(position.line, position.col.saturating_sub(1))),
+            Motion::Right => {
+                if position.col + 1 < self.buffer.line_len(position.line) {
+                    self.set_position(Position::new(position.line, position.col + 1));
+                }
+            }
+            Motion::Up => self.cursor.move_up(),
+            Motion::Down => self.cursor.move_down(),
+            Motion::WordStart => self.cursor.move_word_start_forward(),
+            Motion::WordBackward => self.cursor.move_word_start_backward(),
+            Motion::WordEnd => self.cursor.move_word_end_forward(),
+            Motion::LineStart => self.set_position(Position::new(position.line, 0)),
+            Motion::FirstNonBlank => self.set_position(self.first_non_blank(position.line)),
+            Motion::LineEnd => {
+                let len = self.buffer.line_len(position.line);
+                self.set_position(Position::new(position.line, len.saturating_sub(1)));
+            }
+            Motion::FileStart => self.set_position(self.first_non_blank(0)),
+            Motion::FileEnd => self.set_position(self.first_non_blank(last_line)),
+            Motion::GotoLine(line) => self.set_position(self.first_non_blank(line.saturating_sub(1).min(last_line))),
+            Motion::MatchingBracket => _ = self.cursor.jump_to_matching_bracket(),
+            Motion::SentenceBackward => self.move_sentence(Direction::Left),
+            Motion::SentenceForward => self.move_sentence(Direction::Right),
+            Motion::ParagraphBackward => self.cursor.move_paragraph(Direction::Left),
+            Motion::ParagraphForward => self.cursor.move_paragraph(Direction::Right),
+        }
+    }
+
+    /// The text an operator works on, as a `[start, end)` range and whether it covers whole lines.
+    fn target_range(&mut self, operator: Operator, target: Target, count: usize) -> Option<(Position, Position, bool)> {
+        let start = self.position();
+        let line_range = |editor: &Self, first: usize, last: usize| {
+            (Position::new(first, 0), Position::new(last, editor.buffer.line_len(last)), true)
+        };
+        let motion = match target {
+            Target::Lines => {
+                let last = (start.line + count - 1).min(self.buffer.line_count() - 1);
+                return Some(line_range(self, start.line, last));
+            }
+            Target::Object(object) => return self.object_range(object).map(|(start, end)| (start, end, false)),
+            Target::Motion(motion) => motion,
+        };
+
+        let len = self.buffer.line_len(start.line);
+        match motion {
+            Motion::Left => return Some((Position::new(start.line, start.col.saturating_sub(count)), start, false)),
+            Motion::Right => return Some((start, Position::new(start.line, (start.col + count).min(len)), false)),
+            // Moving the cursor, j and k go by wrapped row, but `dj` and `dk` take whole logical lines
+            Motion::Up => return Some(line_range(self, start.line.saturating_sub(count), start.line)),
+            Motion::Down => return Some(line_range(self, start.line, (start.line + count).min(self.buffer.line_count() - 1))),
+            _ => {}
+        }
+
+        // `cw` on a word changes to its end instead of up to the next word, like vi
+        let on_word = self.buffer.line(start.line).chars().nth(start.col).is_some_and(|c| !c.is_whitespace());
+        let (motion, inclusive) = match motion {
+            Motion::WordStart if operator == Operator::Change && on_word => (Motion::WordEnd, true),
+            _ => (motion, motion.is_inclusive()),
+        };
+        for _ in 0..count {
+            self.apply_motion(motion);
+        }
+        let end = self.position();
+        self.set_position(start);
+
+        let (from, to) = (start.min(end), start.max(end));
+        if motion.is_linewise() {
+            return Some(line_range(self, from.line, to.line));
+        }
+        if inclusive {
+            return Some((from, self.buffer.position_of(self.buffer.offset_of(to) + 1), false));
+        }
+        // A word motion that leaves the line stops at its end
+        let to = match motion {
+            Motion::WordStart if to.line > from.line => Position::new(from.line, self.buffer.line_len(from.line)),
+            _ => to,
+        };
+        Some((from, to, false))
+    }
+
+    fn object_range(&self, object: TextObject) -> Option<(Position, Position)> {
+        let position = self.position();
+        match object {
+            TextObject::Word { around } => {
+                let (start, end) = word_object(self.buffer.line(position.line), position.col, around, &self.word_chars())?;
+                Some((Position::new(position.line, start), Position::new(position.line, end)))
+            }
+            TextObject::Bracket { open, close, around } => {
+                let chars: Vec<char> = self.buffer.text().chars().collect();
+                let (start, end) = bracket_object(&chars, self.buffer.offset_of(position), open, close, around)?;
+                Some((self.buffer.position_of(start), self.buffer.position_of(end)))
+            }
+        }
+    }
+
+    fn operate(&mut self, operator: Operator, start: Position, end: Position, linewise: bool) {
+        self.register = Register { text: self.buffer.slice(start, end), linewise };
+        match operator {
+            Operator::Yank => self.set_position(start),
+            Operator::Delete if linewise => {
+                self.begin_edit();
+                self.buffer.replace_lines(start.line, end.line, Vec::new());
+                let line = start.line.min(self.buffer.line_count() - 1);
+                self.rebuild_view(self.first_non_blank(line));
+            }
+            Operator::Change if linewise => {
+                self.begin_edit();
+                self.buffer.replace_lines(start.line, end.line, vec![String::new()]);
+                self.rebuild_view(Position::new(start.line, 0));
+            }
+            Operator::Delete | Operator::Change => {
+                self.begin_edit();
+                self.buffer.delete(start, end);
+                self.rebuild_view(start);
+            }
+        }
+        if operator == Operator::Change {
+            self.set_mode(ViMode::Insert);
+            // What is typed next belongs to the same undo step as the deletion
+            self.typing = true;
+        }
+    }
+
+    fn start_insert(&mut self, at: InsertAt) {
+        let position = self.position();
+        let len = self.buffer.line_len(position.line);
+        match at {
+            InsertAt::Before => {}
+            InsertAt::After => self.set_position(Position::new(position.line, (position.col + 1).min(len))),
+            InsertAt::LineStart => self.set_position(self.first_non_blank(position.line)),
+            InsertAt::LineEnd => self.set_position(Position::new(position.line, len)),
+            InsertAt::LineBelow => {
+                self.set_position(Position::new(position.line, len));
+                self.newline();
+                self.typing = true;
+            }
+            InsertAt::LineAbove => {
+                self.begin_edit();
+                let indent = indentation(self.buffer.line(position.line)).to_string();
+                let end = self.buffer.insert(Position::new(position.line, 0), &indent);
+                self.buffer.insert(end, "\n");
+                self.rebuild_view(end);
+                self.typing = true;
+            }
+        }
+        self.set_mode(ViMode::Insert);
+    }
+
+    fn put(&mut self, before: bool, count: usize) {
+        if self.register.text.is_empty() && !self.register.linewise {
+            return;
+        }
+        self.begin_edit();
+        let position = self.position();
+        let text = vec![self.register.text.as_str(); count];
+        if self.register.linewise {
+            let text = text.join("\n");
+            let line = if before {
+                self.buffer.insert(Position::new(position.line, 0), &(text + "\n"));
+                position.line
+            } else {
+                self.buffer.insert(Position::new(position.line, self.buffer.line_len(position.line)), &("\n".to_string() + &text));
+                position.line + 1
+            };
+            self.rebuild_view(self.first_non_blank(line));
+        } else {
+            let at = match before {
+                true => position,
+                false => Position::new(position.line, (position.col + 1).min(self.buffer.line_len(position.line))),
+            };
+            let end = self.buffer.insert(at, &text.concat());
+            let last = self.buffer.position_of(self.buffer.offset_of(end).saturating_sub(1));
+            self.rebuild_view(last);
+        }
+    }
+}
@@ -0,0 +1,543 @@
+use std::collections::HashMap;
+use std::fmt;
+use crossterm::event::{KeyCode, KeyModifiers};
+
+/// Every action a key can be bound to. Names are the ones used in the `[keys]` config section.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub enum Command {
+    Quit,
+    MoveUp,
+    MoveDown,
+    MoveLeft,
+    MoveRight,
+    TokenLeft,
+    TokenRight,
+    SubwordLeft,
+    SubwordRight,
+    SentenceBackward,
+    SentenceForward,
+    ParagraphBackward,
+    ParagraphForward,
+    SelectParagraph,
+    ReflowParagraph,
+    LineStart,
+    LineEnd,
+    PageUp,
+    PageDown,
+    FileStart,
+    FileEnd,
+    ScrollUp,
+    ScrollDown,
+    CenterCursor,
+    MatchingBracket,
+    Newline,
+    DeleteBackward,
+    DeleteForward,
+    InsertTab,
+    MoveLinesUp,
+    MoveLinesDown,
+    DuplicateLines,
+    DeleteLines,
+    JoinLines,
+    ToggleComment,
+    Undo,
+    Redo,
+    SetMark,
+    KillLine,
+    KillRegion,
+    CopyRegion,
+    Yank,
+    YankPop,
+    SearchForward,
+    SearchBackward,
+    Cancel,
+    GotoLine,
+    JumpBack,
+    JumpForward,
+    SetNamedMark,
+    GotoMark,
+    Save,
+    Suspend,
+    ReloadConfig,
+    DescribeKey,
+    OpenPalette,
+    ToggleWrap,
+    ToggleLineNumbers,
+    LineEndingsLf,
+    LineEndingsCrlf,
+}
+
+impl Command {
+    pub const ALL: &'static [Command] = &[
+        Command::Quit,
+        Command::MoveUp,
+        Command::MoveDown,
+        Command::MoveLeft,
+        Command::MoveRight,
+        Command::TokenLeft,
+        Command::TokenRight,
+        Command::SubwordLeft,
+        Command::SubwordRight,
+        Command::SentenceBackward,
+        Command::SentenceForward,
+        Command::ParagraphBackward,
+        Command::ParagraphForward,
+        Command::SelectParagraph,
+        Command::ReflowParagraph,
+        Command::LineStart,
+        Command::LineEnd,
+        Command::PageUp,
+        Command::PageDown,
+        Command::FileStart,
+        Command::FileEnd,
+        Command::ScrollUp,
+        Command::ScrollDown,
+        Command::CenterCursor,
+        Command::MatchingBracket,
+        Command::Newline,
+        Command::DeleteBackward,
+        Command::DeleteForward,
+        Command::InsertTab,
+        Command::MoveLinesUp,
+        Command::MoveLinesDown,
+        Command::DuplicateLines,
+        Command::DeleteLines,
+        Command::JoinLines,
+        Command::ToggleComment,
+        Command::Undo,
+        Command::Redo,
+        Command::SetMark,
+        Command::KillLine,
+        Command::KillRegion,
+        Command::CopyRegion,
+        Command::Yank,
+        Command::YankPop,
+        Command::SearchForward,
+        Command::SearchBackward,
+        Command::Cancel,
+        Command::GotoLine,
+        Command::JumpBack,
+        Command::JumpForward,
+        Command::SetNamedMark,
+        Command::GotoMark,
+        Command::Save,
+        Command::Suspend,
+        Command::ReloadConfig,
+        Command::DescribeKey,
+        Command::OpenPalette,
+        Command::ToggleWrap,
+        Command::ToggleLineNumbers,
+        Command::LineEndingsLf,
+        Command::LineEndingsCrlf,
+    ];
+
+    pub fn name(self) -> &'static str {
+        match self {
+            Command::Quit => "quit",
+            Command::MoveUp => "move-up",
+            Command::MoveDown => "move-down",
+            Command::MoveLeft => "move-left",
+            Command::MoveRight => "move-right",
+            Command::TokenLeft => "token-left",
+            Command::TokenRight => "token-right",
+            Command::SubwordLeft => "subword-left",
+            Command::SubwordRight => "subword-right",
+            Command::SentenceBackward => "sentence-backward",
+            Command::SentenceForward => "sentence-forward",
+            Command::ParagraphBackward => "paragraph-backward",
+            Command::ParagraphForward => "paragraph-forward",
+            Command::SelectParagraph => "select-paragraph",
+            Command::ReflowParagraph => "reflow-paragraph",
+            Command::LineStart => "line-start",
+            Command::LineEnd => "line-end",
+            Command::PageUp => "page-up",
+            Command::PageDown => "page-down",
+            Command::FileStart => "file-start",
+            Command::FileEnd => "file-end",
+            Command::ScrollUp => "scroll-up",
+            Command::ScrollDown => "scroll-down",
+            Command::CenterCursor => "center-cursor",
+            Command::MatchingBracket => "matching-bracket",
+            Command::Newline => "newline",
+            Command::DeleteBackward => "delete-backward",
+            Command::DeleteForward => "delete-forward",
+            Command::InsertTab => "insert-tab",
+            Command::MoveLinesUp => "move-lines-up",
+            Command::MoveLinesDown => "move-lines-down",
+            Command::DuplicateLines => "duplicate-lines",
+            Command::DeleteLines => "delete-lines",
+            Command::JoinLines => "join-lines",
+            Command::ToggleComment => "toggle-comment",
+            Command::Undo => "undo",
+            Command::Redo => "redo",
+            Command::SetMark => "set-mark",
+            Command::KillLine => "kill-line",
+            Command::KillRegion => "kill-region",
+            Command::CopyRegion => "copy-region",
+            Command::Yank => "yank",
+            Command::YankPop => "yank-pop",
+            Command::SearchForward => "search-forward",
+            Command::SearchBackward => "search-backward",
+            Command::Cancel => "cancel",
+            Command::GotoLine => "goto-line",
+            Command::JumpBack => "jump-back",
+            Command::JumpForward => "jump-forward",
+            Command::SetNamedMark => "set-named-mark",
+            Command::GotoMark => "goto-mark",
+            Command::Save => "save",
+            Command::Suspend => "suspend",
+            Command::ReloadConfig => "reload-config",
+            Command::DescribeKey => "describe-key",
+            Command::OpenPalette => "command-palette",
+            Command::ToggleWrap => "toggle-wrap",
+            Command::ToggleLineNumbers => "toggle-line-numbers",
+            Command::LineEndingsLf => "line-endings-lf",
+            Command::LineEndingsCrlf => "line-endings-crlf",
+        }
+    }
+
+    /// Whether the command changes the text the way typing does. vi only runs these from keys in
+    /// insert mode; elsewhere its own operators do the editing.
+    pub fn edits_text(self) -> bool {
+        matches!(self, Command::ReflowParagraph | Command::Newline | Command::DeleteBackward | Command::DeleteForward
+            | Command::InsertTab | Command::MoveLinesUp | Command::MoveLinesDown | Command::DuplicateLines
+            | Command::DeleteLines | Command::JoinLines | Command::ToggleComment | Command::KillLine
+            | Command::KillRegion | Command::Yank | Command::YankPop)
+    }
+
+    pub fn from_name(name: &str) -> Option<Command> {
+        Command::ALL.iter().copied().find(|command| command.name() == name)
+    }
+}
+
+/// A single key press with its modifiers, normalized so `Shift+a` and `A` compare equal.
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Debug)]
+pub struct KeyChord {
+    code: KeyCode,
+    modifiers: KeyModifiers,
+}
+
+impl KeyChord {
+    pub fn new(code: KeyCode, modifiers: KeyModifiers) -> Self {
+        let modifiers = modifiers & (KeyModifiers::CONTROL | KeyModifiers::ALT | KeyModifiers::SHIFT);
+        match code {
+            KeyCode::Char(c) if modifiers.contains(KeyModifiers::SHIFT) => Self {
+                code: KeyCode::Char(c.to_uppercase().next().unwrap_or(c)),
+                modifiers: modifiers - KeyModifiers::SHIFT,
+            },
+            KeyCode::BackTab => Self { code, modifiers: modifiers - KeyModifiers::SHIFT },
+            _ => Self { code, modifiers },
+        }
+    }
+
+    /// Parses chords such as `ctrl+k`, `alt+shift+left`, `f5` or `ctrl++`.
+    pub fn parse(text: &str) -> Result<Self, String> {
+        let (modifier_part, key) = match text.strip_suffix("++") {
+            Some(modifiers) => (modifiers, "+"),
+            None => text.rsplit_once('+').unwrap_or(("", text)),
+        };
+
+        let mut modifiers = KeyModifiers::NONE;
+        for modifier in modifier_part.split('+').filter(|m| !m.is_empty()) {
+            modifiers |= match modifier.to_lowercase().as_str() {
+                "ctrl" | "control" => KeyModifiers::CONTROL,
+                "alt" | "meta" => KeyModifiers::ALT,
+                "shift" => KeyModifiers::SHIFT,
+                _ => return Err(format!("unknown modifier `{}` in `{}`", modifier, text)),
+            };
+        }
+
+        let mut chars = key.chars();
+        let code = match (chars.next(), chars.next()) {
+            (Some(c), None) => KeyCode::Char(c.to_lowercase().next().unwrap_or(c)),
+            _ => match key.to_lowercase().as_str() {
+                "up" => KeyCode::Up,
+                "down" => KeyCode::Down,
+                "left" => KeyCode::Left,
+                "right" => KeyCode::Right,
+                "home" => KeyCode::Home,
+                "end" => KeyCode::End,
+                "pageup" => KeyCode::PageUp,
+                "pagedown" => KeyCode::PageDown,
+                "enter" => KeyCode::Enter,
+                "tab" => KeyCode::Tab,
+                "backtab" => KeyCode::BackTab,
+                "backspace" => KeyCode::Backspace,
+                "delete" | "del" => KeyCode::Delete,
+                "insert" => KeyCode::Insert,
+                "esc" | "escape" => KeyCode::Esc,
+                "space" => KeyCode::Char(' '),
+                name => match name.strip_prefix('f').and_then(|n| n.parse::<u8>().ok()) {
+                    Some(n) if (1..=24).contains(&n) => KeyCode::F(n),
+                    _ => return Err(format!("unknown key `{}` in `{}`", key, text)),
+                },
+            },
+        };
+        Ok(KeyChord::new(code, modifiers))
+    }
+
+    /// Parses a space separated sequence such as `ctrl+k ctrl+c`.
+    pub fn parse_sequence(text: &str) -> Result<Vec<Self>, String> {
+        let sequence = text.split_whitespace().map(KeyChord::parse).collect::<Result<Vec<_>, _>>()?;
+        if sequence.is_empty() {
+            return Err("empty key sequence".to_string());
+        }
+        Ok(sequence)
+    }
+}
+
+impl fmt::Display for KeyChord {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.modifiers.contains(KeyModifiers::CONTROL) {
+            write!(f, "Ctrl+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::ALT) {
+            write!(f, "Alt+")?;
+        }
+        if self.modifiers.contains(KeyModifiers::SHIFT) {
+            write!(f, "Shift+")?;
+        }
+        match self.code {
+            KeyCode::Char(' ') => write!(f, "Space"),
+            KeyCode::Char(c) if c.is_uppercase() => write!(f, "Shift+{}", c),
+            KeyCode::Char(c) => write!(f, "{}", c.to_uppercase()),
+            KeyCode::F(n) => write!(f, "F{}", n),
+            KeyCode::PageUp => write!(f, "PageUp"),
+            KeyCode::PageDown => write!(f, "PageDown"),
+            code => write!(f, "{:?}", code),
+        }
+    }
+}
+
+pub fn sequence_to_string(sequence: &[KeyChord]) -> String {
+    sequence.iter().map(|chord| chord.to_string()).collect::<Vec<_>>().join(" ")
+}
+
+pub enum KeyLookup {
+    Command(Command),
+    /// The keys so far are the start of at least one longer binding.
+    Pending,
+    Unbound,
+}
+
+pub struct Keymap {
+    bindings: HashMap<Vec<KeyChord>, Command>,
+}
+
+impl Default for Keymap {
+    fn default() -> Self {
+        let mut keymap = Keymap { bindings: HashMap::new() };
+        keymap.bind_all(&[
+            ("ctrl+q", Command::Quit),
+            ("up", Command::MoveUp),
+            ("down", Command::MoveDown),
+            ("left", Command::MoveLeft),
+            ("right", Command::MoveRight),
+            ("ctrl+left", Command::TokenLeft),
+            ("ctrl+right", Command::TokenRight),
+            ("alt+left", Command::SubwordLeft),
+            ("alt+right", Command::SubwordRight),
+            ("alt+a", Command::SentenceBackward),
+            ("alt+e", Command::SentenceForward),
+            ("alt+{", Command::ParagraphBackward),
+            ("alt+}", Command::ParagraphForward),
+            ("alt+h", Command::SelectParagraph),
+            ("alt+q", Command::ReflowParagraph),
+            ("home", Command::LineStart),
+            ("end", Command::LineEnd),
+            ("pageup", Command::PageUp),
+            ("pagedown", Command::PageDown),
+            ("ctrl+home", Command::FileStart),
+            ("ctrl+end", Command::FileEnd),
+            ("ctrl+up", Command::ScrollUp),
+            ("ctrl+down", Command::ScrollDown),
+            ("ctrl+l", Command::CenterCursor),
+            ("ctrl+]", Command::MatchingBracket),
+            // Terminals send Ctrl+] as Ctrl+5
+            ("ctrl+5", Command::MatchingBracket),
+            ("enter", Command::Newline),
+            ("backspace", Command::DeleteBackward),
+            ("delete", Command::DeleteForward),
+            ("tab", Command::InsertTab),
+            ("alt+up", Command::MoveLinesUp),
+            ("alt+down", Command::MoveLinesDown),
+            ("ctrl+shift+d", Command::DuplicateLines),
+            ("ctrl+d", Command::DuplicateLines),
+            ("ctrl+shift+k", Command::DeleteLines),
+            ("ctrl+j", Command::JoinLines),
+            ("ctrl+/", Command::ToggleComment),
+            // Terminals send Ctrl+/ as Ctrl+7
+            ("ctrl+7", Command::ToggleComment),
+            ("ctrl+z", Command::Undo),
+            ("ctrl+y", Command::Redo),
+            ("ctrl+g", Command::GotoLine),
+            ("ctrl+o", Command::JumpBack),
+            // Terminals send Ctrl+I as Tab, and Alt+[ starts an escape sequence
+            ("alt+]", Command::JumpForward),
+            ("alt+m", Command::SetNamedMark),
+            ("alt+'", Command::GotoMark),
+            ("ctrl+s", Command::Save),
+            ("f5", Command::ReloadConfig),
+            ("f1", Command::DescribeKey),
+            ("ctrl+shift+p", Command::OpenPalette),
+            // Most terminals cannot tell Ctrl+Shift+P from Ctrl+P
+            ("ctrl+p", Command::OpenPalette),
+            ("esc", Command::Cancel),
+        ]);
+        keymap
+    }
+}
+
+impl Keymap {
+    /// The default bindings with Emacs keys layered on top.
+    pub fn emacs() -> Self {
+        let mut keymap = Keymap::default();
+        keymap.bind_all(&[
+            ("ctrl+a", Command::LineStart),
+            ("ctrl+e", Command::LineEnd),
+            ("ctrl+f", Command::MoveRight),
+            ("ctrl+b", Command::MoveLeft),
+            ("ctrl+n", Command::MoveDown),
+            ("ctrl+p", Command::MoveUp),
+            ("alt+f", Command::TokenRight),
+            ("alt+b", Command::TokenLeft),
+            ("ctrl+d", Command::DeleteForward),
+            ("ctrl+v", Command::PageDown),
+            ("alt+v", Command::PageUp),
+            ("alt+<", Command::FileStart),
+            ("alt+>", Command::FileEnd),
+            ("ctrl+space", Command::SetMark),
+            ("ctrl+k", Command::KillLine),
+            ("ctrl+w", Command::KillRegion),
+            ("alt+w", Command::CopyRegion),
+            ("ctrl+y", Command::Yank),
+            ("alt+y", Command::YankPop),
+            ("ctrl+s", Command::SearchForward),
+            ("ctrl+r", Command::SearchBackward),
+            ("ctrl+g", Command::Cancel),
+            ("alt+g g", Command::GotoLine),
+            ("alt+g alt+g", Command::GotoLine),
+            ("ctrl+x r space", Command::SetNamedMark),
+            ("ctrl+x r j", Command::GotoMark),
+            ("ctrl+/", Command::Undo),
+            // Terminals send Ctrl+/ and Ctrl+_ as Ctrl+7
+            ("ctrl+7", Command::Undo),
+            ("ctrl+x u", Command::Undo),
+            ("ctrl+x ctrl+s", Command::Save),
+            ("ctrl+x ctrl+c", Command::Quit),
+            ("ctrl+z", Command::Suspend),
+            ("ctrl+x ctrl+z", Command::Suspend),
+            ("alt+x", Command::OpenPalette),
+            ("alt+;", Command::ToggleComment),
+        ]);
+        keymap
+    }
+
+    /// The default bindings with `Ctrl+Z` suspending, as in vi, which undoes with `u` instead.
+    pub fn vi() -> Self {
+        let mut keymap = Keymap::default();
+        keymap.bind_all(&[("ctrl+z", Command::Suspend)]);
+        keymap
+    }
+
+    fn bind_all(&mut self, bindings: &[(&str, Command)]) {
+        for &(keys, command) in bindings {
+            self.bind(KeyChord::parse_sequence(keys).expect("built-in bindings are valid"), Some(command));
+        }
+    }
+
+    /// Binds a sequence to a command, or removes the binding when `command` is `None`.
+    pub fn bind(&mut self, sequence: Vec<KeyChord>, command: Option<Command>) {
+        match command {
+            Some(command) => self.bindings.insert(sequence, command),
+            None => self.bindings.remove(&sequence),
+        };
+    }
+
+    pub fn lookup(&self, keys: &[KeyChord]) -> KeyLookup {
+        if let Some(&command) = self.bindings.get(keys) {
+            return KeyLookup::Command(command);
+        }
+        if self.bindings.keys().any(|sequence| sequence.len() > keys.len() && sequence.starts_with(keys)) {
+            return KeyLookup::Pending;
+        }
+        KeyLookup::Unbound
+    }
+
+    /// All sequences bound to a command, shortest first.
+    pub fn keys_for(&self, command: Command) -> Vec<String> {
+        let mut sequences: Vec<&Vec<KeyChord>> = self.bindings.iter()
+            .filter(|(_, bound)| **bound == command)
+            .map(|(sequence, _)| sequence)
+            .collect();
+        sequences.sort_by_key(|sequence| (sequence.len(), sequence_to_string(sequence)));
+        sequences.into_iter().map(|sequence| sequence_to_string(sequence)).collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn chord(text: &str) -> KeyChord {
+        KeyChord::parse(text).unwrap()
+    }
+
+    #[test]
+    fn parses_and_prints_chords() {
+        assert_eq!(chord("ctrl+k").to_string(), "Ctrl+K");
+        assert_eq!(chord("Ctrl+Shift+P").to_string(), "Ctrl+Shift+P");
+        assert_eq!(chord("alt+left").to_string(), "Alt+Left");
+        assert_eq!(chord("ctrl++").to_string(), "Ctrl++");
+        assert_eq!(chord("f12").to_string(), "F12");
+        assert!(KeyChord::parse("hyper+x").is_err());
+        assert!(KeyChord::parse("ctrl+nope").is_err());
+    }
+
+    #[test]
+    fn shifted_characters_match_their_uppercase_event() {
+        assert_eq!(chord("shift+p"), KeyChord::new(KeyCode::Char('P'), KeyModifiers::SHIFT));
+        assert_eq!(chord("ctrl+shift+p"), KeyChord::new(KeyCode::Char('p'), KeyModifiers::CONTROL | KeyModifiers::SHIFT));
+    }
+
+    #[test]
+    fn sequences_are_pending_until_complete() {
+        let mut keymap = Keymap::default();
+        keymap.bind(KeyChord::parse_sequence("ctrl+k ctrl+c").unwrap(), Some(Command::Quit));
+
+        assert!(matches!(keymap.lookup(&[chord("ctrl+k")]), KeyLookup::Pending));
+        assert!(matches!(keymap.lookup(&[chord("ctrl+k"), chord("ctrl+c")]), KeyLookup::Command(Command::Quit)));
+        assert!(matches!(keymap.lookup(&[chord("ctrl+k"), chord("x")]), KeyLookup::Unbound));
+    }
+
+    #[test]
+    fn emacs_preset_overrides_the_defaults_it_shares_keys_with() {
+        let keymap = Keymap::emacs();
+        assert!(matches!(keymap.lookup(&[chord("ctrl+p")]), KeyLookup::Command(Command::MoveUp)));
+        assert!(matches!(keymap.lookup(&[chord("ctrl+x")]), KeyLookup::Pending));
+        assert!(matches!(keymap.lookup(&[chord("ctrl+x"), chord("ctrl+s")]), KeyLookup::Command(Command::Save)));
+        assert!(matches!(keymap.lookup(&[chord("home")]), KeyLookup::Command(Command::LineStart)));
+    }
+
+    #[test]
+    fn emacs_and_vi_presets_suspend_on_ctrl_z() {
+        assert!(matches!(Keymap::default().lookup(&[chord("ctrl+z")]), KeyLookup::Command(Command::Undo)));
+        assert!(matches!(Keymap::emacs().lookup(&[chord("ctrl+z")]), KeyLookup::Command(Command::Suspend)));
+        assert!(matches!(Keymap::vi().lookup(&[chord("ctrl+z")]), KeyLookup::Command(Command::Suspend)));
+    }
+
+    #[test]
+    fn unbinding_removes_the_default() {
+        let mut keymap = Keymap::default();
+        keymap.bind(vec![chord("ctrl+q")], None);
+        assert!(matches!(keymap.lookup(&[chord("ctrl+q")]), KeyLookup::Unbound));
+        assert!(keymap.keys_for(Command::Quit).is_empty());
+    }
+
+    #[test]
+    fn every_command_name_round_trips() {
+        for &command in Command::ALL {
+            assert_eq!(Command::from_name(command.name()), Some(command));
+        }
+    }
+}
@@ -0,0 +1,80 @@
+/// How many kills are remembered before the oldest is dropped.
+const KILL_RING_SIZE: usize = 60;
+
+/// Killed text, most recent last, with the entry the next yank inserts.
+#[derive(Default)]
+pub struct KillRing {
+    entries: Vec<String>,
+    /// Index of the entry yank inserts; moves back with each yank-pop.
+    yank_index: usize,
+}
+
+impl KillRing {
+    pub fn push(&mut self, text: String) {
+        if self.entries.len() == KILL_RING_SIZE {
+            self.entries.remove(0);
+        }
+        self.entries.push(text);
+        self.yank_index = self.entries.len() - 1;
+    }
+
+    /// Adds to the most recent kill, so consecutive kills are yanked back together.
+    pub fn append(&mut self, text: &str) {
+        match self.entries.last_mut() {
+            Some(last) => {
+                last.push_str(text);
+                self.yank_index = self.entries.len() - 1;
+            }
+            None => self.push(text.to_string()),
+        }
+    }
+
+    /// The text yank inserts.
+    pub fn current(&self) -> Option<&str> {
+        self.entries.get(self.yank_index).map(String::as_str)
+    }
+
+    /// Moves to the kill before the current one, wrapping around to the newest.
+    pub fn rotate(&mut self) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.yank_index = self.yank_index.checked_sub(1).unwrap_or(self.entries.len() - 1);
+        self.current()
+    }
+
+    /// Starts the next yank from the newest kill again.
+    pub fn reset_yank(&mut self) {
+        self.yank_index = self.entries.len().saturating_sub(1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn appended_kills_form_one_entry() {
+        let mut ring = KillRing::default();
+        ring.push("first".to_string());
+        ring.push("one ".to_string());
+        ring.append("two");
+        assert_eq!(ring.current(), Some("one two"));
+        assert_eq!(ring.rotate(), Some("first"));
+        assert_eq!(ring.rotate(), Some("one two"));
+    }
+
+    #[test]
+    fn oldest_kills_are_dropped() {
+        let mut ring = KillRing::default();
+        for i in 0..=KILL_RING_SIZE {
+            ring.push(i.to_string());
+        }
+        ring.reset_yank();
+        for _ in 0..KILL_RING_SIZE - 1 {
+            ring.rotate();
+        }
+        assert_eq!(ring.current(), Some("1"));
+        assert_eq!(KillRing::default().rotate(), None);
+    }
+}
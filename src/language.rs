@@ -0,0 +1,208 @@
+use std::path::Path;
+
+/// What the editor knows about a kind of file: how to indent and comment it, which characters
+/// come in pairs and which make up words.
+pub struct Language {
+    pub name: &'static str,
+    extensions: &'static [&'static str],
+    /// Characters that, ending a line, indent the next one.
+    pub indent_after: &'static [char],
+    /// Brackets and quotes typed in pairs.
+    pub pairs: &'static [(char, char)],
+    /// Bracket pairs highlighted and jumped between.
+    pub brackets: &'static [(char, char)],
+    /// Characters besides letters and digits that are part of words.
+    pub word_chars: &'static str,
+    /// Mostly prose, where `don't` and `3.14` are one word; in code, punctuation splits words.
+    pub prose: bool,
+    /// Marker starting a comment that runs to the end of the line.
+    pub line_comment: Option<&'static str>,
+    /// Markers around a comment that can span lines.
+    pub block_comment: Option<(&'static str, &'static str)>,
+}
+
+const BRACKETS: &[char] = &['(', '[', '{'];
+const PAIRS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"'), ('\'', '\'')];
+/// Without `'`, which mostly shows up alone: apostrophes in prose, lifetimes in Rust.
+const PAIRS_NO_APOSTROPHE: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}'), ('"', '"')];
+pub const MATCHED_BRACKETS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}')];
+/// With `<>`, for markup, where they always come in pairs; in code they also stand alone, as in `->`.
+const MATCHED_BRACKETS_AND_TAGS: &[(char, char)] = &[('(', ')'), ('[', ']'), ('{', '}'), ('<', '>')];
+
+pub const PLAIN_TEXT: Language = Language {
+    name: "text",
+    extensions: &[],
+    indent_after: BRACKETS,
+    pairs: PAIRS_NO_APOSTROPHE,
+    brackets: MATCHED_BRACKETS,
+    word_chars: "_",
+    prose: true,
+    line_comment: None,
+    block_comment: None,
+};
+
+pub const LANGUAGES: &[Language] = &[
+    Language {
+        name: "rust",
+        extensions: &["rs"],
+        indent_after: BRACKETS,
+        pairs: PAIRS_NO_APOSTROPHE,
+        brackets: MATCHED_BRACKETS,
+        word_chars: "_",
+        prose: false,
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+    },
+    Language {
+        name: "c",
+        extensions: &["c", "h", "cpp", "hpp", "cc", "cs", "java", "js", "ts", "jsx", "tsx", "go", "kt", "swift"],
+        indent_after: BRACKETS,
+        pairs: PAIRS,
+        brackets: MATCHED_BRACKETS,
+        word_chars: "_",
+        prose: false,
+        line_comment: Some("//"),
+        block_comment: Some(("/*", "*/")),
+    },
+    Language {
+        name: "python",
+        extensions: &["py", "pyw"],
+        indent_after: &['(', '[', '{', ':'],
+        pairs: PAIRS,
+        brackets: MATCHED_BRACKETS,
+        word_chars: "_",
+        prose: false,
+        line_comment: Some("#"),
+        block_comment: None,
+    },
+    Language {
+        name: "shell",
+        extensions: &["sh", "bash", "zsh", "toml", "yaml", "yml", "conf"],
+        indent_after: BRACKETS,
+        pairs: PAIRS,
+        brackets: MATCHED_BRACKETS,
+        word_chars: "_",
+        prose: false,
+        line_comment: Some("#"),
+        block_comment: None,
+    },
+    Language {
+        name: "lua",
+        extensions: &["lua"],
+        indent_after: BRACKETS,
+        pairs: PAIRS,
+        brackets: MATCHED_BRACKETS,
+        word_chars: "_",
+        prose: false,
+        line_comment: Some("--"),
+        block_comment: Some(("--[[", "]]")),
+    },
+    Language {
+        name: "sql",
+        extensions: &["sql"],
+        indent_after: &['('],
+        pairs: PAIRS,
+        brackets: MATCHED_BRACKETS,
+        word_chars: "_",
+        prose: false,
+        line_comment: Some("--"),
+        block_comment: Some(("/*", "*/")),
+    },
+    Language {
+        name: "haskell",
+        extensions: &["hs", "lhs"],
+        indent_after: BRACKETS,
+        pairs: PAIRS_NO_APOSTROPHE,
+        brackets: MATCHED_BRACKETS,
+        word_chars: "_'",
+        prose: false,
+        line_comment: Some("--"),
+        block_comment: Some(("{-", "-}")),
+    },
+    Language {
+        name: "css",
+        extensions: &["css", "scss", "less"],
+        indent_after: BRACKETS,
+        pairs: PAIRS,
+        brackets: MATCHED_BRACKETS,
+        word_chars: "-_",
+        prose: false,
+        line_comment: None,
+        block_comment: Some(("/*", "*/")),
+    },
+    Language {
+        name: "html",
+        extensions: &["html", "htm", "xml", "svg", "vue"],
+        indent_after: BRACKETS,
+        pairs: PAIRS,
+        brackets: MATCHED_BRACKETS_AND_TAGS,
+        word_chars: "-_",
+        prose: false,
+        line_comment: None,
+        block_comment: Some(("<!--", "-->")),
+    },
+    Language {
+        name: "markdown",
+        extensions: &["md", "markdown"],
+        indent_after: &[],
+        pairs: PAIRS_NO_APOSTROPHE,
+        brackets: MATCHED_BRACKETS,
+        word_chars: "_",
+        prose: true,
+        line_comment: None,
+        block_comment: Some(("<!--", "-->")),
+    },
+    Language {
+        name: "lisp",
+        extensions: &["lisp", "lsp", "el", "scm", "ss", "rkt", "clj", "cljs", "edn"],
+        indent_after: BRACKETS,
+        pairs: PAIRS_NO_APOSTROPHE,
+        brackets: MATCHED_BRACKETS,
+        word_chars: "-_?!*<>=/+",
+        prose: false,
+        line_comment: Some(";"),
+        block_comment: None,
+    },
+];
+
+impl Language {
+    /// A language by the name used in the status line and `[languages.<name>]` sections.
+    pub fn by_name(name: &str) -> Option<&'static Language> {
+        LANGUAGES.iter().chain([&PLAIN_TEXT]).find(|language| language.name == name)
+    }
+
+    /// The language of a file, by extension, or plain text.
+    pub fn for_path(path: &Path) -> &'static Language {
+        let extension = path.extension().map(|e| e.to_string_lossy().to_lowercase()).unwrap_or_default();
+        LANGUAGES.iter()
+            .find(|language| language.extensions.contains(&extension.as_str()))
+            .unwrap_or(&PLAIN_TEXT)
+    }
+}
+
+/// Closing partner of an opening bracket.
+pub fn closing_bracket(open: char) -> Option<char> {
+    match open {
+        '(' => Some(')'),
+        '[' => Some(']'),
+        '{' => Some('}'),
+        _ => None,
+    }
+}
+
+/// Opening partner of a closing bracket.
+pub fn opening_bracket(close: char) -> Option<char> {
+    BRACKETS.iter().copied().find(|&open| closing_bracket(open) == Some(close))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn languages_are_found_by_extension() {
+        assert_eq!(Language::for_path(Path::new("src/main.rs")).name, "rust");
+        assert_eq!(Language::for_path(Path::new("setup.PY")).name, "python");
+        assert_eq!(Language::for_path(Path::new("README")).name, "text");
+    }
+}
@@ -1,76 +1,918 @@
+mod buffer;
+mod config;
 mod cursor;
+mod editor;
+mod keymap;
+mod kill_ring;
+mod language;
+mod marks;
+mod palette;
 mod utils;
 mod screen;
+mod search;
+mod signals;
+mod swap;
+mod terminal;
+mod vi;
 
 use std::io;
-use std::io::stdout;
-use std::time::{Duration};
-use crossterm::{event, execute};
-use crossterm::event::{Event, KeyCode, KeyEvent, KeyEventKind, KeyModifiers};
-use crossterm::terminal::{enable_raw_mode};
-use cursor::{CursorPos};
+use std::io::Write;
+use std::panic::{self, AssertUnwindSafe};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant};
+use crossterm::event::{Event, KeyEvent, KeyEventKind};
 use utils::path::get_route;
 use utils::files::read_file;
-use crate::screen::{clean_screen, draw_screen};
-use crate::utils::content_wrapper::wrap_content;
-use crate::utils::tokenizer::{ tokenize_text};
-use crate::utils::direction::Direction;
+use crate::buffer::Buffer;
+use crate::config::Config;
+use crate::editor::Editor;
+use crate::screen::{clean_screen, set_raw_mode};
+use crate::swap::SwapFile;
+use crate::terminal::{CrosstermTerminal, Terminal};
+
+const CONFIG_CHECK_INTERVAL: Duration = Duration::from_secs(1);
+const SWAP_WRITE_INTERVAL: Duration = Duration::from_secs(2);
+
 fn main() -> io::Result<()> {
-    enable_raw_mode()?;
-    program_loop(read_file(&get_route())?)?;
+    install_panic_hook();
+    let route = get_route();
+    // Read before raw mode is on, so a read error reaches an untouched terminal
+    let buffer = Buffer::from_text(PathBuf::from(&route), &read_file(&route)?);
+    signals::register()?;
+    set_raw_mode(true)?;
+    let swap = SwapFile::for_file(Path::new(&route));
+    let result = program_loop(buffer, Config::load(Path::new(&route)), swap, &mut CrosstermTerminal::new());
     clean_screen()?;
+    if let Some(note) = result? {
+        // After SIGHUP there may be no terminal left to print to
+        _ = writeln!(io::stderr(), "{}", note);
+    }
     Ok(())
 }
 
-fn program_loop(contents: String) -> io::Result<()> {
-    let (terminal_cols, _) = crossterm::terminal::size()?;
-    let mut wrap_result = wrap_content(&contents, terminal_cols as usize);
-    let mut tokenized_words = tokenize_text(&wrap_result.wrapped_text, &wrap_result.wrap_ids, false);
+/// Restores the terminal before the panic message is printed, so the message shows up in the shell
+/// instead of vanishing with the alternate screen, and the shell is usable afterwards.
+fn install_panic_hook() {
+    let default_hook = panic::take_hook();
+    panic::set_hook(Box::new(move |info| {
+        _ = clean_screen();
+        default_hook(info);
+    }));
+}
+
+/// Runs the editor until it quits. Returns a note for the shell when it had to exit early, saying where
+/// the unsaved text went.
+fn program_loop(buffer: Buffer, config: Config, swap: Option<SwapFile>, terminal: &mut impl Terminal) -> io::Result<Option<String>> {
+    let (terminal_cols, terminal_rows) = terminal.size()?;
+    let mut editor = Editor::new(buffer, config, terminal_cols, terminal_rows);
+    if let Some(swap) = swap {
+        editor.open_swap(swap);
+    }
 
-    let mut cursor = CursorPos::new(&wrap_result.wrapped_text, wrap_result.wrap_ids.clone(), tokenized_words);
+    // A panic leaves the text in the editor intact, so it can still be written out
+    match panic::catch_unwind(AssertUnwindSafe(|| event_loop(&mut editor, terminal))) {
+        Ok(Ok(note)) => Ok(note),
+        Ok(Err(e)) => {
+            editor.write_emergency_file();
+            Err(e)
+        }
+        Err(panic) => {
+            if let Some(note) = editor.write_emergency_file() {
+                _ = writeln!(io::stderr(), "{}", note);
+            }
+            panic::resume_unwind(panic)
+        }
+    }
+}
 
-    execute!(
-        stdout(),
-        crossterm::terminal::EnterAlternateScreen,
-        crossterm::cursor::Hide
-    )?;
-    
-    draw_screen(&wrap_result.wrapped_text, &cursor)?;
-    cursor.refresh()?;
-    
+fn event_loop(editor: &mut Editor, terminal: &mut impl Terminal) -> io::Result<Option<String>> {
+    terminal.enter_alternate_screen()?;
+    editor.draw(terminal)?;
 
+    let mut last_config_check = Instant::now();
+    let mut last_swap_write = Instant::now();
     loop {
-        if event::poll(Duration::from_millis(16))? {
-            match event::read()? {
-                Event::Resize(cols, _) => {
-                    wrap_result = wrap_content(&contents, cols as usize);
-                    let old_cursor_state = (cursor.x, cursor.y, cursor.last_x, cursor.vertical_offset);
-                    tokenized_words = tokenize_text(&wrap_result.wrapped_text, &wrap_result.wrap_ids, false);
-                    
-                    cursor = CursorPos::new(&wrap_result.wrapped_text, wrap_result.wrap_ids.clone(), tokenized_words);
-                    (cursor.x, cursor.y, cursor.last_x, cursor.vertical_offset) = old_cursor_state;
-                    draw_screen(&wrap_result.wrapped_text, &cursor)?;
-                },
-                Event::Key(KeyEvent { code, kind: KeyEventKind::Press, modifiers, .. }) => match (code, modifiers){
-                    (KeyCode::Char('q'), KeyModifiers::CONTROL) => break,
-                    (KeyCode::Up, _) if cursor.move_up() => draw_screen(&wrap_result.wrapped_text, &cursor)?,
-                    (KeyCode::Down, _) if cursor.move_down() => draw_screen(&wrap_result.wrapped_text, &cursor)?,
-                    (KeyCode::Left, KeyModifiers::CONTROL) => cursor.move_token(Direction::Left),
-                    (KeyCode::Right, KeyModifiers::CONTROL) => cursor.move_token(Direction::Right),
-                    (KeyCode::Left, _) => cursor.move_left(),
-                    (KeyCode::Right, _) => cursor.move_right(),
-                    (KeyCode::Home, _) => cursor.move_home(),
-                    (KeyCode::End, _) => cursor.move_end(),
-                    (KeyCode::Char('t'), KeyModifiers::CONTROL) => _ = cursor.get_token_on_cursor(),
-                    (KeyCode::Char('t'), KeyModifiers::NONE) => _ = tokenize_text(&wrap_result.wrapped_text, &wrap_result.wrap_ids, true),
-
-                    _ => {}
-                },
-                _ => {}
+        if signals::termination_requested() {
+            // The swap file stays behind too, so the next start offers to recover it
+            return Ok(editor.write_emergency_file());
+        }
+        if signals::take_suspend_request() {
+            suspend(editor, terminal)?;
+            editor.draw(terminal)?;
+            continue;
+        }
+        if signals::take_continued() {
+            // Stopped from outside with SIGSTOP: the shell may have drawn over the screen
+            let (cols, rows) = terminal.size()?;
+            editor.resume(cols, rows);
+            editor.draw(terminal)?;
+            continue;
+        }
+        match terminal.poll_event(Duration::from_millis(16))? {
+            Some(Event::Resize(cols, rows)) => editor.resize(cols, rows),
+            Some(Event::Key(KeyEvent { code, kind: KeyEventKind::Press, modifiers, .. })) => {
+                if !editor.handle_key(code, modifiers) {
+                    break;
+                }
+                if editor.take_suspend_request() {
+                    suspend(editor, terminal)?;
+                }
+            }
+            Some(Event::Mouse(mouse)) => editor.handle_mouse(mouse),
+            Some(_) => continue,
+            None => {
+                if last_swap_write.elapsed() >= SWAP_WRITE_INTERVAL {
+                    last_swap_write = Instant::now();
+                    editor.update_swap();
+                }
+                if last_config_check.elapsed() < CONFIG_CHECK_INTERVAL {
+                    continue;
+                }
+                last_config_check = Instant::now();
+                if !editor.reload_config_if_stale() {
+                    continue;
+                }
             }
-            cursor.refresh()?;
         }
+        editor.draw(terminal)?;
     }
 
+    editor.close_swap();
+    Ok(None)
+}
+
+/// Hands the terminal back to the shell until the editor is continued, then repaints everything.
+fn suspend(editor: &mut Editor, terminal: &mut impl Terminal) -> io::Result<()> {
+    // A stopped editor may never be continued, so the swap file has to be current
+    editor.update_swap();
+    terminal.suspend()?;
+    // The SIGCONT that woke the editor up is handled here already
+    signals::take_continued();
+    let (cols, rows) = terminal.size()?;
+    editor.resume(cols, rows);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crossterm::event::{KeyCode, KeyModifiers, MouseButton, MouseEvent, MouseEventKind};
+    use crate::terminal::HeadlessTerminal;
+
+    fn key(code: KeyCode, modifiers: KeyModifiers) -> Event {
+        Event::Key(KeyEvent::new(code, modifiers))
+    }
+
+    fn buffer_for(contents: &str) -> Buffer {
+        Buffer::from_text(PathBuf::from("test.txt"), contents)
+    }
+
+    fn typed(text: &str) -> Vec<Event> {
+        text.chars().map(|c| key(KeyCode::Char(c), KeyModifiers::NONE)).collect()
+    }
+
+    fn run(contents: &str, terminal: &mut HeadlessTerminal, keys: &[Event]) {
+        run_with(Config::default(), contents, terminal, keys);
+    }
+
+    fn run_with(config: Config, contents: &str, terminal: &mut HeadlessTerminal, keys: &[Event]) {
+        for event in keys {
+            terminal.push_event(event.clone());
+        }
+        push_quit(terminal);
+        program_loop(buffer_for(contents), config, None, terminal).unwrap();
+    }
+
+    /// Ends a run. The second Ctrl+Q confirms quitting with unsaved changes; without any, it is never read.
+    fn push_quit(terminal: &mut HeadlessTerminal) {
+        terminal.push_event(key(KeyCode::Char('q'), KeyModifiers::CONTROL));
+        terminal.push_event(key(KeyCode::Char('q'), KeyModifiers::CONTROL));
+    }
+
+    /// Runs the keys with the swap file at the returned path, which starts out holding `swap_text`.
+    fn run_with_swap(name: &str, swap_text: Option<&str>, contents: &str, terminal: &mut HeadlessTerminal, keys: &[Event]) -> PathBuf {
+        let dir = std::env::temp_dir().join(format!("fumaedit-{}-{}", name, std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        let swap = SwapFile::in_dir(&dir, Path::new("test.txt"));
+        let swap_path = swap.path().to_path_buf();
+        if let Some(text) = swap_text {
+            std::fs::create_dir_all(&dir).unwrap();
+            std::fs::write(&swap_path, text).unwrap();
+        }
+        for event in keys {
+            terminal.push_event(event.clone());
+        }
+        push_quit(terminal);
+        program_loop(buffer_for(contents), Config::default(), Some(swap), terminal).unwrap();
+        swap_path
+    }
+
+    fn mouse(kind: MouseEventKind, column: u16, row: u16) -> Event {
+        Event::Mouse(MouseEvent { kind, column, row, modifiers: KeyModifiers::NONE })
+    }
+
+    fn click(column: u16, row: u16) -> Event {
+        mouse(MouseEventKind::Down(MouseButton::Left), column, row)
+    }
+
+    fn emacs_config() -> Config {
+        let mut config = Config::default();
+        config.settings.keymap = config::KeymapPreset::Emacs;
+        config
+    }
+
+    fn ctrl(c: char) -> Event {
+        key(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    fn vi_config() -> Config {
+        let mut config = Config::default();
+        config.settings.keymap = config::KeymapPreset::Vi;
+        config
+    }
+
+    #[test]
+    fn draws_the_first_screenful() {
+        let mut terminal = HeadlessTerminal::new(30, 3);
+        run("first\nsecond\nthird", &mut terminal, &[]);
+        assert_eq!(terminal.row_text(0), "first");
+        assert_eq!(terminal.row_text(1), "second");
+        assert!(terminal.row_text(2).starts_with("test.txt"));
+        assert!(terminal.row_text(2).ends_with("LF  Ln 1, Col 1"));
+        assert_eq!(terminal.cursor(), (0, 0));
+        assert!(terminal.cursor_visible());
+    }
+
+    #[test]
+    fn scrolls_when_moving_past_the_last_row() {
+        let mut terminal = HeadlessTerminal::new(20, 3);
+        let down = key(KeyCode::Down, KeyModifiers::NONE);
+        run("first\nsecond\nthird", &mut terminal, &[down.clone(), down]);
+        assert_eq!(terminal.row_text(0), "second");
+        assert_eq!(terminal.row_text(1), "third");
+        assert_eq!(terminal.cursor(), (0, 1));
+    }
+
+    #[test]
+    fn ctrl_arrows_move_by_token() {
+        let mut terminal = HeadlessTerminal::new(20, 2);
+        let ctrl_right = key(KeyCode::Right, KeyModifiers::CONTROL);
+        run("let x = 1;", &mut terminal, &[ctrl_right.clone(), ctrl_right]);
+        assert_eq!(terminal.cursor(), (5, 0));
+    }
+
+    #[test]
+    fn resize_rewraps_the_text() {
+        let mut terminal = HeadlessTerminal::new(20, 3);
+        terminal.push_event(key(KeyCode::End, KeyModifiers::NONE));
+        terminal.resize(8, 4);
+        run("hello world", &mut terminal, &[]);
+        assert_eq!(terminal.row_text(0), "hello");
+        assert_eq!(terminal.row_text(1), "world");
+        assert_eq!(terminal.cursor(), (5, 1));
+    }
+
+    #[test]
+    fn resize_keeps_the_cursor_on_its_text_and_screen_row() {
+        let contents = vec!["word ".repeat(8).trim_end().to_string(); 30].join("\n");
+        let mut terminal = HeadlessTerminal::new(60, 8);
+        for _ in 0..12 {
+            terminal.push_event(key(KeyCode::Down, KeyModifiers::NONE));
+        }
+        for _ in 0..3 {
+            terminal.push_event(key(KeyCode::Up, KeyModifiers::NONE));
+        }
+        terminal.push_event(key(KeyCode::End, KeyModifiers::NONE));
+        terminal.resize(30, 8);
+        run(&contents, &mut terminal, &[]);
+        assert!(terminal.row_text(7).ends_with("Ln 10, Col 40"));
+        assert_eq!(terminal.cursor().1, 3);
+
+        // A window taller than the file shows it from the top
+        let mut terminal = HeadlessTerminal::new(20, 5);
+        terminal.push_event(key(KeyCode::End, KeyModifiers::CONTROL));
+        terminal.resize(20, 30);
+        run("1\n2\n3\n4\n5\n6\n7\n8\n9\n10", &mut terminal, &[]);
+        assert_eq!(terminal.row_text(0), "1");
+        assert_eq!(terminal.cursor(), (2, 9));
+    }
+
+    #[test]
+    fn suspending_redraws_everything_at_the_new_size_on_resume() {
+        let mut terminal = HeadlessTerminal::new(20, 3);
+        terminal.resize_while_suspended(8, 4);
+        run_with(emacs_config(), "hello world", &mut terminal, &[key(KeyCode::End, KeyModifiers::NONE), ctrl('z')]);
+        assert_eq!(terminal.suspensions(), 1);
+        assert_eq!(terminal.row_text(0), "hello");
+        assert_eq!(terminal.row_text(1), "world");
+        assert_eq!(terminal.cursor(), (5, 1));
+
+        // Ctrl+Z undoes in the default keymap, and suspends once it is unbound
+        let mut terminal = HeadlessTerminal::new(20, 3);
+        run("hello", &mut terminal, &[ctrl('z')]);
+        assert_eq!(terminal.suspensions(), 0);
+        let mut terminal = HeadlessTerminal::new(20, 3);
+        let mut config = Config::default();
+        config.settings.key_bindings.push((keymap::KeyChord::parse_sequence("ctrl+z").unwrap(), None));
+        run_with(config, "hello", &mut terminal, &[ctrl('z')]);
+        assert_eq!(terminal.suspensions(), 1);
+        assert_eq!(terminal.row_text(0), "hello");
+    }
+
+    #[test]
+    fn line_numbers_only_label_the_first_row_of_a_line() {
+        let mut terminal = HeadlessTerminal::new(10, 4);
+        let mut config = Config::default();
+        config.settings.line_numbers = true;
+        terminal.push_event(key(KeyCode::Char('q'), KeyModifiers::CONTROL));
+        program_loop(buffer_for("abcdefghij\nx"), config, None, &mut terminal).unwrap();
+        assert_eq!(terminal.row_text(0), "1 abcdef");
+        assert_eq!(terminal.row_text(1), "  ghij");
+        assert_eq!(terminal.row_text(2), "2 x");
+        assert_eq!(terminal.cursor(), (2, 0));
+    }
+
+    #[test]
+    fn no_wrap_scrolls_horizontally() {
+        let mut terminal = HeadlessTerminal::new(6, 3);
+        let mut config = Config::default();
+        config.settings.wrap = config::WrapMode::None;
+        terminal.push_event(key(KeyCode::End, KeyModifiers::NONE));
+        terminal.push_event(key(KeyCode::Char('q'), KeyModifiers::CONTROL));
+        program_loop(buffer_for("abcdefghij\nxyz"), config, None, &mut terminal).unwrap();
+        assert_eq!(terminal.row_text(0), "fghij");
+        assert_eq!(terminal.cursor(), (5, 0));
+    }
+
+    #[test]
+    fn scroll_margin_keeps_rows_below_the_cursor() {
+        let mut terminal = HeadlessTerminal::new(20, 5);
+        let mut config = Config::default();
+        config.settings.scroll_margin = 1;
+        let down = key(KeyCode::Down, KeyModifiers::NONE);
+        for _ in 0..3 {
+            terminal.push_event(down.clone());
+        }
+        terminal.push_event(key(KeyCode::Char('q'), KeyModifiers::CONTROL));
+        program_loop(buffer_for("0\n1\n2\n3\n4\n5"), config, None, &mut terminal).unwrap();
+        assert_eq!(terminal.row_text(0), "1");
+        assert_eq!(terminal.cursor(), (0, 2));
+    }
+
+    #[test]
+    fn chorded_bindings_from_config_run_their_command() {
+        let mut terminal = HeadlessTerminal::new(20, 3);
+        let mut config = Config::default();
+        config.settings.key_bindings.push((keymap::KeyChord::parse_sequence("ctrl+k ctrl+e").unwrap(), Some(keymap::Command::LineEnd)));
+        config.settings.key_bindings.push((keymap::KeyChord::parse_sequence("end").unwrap(), None));
+        terminal.push_event(key(KeyCode::End, KeyModifiers::NONE));
+        terminal.push_event(key(KeyCode::Char('k'), KeyModifiers::CONTROL));
+        terminal.push_event(key(KeyCode::Char('e'), KeyModifiers::CONTROL));
+        terminal.push_event(key(KeyCode::Char('q'), KeyModifiers::CONTROL));
+        program_loop(buffer_for("hello"), config, None, &mut terminal).unwrap();
+        assert_eq!(terminal.cursor(), (5, 0));
+    }
+
+    #[test]
+    fn describe_key_reports_the_binding_without_running_it() {
+        let mut terminal = HeadlessTerminal::new(40, 3);
+        run("hello", &mut terminal, &[
+            key(KeyCode::F(1), KeyModifiers::NONE),
+            key(KeyCode::End, KeyModifiers::NONE),
+        ]);
+        assert!(terminal.row_text(2).starts_with("End runs line-end"));
+        assert_eq!(terminal.cursor(), (0, 0));
+    }
+
+    #[test]
+    fn palette_runs_commands_that_have_no_binding() {
+        let mut terminal = HeadlessTerminal::new(40, 4);
+        let mut keys = vec![key(KeyCode::Char('P'), KeyModifiers::CONTROL | KeyModifiers::SHIFT)];
+        keys.extend("crlf".chars().map(|c| key(KeyCode::Char(c), KeyModifiers::NONE)));
+        keys.push(key(KeyCode::Enter, KeyModifiers::NONE));
+        run("hello", &mut terminal, &keys);
+        assert!(terminal.row_text(3).ends_with("CRLF  Ln 1, Col 1"));
+        assert_eq!(terminal.row_text(0), "hello");
+        assert_eq!(terminal.cursor(), (0, 0));
+    }
+
+    #[test]
+    fn quitting_with_unsaved_changes_takes_a_second_quit() {
+        let mut terminal = HeadlessTerminal::new(50, 3);
+        run("x", &mut terminal, &[typed("a"), vec![ctrl('q')], typed("b")].concat());
+        assert_eq!(terminal.row_text(0), "abx");
+        assert!(terminal.row_text(2).starts_with("Quit again"));
+    }
+
+    #[test]
+    fn deleting_past_either_end_leaves_the_undo_history_alone() {
+        let mut terminal = HeadlessTerminal::new(20, 3);
+        let mut keys = typed("x");
+        keys.push(ctrl('z'));
+        keys.push(key(KeyCode::Backspace, KeyModifiers::NONE));
+        keys.push(key(KeyCode::End, KeyModifiers::NONE));
+        keys.push(key(KeyCode::Delete, KeyModifiers::NONE));
+        keys.push(ctrl('y'));
+        run("ab", &mut terminal, &keys);
+        assert_eq!(terminal.row_text(0), "xab");
+    }
+
+    #[test]
+    fn typing_inserts_text_and_undo_removes_it_in_one_step() {
+        let mut terminal = HeadlessTerminal::new(40, 3);
+        let mut keys = typed("ab");
+        keys.push(key(KeyCode::Enter, KeyModifiers::NONE));
+        keys.extend(typed("cd"));
+        run("x", &mut terminal, &keys);
+        assert_eq!(terminal.row_text(0), "ab");
+        assert_eq!(terminal.row_text(1), "cdx");
+        // Quitting with the changes unsaved asks first
+        assert!(terminal.row_text(2).starts_with("Quit again"));
+
+        let mut terminal = HeadlessTerminal::new(20, 3);
+        keys.push(key(KeyCode::Char('z'), KeyModifiers::CONTROL));
+        run("x", &mut terminal, &keys);
+        assert_eq!(terminal.row_text(0), "ab");
+        assert_eq!(terminal.row_text(1), "x");
+    }
+
+    #[test]
+    fn vi_operators_take_counts_and_motions() {
+        let mut terminal = HeadlessTerminal::new(50, 3);
+        run_with(vi_config(), "one two three four", &mut terminal, &typed("2dwx"));
+        assert_eq!(terminal.row_text(0), "hree four");
+        // Deleted rather than typed, and the changes are unsaved
+        assert!(terminal.row_text(2).starts_with("Quit again"));
+    }
+
+    #[test]
+    fn vi_change_inside_brackets_enters_insert_mode() {
+        let mut terminal = HeadlessTerminal::new(30, 3);
+        run_with(vi_config(), "call(a, b)", &mut terminal, &typed("4lci(x"));
+        // The x was typed, not run as a command
+        assert_eq!(terminal.row_text(0), "call(x)");
+    }
+
+    #[test]
+    fn vi_word_objects_take_in_the_language_word_chars() {
+        let mut terminal = HeadlessTerminal::new(30, 3);
+        for event in typed("4ldiw") {
+            terminal.push_event(event);
+        }
+        push_quit(&mut terminal);
+        let buffer = Buffer::from_text(PathBuf::from("test.rs"), "let my_var = 1;");
+        program_loop(buffer, vi_config(), None, &mut terminal).unwrap();
+        assert_eq!(terminal.row_text(0), "let  = 1;");
+    }
+
+    #[test]
+    fn vi_visual_line_delete_and_put() {
+        let mut terminal = HeadlessTerminal::new(30, 4);
+        run_with(vi_config(), "a\nb\nc", &mut terminal, &typed("Vjdp"));
+        assert_eq!(terminal.row_text(0), "c");
+        assert_eq!(terminal.row_text(1), "a");
+        assert_eq!(terminal.row_text(2), "b");
+        assert_eq!(terminal.cursor(), (0, 1));
+    }
+
+    #[test]
+    fn vi_normal_mode_keys_do_not_type_into_the_text() {
+        let mut terminal = HeadlessTerminal::new(40, 4);
+        let keys = [
+            key(KeyCode::Enter, KeyModifiers::NONE),
+            key(KeyCode::Backspace, KeyModifiers::NONE),
+            key(KeyCode::Delete, KeyModifiers::NONE),
+            key(KeyCode::Tab, KeyModifiers::NONE),
+            ctrl('d'),
+        ];
+        run_with(vi_config(), "ab\ncd", &mut terminal, &keys);
+        assert_eq!(terminal.row_text(0), "ab");
+        assert_eq!(terminal.row_text(1), "cd");
+        assert!(terminal.row_text(2).is_empty());
+        assert!(!terminal.row_text(3).contains("[+]"));
+    }
+
+    #[test]
+    fn vi_line_operators_on_j_and_k_take_logical_lines() {
+        // "one two three" wraps onto two rows
+        let mut terminal = HeadlessTerminal::new(10, 5);
+        run_with(vi_config(), "one two three\nfour\nfive", &mut terminal, &typed("dj"));
+        assert_eq!(terminal.row_text(0), "five");
+
+        let mut terminal = HeadlessTerminal::new(10, 5);
+        run_with(vi_config(), "zero\none two three\nfour", &mut terminal, &typed("jjdk"));
+        assert_eq!(terminal.row_text(0), "four");
+        assert_eq!(terminal.row_text(1), "");
+    }
+
+    #[test]
+    fn vi_undo_restores_deleted_lines() {
+        let mut terminal = HeadlessTerminal::new(30, 4);
+        run_with(vi_config(), "a\nb", &mut terminal, &typed("ddu"));
+        assert_eq!(terminal.row_text(0), "a");
+        assert_eq!(terminal.row_text(1), "b");
+    }
+
+    #[test]
+    fn emacs_consecutive_kills_are_yanked_together() {
+        let mut terminal = HeadlessTerminal::new(30, 4);
+        run_with(emacs_config(), "one\ntwo\nthree", &mut terminal, &[ctrl('k'), ctrl('k'), ctrl('k'), ctrl('y')]);
+        assert_eq!(terminal.row_text(0), "one");
+        assert_eq!(terminal.row_text(1), "two");
+        assert_eq!(terminal.cursor(), (3, 1));
+    }
+
+    #[test]
+    fn emacs_yank_pop_cycles_to_earlier_kills() {
+        let mut terminal = HeadlessTerminal::new(30, 4);
+        let keys = [ctrl('k'), ctrl('n'), ctrl('k'), ctrl('y'), key(KeyCode::Char('y'), KeyModifiers::ALT)];
+        run_with(emacs_config(), "x\ny", &mut terminal, &keys);
+        assert_eq!(terminal.row_text(0), "");
+        assert_eq!(terminal.row_text(1), "x");
+    }
+
+    #[test]
+    fn emacs_incremental_search_moves_to_each_match() {
+        let mut terminal = HeadlessTerminal::new(40, 4);
+        let mut keys = vec![ctrl('s')];
+        keys.extend(typed("beta"));
+        keys.push(ctrl('s'));
+        run_with(emacs_config(), "alpha beta\nbeta gamma", &mut terminal, &keys);
+        assert!(terminal.row_text(3).starts_with("I-search: beta"));
+        assert_eq!(terminal.cursor(), (4, 1));
+
+        let mut terminal = HeadlessTerminal::new(30, 4);
+        keys.push(ctrl('g'));
+        run_with(emacs_config(), "alpha beta\nbeta gamma", &mut terminal, &keys);
+        assert_eq!(terminal.cursor(), (0, 0));
+    }
+
+    #[test]
+    fn goto_line_centers_the_target_line() {
+        let contents: Vec<String> = (0..20).map(|i| i.to_string()).collect();
+        let mut terminal = HeadlessTerminal::new(60, 6);
+        let mut keys = vec![ctrl('g')];
+        keys.extend(typed("12:2"));
+        keys.push(key(KeyCode::Enter, KeyModifiers::NONE));
+        run(&contents.join("\n"), &mut terminal, &keys);
+        assert_eq!(terminal.row_text(0), "9");
+        assert_eq!(terminal.cursor(), (1, 2));
+    }
+
+    #[test]
+    fn goto_line_maps_logical_lines_to_wrapped_rows() {
+        let mut terminal = HeadlessTerminal::new(4, 4);
+        let mut keys = vec![ctrl('g')];
+        keys.extend(typed("2"));
+        keys.push(key(KeyCode::Enter, KeyModifiers::NONE));
+        run("abcdefgh\nxy\nz", &mut terminal, &keys);
+        assert_eq!(terminal.cursor(), (0, 1));
+        assert_eq!(terminal.row_text(1), "xy");
+    }
+
+    #[test]
+    fn wheel_scrolls_and_clicks_account_for_the_offset_and_gutter() {
+        let contents: Vec<String> = (0..20).map(|i| format!("line {}", i)).collect();
+        let numbered = || {
+            let mut config = Config::default();
+            config.settings.line_numbers = true;
+            config
+        };
+        let mut terminal = HeadlessTerminal::new(30, 5);
+        run_with(numbered(), &contents.join("\n"), &mut terminal, &[mouse(MouseEventKind::ScrollDown, 0, 0)]);
+        assert_eq!(terminal.row_text(0), " 4 line 3");
+        assert!(!terminal.cursor_visible());
+
+        let mut terminal = HeadlessTerminal::new(30, 5);
+        run_with(numbered(), &contents.join("\n"), &mut terminal, &[mouse(MouseEventKind::ScrollDown, 0, 0), click(8, 1), click(20, 2)]);
+        assert!(terminal.row_text(4).ends_with("Ln 6, Col 7"));
+        assert_eq!(terminal.cursor(), (9, 2));
+    }
+
+    #[test]
+    fn dragging_selects_and_typing_replaces_the_selection() {
+        let mut terminal = HeadlessTerminal::new(30, 3);
+        let drag = mouse(MouseEventKind::Drag(MouseButton::Left), 5, 0);
+        run("hello world", &mut terminal, &[click(0, 0), drag, key(KeyCode::Char('X'), KeyModifiers::SHIFT)]);
+        assert_eq!(terminal.row_text(0), "X world");
+    }
+
+    #[test]
+    fn double_click_selects_the_token_and_triple_click_the_line() {
+        let mut terminal = HeadlessTerminal::new(30, 3);
+        run("hello world\nnext", &mut terminal, &[click(8, 0), click(8, 0), key(KeyCode::Char('X'), KeyModifiers::SHIFT)]);
+        assert_eq!(terminal.row_text(0), "hello X");
+
+        let mut terminal = HeadlessTerminal::new(30, 3);
+        run("hello world\nnext", &mut terminal, &[click(8, 0), click(8, 0), click(8, 0), key(KeyCode::Char('X'), KeyModifiers::SHIFT)]);
+        assert_eq!(terminal.row_text(0), "Xnext");
+    }
+
+    #[test]
+    fn matching_brackets_are_highlighted_and_jumped_to() {
+        let mut terminal = HeadlessTerminal::new(30, 3);
+        run("f(x) + 1", &mut terminal, &[key(KeyCode::Right, KeyModifiers::NONE)]);
+        assert_ne!(terminal.style_at(3, 0), terminal.style_at(2, 0));
+        assert_eq!(terminal.style_at(3, 0), terminal.style_at(1, 0));
+
+        let mut terminal = HeadlessTerminal::new(30, 3);
+        run("f(x) + 1", &mut terminal, &[key(KeyCode::Right, KeyModifiers::NONE), ctrl(']')]);
+        assert_eq!(terminal.cursor(), (3, 0));
+
+        let mut terminal = HeadlessTerminal::new(30, 3);
+        run_with(vi_config(), "f(x) + 1", &mut terminal, &typed("ld%"));
+        assert_eq!(terminal.row_text(0), "f + 1");
+    }
+
+    #[test]
+    fn enter_between_braces_indents_and_puts_the_closer_on_its_own_line() {
+        let mut terminal = HeadlessTerminal::new(40, 5);
+        let keys = [key(KeyCode::End, KeyModifiers::NONE), key(KeyCode::Left, KeyModifiers::NONE), key(KeyCode::Enter, KeyModifiers::NONE), key(KeyCode::Char('x'), KeyModifiers::NONE)];
+        run("  fn main() {}", &mut terminal, &keys);
+        assert_eq!(terminal.row_text(0), "  fn main() {");
+        assert_eq!(terminal.row_text(1), "      x");
+        assert_eq!(terminal.row_text(2), "  }");
+    }
+
+    #[test]
+    fn closing_bracket_dedents_to_its_opening_line() {
+        let mut terminal = HeadlessTerminal::new(40, 5);
+        let mut keys = vec![key(KeyCode::End, KeyModifiers::NONE), key(KeyCode::Enter, KeyModifiers::NONE)];
+        keys.extend(typed("a"));
+        keys.push(key(KeyCode::Enter, KeyModifiers::NONE));
+        keys.extend(typed("]"));
+        run("  let v = [", &mut terminal, &keys);
+        assert_eq!(terminal.row_text(1), "      a");
+        assert_eq!(terminal.row_text(2), "  ]");
+    }
+
+    #[test]
+    fn python_colons_indent_the_next_line() {
+        let mut terminal = HeadlessTerminal::new(40, 4);
+        terminal.push_event(key(KeyCode::End, KeyModifiers::NONE));
+        terminal.push_event(key(KeyCode::Enter, KeyModifiers::NONE));
+        push_quit(&mut terminal);
+        let buffer = Buffer::from_text(PathBuf::from("test.py"), "if x:");
+        program_loop(buffer, Config::default(), None, &mut terminal).unwrap();
+        assert_eq!(terminal.cursor(), (4, 1));
+        assert!(terminal.row_text(3).ends_with("python  LF  Ln 2, Col 5"));
+    }
+
+    #[test]
+    fn brackets_and_quotes_are_typed_in_pairs_and_over() {
+        let mut terminal = HeadlessTerminal::new(30, 3);
+        run("", &mut terminal, &typed("f(\"a\")"));
+        assert_eq!(terminal.row_text(0), "f(\"a\")");
+        assert_eq!(terminal.cursor(), (6, 0));
+
+        let mut terminal = HeadlessTerminal::new(30, 3);
+        run("", &mut terminal, &typed("f(\"a"));
+        assert_eq!(terminal.row_text(0), "f(\"a\")");
+
+        let mut terminal = HeadlessTerminal::new(30, 3);
+        run("", &mut terminal, &typed("don't ["));
+        assert_eq!(terminal.row_text(0), "don't []");
+    }
+
+    #[test]
+    fn backspace_in_an_empty_pair_deletes_both() {
+        let mut terminal = HeadlessTerminal::new(30, 3);
+        let mut keys = typed("x = {");
+        keys.push(key(KeyCode::Backspace, KeyModifiers::NONE));
+        run("", &mut terminal, &keys);
+        assert_eq!(terminal.row_text(0), "x =");
+        assert_eq!(terminal.cursor(), (4, 0));
+    }
+
+    #[test]
+    fn typing_an_opener_wraps_the_selection() {
+        let mut terminal = HeadlessTerminal::new(30, 3);
+        let drag = mouse(MouseEventKind::Drag(MouseButton::Left), 5, 0);
+        run("hello world", &mut terminal, &[click(0, 0), drag, key(KeyCode::Char('('), KeyModifiers::NONE), key(KeyCode::Char('['), KeyModifiers::NONE)]);
+        assert_eq!(terminal.row_text(0), "([hello]) world");
+    }
+
+    #[test]
+    fn auto_pairs_can_be_turned_off() {
+        let mut config = Config::default();
+        config.settings.auto_pairs = false;
+        let mut terminal = HeadlessTerminal::new(30, 3);
+        run_with(config, "", &mut terminal, &typed("f("));
+        assert_eq!(terminal.row_text(0), "f(");
+    }
+
+    #[test]
+    fn alt_arrows_move_lines_and_undo_in_one_step() {
+        let mut terminal = HeadlessTerminal::new(30, 5);
+        let alt_down = key(KeyCode::Down, KeyModifiers::ALT);
+        run("one\ntwo\nthree", &mut terminal, &[key(KeyCode::Right, KeyModifiers::NONE), alt_down.clone(), alt_down]);
+        assert_eq!(terminal.row_text(0), "two");
+        assert_eq!(terminal.row_text(2), "one");
+        assert_eq!(terminal.cursor(), (1, 2));
+
+        let mut terminal = HeadlessTerminal::new(30, 5);
+        run("one\ntwo\nthree", &mut terminal, &[key(KeyCode::Down, KeyModifiers::ALT), ctrl('z')]);
+        assert_eq!(terminal.row_text(0), "one");
+        assert_eq!(terminal.row_text(1), "two");
+    }
+
+    #[test]
+    fn line_commands_work_on_every_selected_line() {
+        let mut terminal = HeadlessTerminal::new(30, 6);
+        let drag = mouse(MouseEventKind::Drag(MouseButton::Left), 1, 1);
+        run("a\nb\nc", &mut terminal, &[click(0, 0), drag, ctrl('d')]);
+        let rows: Vec<String> = (0..5).map(|row| terminal.row_text(row)).collect();
+        assert_eq!(rows, ["a", "b", "a", "b", "c"]);
+        assert_eq!(terminal.cursor(), (1, 3));
+
+        let mut terminal = HeadlessTerminal::new(30, 6);
+        let drag = mouse(MouseEventKind::Drag(MouseButton::Left), 0, 1);
+        run("a\nb\nc", &mut terminal, &[click(0, 0), drag, key(KeyCode::Up, KeyModifiers::ALT)]);
+        assert_eq!(terminal.row_text(0), "a");
+
+        let mut terminal = HeadlessTerminal::new(30, 6);
+        let drag = mouse(MouseEventKind::Drag(MouseButton::Left), 0, 1);
+        run("a\nb\nc", &mut terminal, &[click(0, 1), drag, key(KeyCode::Char('k'), KeyModifiers::CONTROL | KeyModifiers::SHIFT)]);
+        assert_eq!(terminal.row_text(0), "a");
+        assert_eq!(terminal.row_text(1), "c");
+    }
+
+    #[test]
+    fn joining_lines_collapses_the_indentation() {
+        let mut terminal = HeadlessTerminal::new(30, 4);
+        run("if x {  \n    y();\n}", &mut terminal, &[ctrl('j'), ctrl('j')]);
+        assert_eq!(terminal.row_text(0), "if x { y(); }");
+        assert_eq!(terminal.cursor(), (11, 0));
+    }
+
+    #[test]
+    fn ctrl_slash_toggles_comments_at_the_smallest_indentation() {
+        let mut terminal = HeadlessTerminal::new(40, 5);
+        for event in [click(0, 0), mouse(MouseEventKind::Drag(MouseButton::Left), 2, 1), ctrl('/')] {
+            terminal.push_event(event);
+        }
+        push_quit(&mut terminal);
+        let buffer = Buffer::from_text(PathBuf::from("test.rs"), "    a\n  b\n  c");
+        program_loop(buffer, Config::default(), None, &mut terminal).unwrap();
+        assert_eq!(terminal.row_text(0), "  //   a");
+        assert_eq!(terminal.row_text(1), "  // b");
+        assert_eq!(terminal.row_text(2), "  c");
+        assert_eq!(terminal.cursor(), (5, 1));
+
+        let mut terminal = HeadlessTerminal::new(40, 5);
+        for event in [ctrl('7'), key(KeyCode::Down, KeyModifiers::NONE), ctrl('7')] {
+            terminal.push_event(event);
+        }
+        push_quit(&mut terminal);
+        let buffer = Buffer::from_text(PathBuf::from("test.py"), "  # a\n  b");
+        program_loop(buffer, Config::default(), None, &mut terminal).unwrap();
+        assert_eq!(terminal.row_text(0), "  a");
+        assert_eq!(terminal.row_text(1), "  # b");
+    }
+
+    #[test]
+    fn token_motion_keeps_identifiers_whole_and_alt_arrows_stop_inside_them() {
+        let mut terminal = HeadlessTerminal::new(30, 3);
+        run("my_var x", &mut terminal, &[key(KeyCode::Right, KeyModifiers::CONTROL)]);
+        assert_eq!(terminal.cursor(), (6, 0));
+
+        let mut terminal = HeadlessTerminal::new(30, 3);
+        let alt_right = key(KeyCode::Right, KeyModifiers::ALT);
+        run("my_var isCamel", &mut terminal, &[alt_right.clone(), alt_right.clone(), alt_right.clone(), alt_right]);
+        assert_eq!(terminal.cursor(), (9, 0));
+    }
+
+    #[test]
+    fn paragraphs_are_reflowed_selected_and_skipped() {
+        let mut config = Config::default();
+        config.settings.text_width = 12;
+        let mut terminal = HeadlessTerminal::new(30, 6);
+        run_with(config, "one two\nthree four five\n\nsix", &mut terminal, &[key(KeyCode::Char('q'), KeyModifiers::ALT)]);
+        let rows: Vec<String> = (0..5).map(|row| terminal.row_text(row)).collect();
+        assert_eq!(rows, ["one two", "three four", "five", "", "six"]);
+        assert_eq!(terminal.cursor(), (4, 2));
+
+        let mut terminal = HeadlessTerminal::new(30, 6);
+        let keys = [key(KeyCode::Char('}'), KeyModifiers::ALT), key(KeyCode::Down, KeyModifiers::NONE), key(KeyCode::Char('h'), KeyModifiers::ALT), key(KeyCode::Char('X'), KeyModifiers::SHIFT)];
+        run("a\nb\n\nc\nd", &mut terminal, &keys);
+        let rows: Vec<String> = (0..4).map(|row| terminal.row_text(row)).collect();
+        assert_eq!(rows, ["a", "b", "", "X"]);
+    }
+
+    #[test]
+    fn vi_sentence_motions_skip_to_the_next_sentence() {
+        let mut terminal = HeadlessTerminal::new(40, 3);
+        run_with(vi_config(), "One. Two! Three.", &mut terminal, &typed("))d("));
+        assert_eq!(terminal.row_text(0), "One. Three.");
+    }
+
+    #[test]
+    fn ctrl_o_and_alt_bracket_walk_the_jump_list() {
+        let contents: Vec<String> = (1..=30).map(|i| i.to_string()).collect();
+        let mut keys = vec![key(KeyCode::Down, KeyModifiers::NONE), key(KeyCode::End, KeyModifiers::CONTROL), ctrl('g')];
+        keys.extend(typed("10"));
+        keys.push(key(KeyCode::Enter, KeyModifiers::NONE));
+        let back = ctrl('o');
+        let forward = key(KeyCode::Char(']'), KeyModifiers::ALT);
+
+        let mut terminal = HeadlessTerminal::new(30, 6);
+        run(&contents.join("\n"), &mut terminal, &[keys.clone(), vec![back.clone()]].concat());
+        assert!(terminal.row_text(5).ends_with("Ln 30, Col 3"));
+
+        let mut terminal = HeadlessTerminal::new(30, 6);
+        run(&contents.join("\n"), &mut terminal, &[keys.clone(), vec![back.clone(), back.clone()]].concat());
+        assert!(terminal.row_text(5).ends_with("Ln 2, Col 1"));
+
+        let mut terminal = HeadlessTerminal::new(30, 6);
+        run(&contents.join("\n"), &mut terminal, &[keys, vec![back.clone(), back, forward.clone(), forward]].concat());
+        assert!(terminal.row_text(5).ends_with("Ln 10, Col 1"));
+    }
+
+    #[test]
+    fn named_marks_follow_the_text_through_edits_and_resizes() {
+        let mut terminal = HeadlessTerminal::new(40, 6);
+        terminal.push_event(key(KeyCode::End, KeyModifiers::CONTROL));
+        terminal.push_event(key(KeyCode::Char('m'), KeyModifiers::ALT));
+        terminal.push_event(key(KeyCode::Char('x'), KeyModifiers::NONE));
+        terminal.push_event(key(KeyCode::Home, KeyModifiers::CONTROL));
+        for event in typed("new") {
+            terminal.push_event(event);
+        }
+        terminal.push_event(key(KeyCode::Enter, KeyModifiers::NONE));
+        terminal.resize(26, 6);
+        run("a first line long enough to wrap\nb\nc", &mut terminal, &[key(KeyCode::Char('\''), KeyModifiers::ALT), key(KeyCode::Char('x'), KeyModifiers::NONE)]);
+        assert_eq!(terminal.row_text(2), " to wrap");
+        assert!(terminal.row_text(5).contains("Ln 4, Col 2"));
+
+        let mut terminal = HeadlessTerminal::new(50, 6);
+        run("a\nb", &mut terminal, &[key(KeyCode::Char('\''), KeyModifiers::ALT), key(KeyCode::Char('y'), KeyModifiers::NONE)]);
+        assert!(terminal.row_text(5).starts_with("Mark y is not set"));
+    }
+
+    #[test]
+    fn vi_marks_go_to_the_line_or_the_exact_position() {
+        let mut terminal = HeadlessTerminal::new(30, 4);
+        run_with(vi_config(), "  one two\nthree", &mut terminal, &typed("wwmaG'a"));
+        assert_eq!(terminal.cursor(), (2, 0));
+
+        let mut terminal = HeadlessTerminal::new(30, 4);
+        run_with(vi_config(), "  one two\nthree", &mut terminal, &typed("wwmaG`a"));
+        assert_eq!(terminal.cursor(), (6, 0));
+    }
+
+    #[test]
+    fn swap_files_are_recovered_compared_or_discarded() {
+        let swap_text = format!("fumaedit-swap pid={}\nchanged\ntext", std::process::id());
+
+        let mut terminal = HeadlessTerminal::new(60, 5);
+        let swap = run_with_swap("recover", Some(&swap_text), "original\ntext", &mut terminal, &typed("r"));
+        assert_eq!(terminal.row_text(0), "changed");
+        assert!(!swap.exists());
+
+        let mut terminal = HeadlessTerminal::new(60, 5);
+        let swap = run_with_swap("diff", Some(&swap_text), "original\ntext", &mut terminal, &typed("dq"));
+        assert!(terminal.row_text(0).trim_start().starts_with("Swap file found"));
+        assert!(terminal.row_text(1).trim_start().starts_with("- original"));
+        assert!(terminal.row_text(2).trim_start().starts_with("+ changed"));
+        assert_eq!(std::fs::read_to_string(&swap).unwrap(), swap_text);
+
+        let mut terminal = HeadlessTerminal::new(60, 5);
+        run_with_swap("discard", Some(&swap_text), "original\ntext", &mut terminal, &typed("dx"));
+        assert_eq!(terminal.row_text(0), "original");
+        assert!(terminal.row_text(4).starts_with("Discarded the swap file"));
+    }
+
+    #[test]
+    fn unchanged_files_write_their_swap_file_and_remove_it_on_exit() {
+        let mut terminal = HeadlessTerminal::new(60, 5);
+        let stale = format!("fumaedit-swap pid={}\nsame", std::process::id());
+        let swap = run_with_swap("unchanged", Some(&stale), "same", &mut terminal, &[]);
+        assert_eq!(terminal.row_text(0), "same");
+        assert!(!swap.exists());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn a_swap_file_of_a_running_instance_is_left_alone() {
+        let mut terminal = HeadlessTerminal::new(60, 5);
+        let other = format!("fumaedit-swap pid={}\nother", std::os::unix::process::parent_id());
+        let swap = run_with_swap("in-use", Some(&other), "text", &mut terminal, &typed("q"));
+        assert!(terminal.row_text(0).trim_start().starts_with(&format!("Open in FumaEdit process {}", std::os::unix::process::parent_id())));
+        assert_eq!(std::fs::read_to_string(&swap).unwrap(), other);
+    }
+
+    #[test]
+    fn unsaved_text_is_written_to_an_emergency_file_next_to_the_file() {
+        let dir = std::env::temp_dir().join(format!("fumaedit-emergency-{}", std::process::id()));
+        _ = std::fs::remove_dir_all(&dir);
+        std::fs::create_dir_all(&dir).unwrap();
+        let mut editor = Editor::new(Buffer::from_text(dir.join("notes.txt"), "old"), Config::default(), 40, 5);
+        assert_eq!(editor.write_emergency_file(), None);
+
+        editor.handle_key(KeyCode::Char('x'), KeyModifiers::NONE);
+        let note = editor.write_emergency_file().unwrap();
+        assert!(note.ends_with("notes.txt.emergency"));
+        assert_eq!(std::fs::read_to_string(dir.join("notes.txt.emergency")).unwrap(), "xold");
+        _ = std::fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,161 @@
+use std::collections::BTreeMap;
+use crate::buffer::Position;
+
+/// Positions kept in the jump list; the oldest are dropped first.
+const MAX_JUMPS: usize = 100;
+
+/// Named marks and the jump list. Both are logical positions, so they do not depend on how the text
+/// is wrapped, and they move along with the text when lines are inserted or deleted before them.
+#[derive(Clone, Default)]
+pub struct Marks {
+    named: BTreeMap<char, Position>,
+    jumps: Vec<Position>,
+    /// Index of the jump list entry last jumped to, or `jumps.len()` when not walking the list.
+    current: usize,
+}
+
+impl Marks {
+    pub fn set(&mut self, name: char, at: Position) {
+        self.named.insert(name, at);
+    }
+
+    pub fn get(&self, name: char) -> Option<Position> {
+        self.named.get(&name).copied()
+    }
+
+    /// Records where the cursor was before a jump. Jumping after walking back through the list drops
+    /// the entries after the current one, like a browser's history.
+    pub fn push_jump(&mut self, from: Position) {
+        self.jumps.truncate(self.current);
+        if self.jumps.last() != Some(&from) {
+            self.jumps.push(from);
+        }
+        if self.jumps.len() > MAX_JUMPS {
+            self.jumps.remove(0);
+        }
+        self.current = self.jumps.len();
+    }
+
+    /// The previous position in the jump list. When starting to walk back, `cursor` is recorded first
+    /// so jumping forward returns to it.
+    pub fn jump_back(&mut self, cursor: Position) -> Option<Position> {
+        if self.jumps.is_empty() {
+            return None;
+        }
+        if self.current == self.jumps.len() {
+            if self.jumps.last() != Some(&cursor) {
+                self.jumps.push(cursor);
+            }
+            self.current = self.jumps.len() - 1;
+        }
+        self.current = self.current.checked_sub(1)?;
+        Some(self.jumps[self.current])
+    }
+
+    pub fn jump_forward(&mut self) -> Option<Position> {
+        if self.current + 1 >= self.jumps.len() {
+            return None;
+        }
+        self.current += 1;
+        Some(self.jumps[self.current])
+    }
+
+    /// Moves the positions at or after `at` along with text inserted there, which ends at `end`.
+    pub fn inserted(&mut self, at: Position, end: Position) {
+        for position in self.positions_mut().filter(|position| **position >= at) {
+            *position = match position.line == at.line {
+                true => Position::new(end.line, end.col + position.col - at.col),
+                false => Position::new(position.line + end.line - at.line, position.col),
+            };
+        }
+    }
+
+    /// Moves the positions after the deleted range `[start, end)` back, and those inside it to `start`.
+    pub fn deleted(&mut self, start: Position, end: Position) {
+        for position in self.positions_mut().filter(|position| **position > start) {
+            *position = if *position < end {
+                start
+            } else if position.line == end.line {
+                Position::new(start.line, start.col + position.col - end.col)
+            } else {
+                Position::new(position.line - (end.line - start.line), position.col)
+            };
+        }
+    }
+
+    /// Moves the positions after lines `first..=last` along when those lines are replaced with `count`
+    /// others. Positions on the replaced lines stay where they are, or on the last new line.
+    pub fn replaced_lines(&mut self, first: usize, last: usize, count: usize) {
+        let new_last = (first + count).saturating_sub(1).max(first);
+        for position in self.positions_mut().filter(|position| position.line >= first) {
+            position.line = match position.line > last {
+                true => position.line + new_last - last,
+                false => position.line.min(new_last),
+            };
+        }
+    }
+
+    fn positions_mut(&mut self) -> impl Iterator<Item = &mut Position> {
+        self.named.values_mut().chain(self.jumps.iter_mut())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn marks_follow_inserted_and_deleted_text() {
+        let mut marks = Marks::default();
+        marks.set('a', Position::new(2, 4));
+        marks.set('b', Position::new(0, 1));
+
+        // Two lines typed in the middle of line 1 push line 2 down
+        marks.inserted(Position::new(1, 3), Position::new(3, 2));
+        assert_eq!(marks.get('a'), Some(Position::new(4, 4)));
+        assert_eq!(marks.get('b'), Some(Position::new(0, 1)));
+
+        // Text typed before the mark on its own line moves it right
+        marks.inserted(Position::new(4, 0), Position::new(4, 3));
+        assert_eq!(marks.get('a'), Some(Position::new(4, 7)));
+
+        marks.deleted(Position::new(1, 0), Position::new(3, 0));
+        assert_eq!(marks.get('a'), Some(Position::new(2, 7)));
+        marks.deleted(Position::new(2, 2), Position::new(2, 5));
+        assert_eq!(marks.get('a'), Some(Position::new(2, 4)));
+        marks.deleted(Position::new(1, 0), Position::new(2, 6));
+        assert_eq!(marks.get('a'), Some(Position::new(1, 0)));
+    }
+
+    #[test]
+    fn replacing_lines_shifts_the_marks_below() {
+        let mut marks = Marks::default();
+        marks.set('a', Position::new(10, 2));
+        marks.set('b', Position::new(4, 1));
+        marks.replaced_lines(3, 5, 1);
+        assert_eq!(marks.get('a'), Some(Position::new(8, 2)));
+        assert_eq!(marks.get('b'), Some(Position::new(3, 1)));
+    }
+
+    #[test]
+    fn jump_list_walks_back_and_forward() {
+        let mut marks = Marks::default();
+        marks.push_jump(Position::new(1, 0));
+        marks.push_jump(Position::new(5, 0));
+        let cursor = Position::new(9, 0);
+
+        assert_eq!(marks.jump_back(cursor), Some(Position::new(5, 0)));
+        assert_eq!(marks.jump_back(cursor), Some(Position::new(1, 0)));
+        assert_eq!(marks.jump_back(cursor), None);
+        assert_eq!(marks.jump_forward(), Some(Position::new(5, 0)));
+        assert_eq!(marks.jump_forward(), Some(cursor));
+        assert_eq!(marks.jump_forward(), None);
+
+        // A new jump from the middle of the list drops the entries after it
+        marks.jump_back(cursor);
+        marks.push_jump(Position::new(6, 0));
+        assert_eq!(marks.jump_forward(), None);
+        assert_eq!(marks.jump_back(Position::new(7, 0)), Some(Position::new(6, 0)));
+        assert_eq!(marks.jump_back(Position::new(7, 0)), Some(Position::new(1, 0)));
+    }
+}
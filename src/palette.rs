@@ -0,0 +1,135 @@
+use crossterm::event::{KeyCode, KeyModifiers};
+use crate::keymap::{Command, Keymap};
+
+pub enum PaletteAction {
+    None,
+    Run(Command),
+    Close,
+}
+
+/// Overlay listing every command, filtered as the user types.
+#[derive(Default)]
+pub struct Palette {
+    query: String,
+    selected: usize,
+}
+
+impl Palette {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn query(&self) -> &str {
+        &self.query
+    }
+
+    pub fn selected(&self) -> usize {
+        self.selected
+    }
+
+    /// Commands matching the query, best match first, with the keys bound to each.
+    pub fn matches(&self, keymap: &Keymap) -> Vec<(Command, String)> {
+        let mut scored: Vec<(i64, Command)> = Command::ALL.iter()
+            .filter_map(|&command| fuzzy_score(&self.query, command.name()).map(|score| (score, command)))
+            .collect();
+        scored.sort_by(|(a_score, a), (b_score, b)| b_score.cmp(a_score).then(a.name().cmp(b.name())));
+        scored.into_iter()
+            .map(|(_, command)| (command, keymap.keys_for(command).join(", ")))
+            .collect()
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode, modifiers: KeyModifiers, keymap: &Keymap) -> PaletteAction {
+        let match_count = self.matches(keymap).len();
+        match (code, modifiers) {
+            (KeyCode::Esc, _) => return PaletteAction::Close,
+            (KeyCode::Enter, _) => {
+                return match self.matches(keymap).get(self.selected) {
+                    Some(&(command, _)) => PaletteAction::Run(command),
+                    None => PaletteAction::Close,
+                };
+            }
+            (KeyCode::Up, _) | (KeyCode::Char('p'), KeyModifiers::CONTROL) => {
+                self.selected = self.selected.saturating_sub(1);
+            }
+            (KeyCode::Down, _) | (KeyCode::Char('n'), KeyModifiers::CONTROL) => {
+                self.selected = (self.selected + 1).min(match_count.saturating_sub(1));
+            }
+            (KeyCode::Backspace, _) => {
+                self.query.pop();
+                self.selected = 0;
+            }
+            (KeyCode::Char(c), KeyModifiers::NONE | KeyModifiers::SHIFT) => {
+                self.query.push(c);
+                self.selected = 0;
+            }
+            _ => {}
+        }
+        PaletteAction::None
+    }
+}
+
+/// Scores `candidate` if every non-space character of `query` appears in it in order, ignoring case.
+/// Consecutive matches and matches at the start of a word score higher; skipped characters cost a little.
+pub fn fuzzy_score(query: &str, candidate: &str) -> Option<i64> {
+    let candidate: Vec<char> = candidate.chars().flat_map(char::to_lowercase).collect();
+    let mut score = 0;
+    let mut position = 0;
+    let mut previous_match: Option<usize> = None;
+
+    for query_char in query.chars().filter(|c| !c.is_whitespace()).flat_map(char::to_lowercase) {
+        let found = position + candidate[position..].iter().position(|&c| c == query_char)?;
+        score += 1;
+        if previous_match.is_some_and(|previous| previous + 1 == found) {
+            score += 5;
+        }
+        if found == 0 || !candidate[found - 1].is_alphanumeric() {
+            score += 3;
+        }
+        score -= (found - position) as i64;
+        previous_match = Some(found);
+        position = found + 1;
+    }
+    Some(score)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn matches_subsequences_only() {
+        assert!(fuzzy_score("tw", "toggle-wrap").is_some());
+        assert!(fuzzy_score("TOG WR", "toggle-wrap").is_some());
+        assert!(fuzzy_score("wt", "toggle-wrap").is_none());
+    }
+
+    #[test]
+    fn word_starts_beat_scattered_matches() {
+        let word_starts = fuzzy_score("le", "line-end").unwrap();
+        let scattered = fuzzy_score("le", "reload-config").unwrap_or(i64::MIN);
+        assert!(word_starts > scattered);
+    }
+
+    #[test]
+    fn typing_filters_and_enter_runs_the_selection() {
+        let keymap = Keymap::default();
+        let mut palette = Palette::new();
+        for c in "togwrap".chars() {
+            palette.handle_key(KeyCode::Char(c), KeyModifiers::NONE, &keymap);
+        }
+        assert_eq!(palette.matches(&keymap)[0].0, Command::ToggleWrap);
+        assert!(matches!(
+            palette.handle_key(KeyCode::Enter, KeyModifiers::NONE, &keymap),
+            PaletteAction::Run(Command::ToggleWrap)
+        ));
+    }
+
+    #[test]
+    fn lists_bindings_next_to_commands() {
+        let keymap = Keymap::default();
+        let palette = Palette::new();
+        let matches = palette.matches(&keymap);
+        assert!(matches.contains(&(Command::Quit, "Ctrl+Q".to_string())));
+        assert!(matches.contains(&(Command::LineEndingsCrlf, String::new())));
+    }
+}
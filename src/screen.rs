@@ -1,52 +1,638 @@
 ﻿use std::io;
-use std::io::{stdout};
+use std::io::stdout;
+use std::sync::atomic::{AtomicBool, Ordering};
 use crossterm::cursor::{MoveTo, Show};
-use crossterm::{execute};
-use crossterm::style::Print;
-use crossterm::terminal::{disable_raw_mode, size, BeginSynchronizedUpdate, Clear, ClearType, EndSynchronizedUpdate};
+use crossterm::event::DisableMouseCapture;
+use crossterm::execute;
+use crossterm::style::{Attribute, Attributes, Color, ContentStyle};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode, Clear, ClearType, LeaveAlternateScreen};
+use crate::buffer::Position;
+use crate::config::{Settings, Theme, WrapMode};
 use crate::cursor::CursorPos;
+use crate::terminal::Terminal;
+use crate::utils::content_wrapper::glyph_width;
 
+/// Whether raw mode and the alternate screen are on, so `clean_screen` undoes only what was set up:
+/// a panic before the editor takes over the screen must not clear the shell's.
+static RAW_MODE: AtomicBool = AtomicBool::new(false);
+static ALTERNATE_SCREEN: AtomicBool = AtomicBool::new(false);
+
+pub fn set_raw_mode(on: bool) -> io::Result<()> {
+    match on {
+        true => enable_raw_mode()?,
+        false => disable_raw_mode()?,
+    }
+    RAW_MODE.store(on, Ordering::SeqCst);
+    Ok(())
+}
+
+/// Records that the alternate screen was entered or left.
+pub fn note_alternate_screen(on: bool) {
+    ALTERNATE_SCREEN.store(on, Ordering::SeqCst);
+}
+
+/// Leaves the alternate screen and raw mode, as far as they were entered. Safe to call more than once.
 pub fn clean_screen() -> io::Result<()>{
-    execute!(
-        stdout(),
-        MoveTo(0, 0),
-        Clear(ClearType::All),
-        Show
-    )?;
-    disable_raw_mode()?;
+    if ALTERNATE_SCREEN.swap(false, Ordering::SeqCst) {
+        execute!(
+            stdout(),
+            MoveTo(0, 0),
+            Clear(ClearType::All),
+            Show,
+            DisableMouseCapture,
+            LeaveAlternateScreen
+        )?;
+    }
+    if RAW_MODE.swap(false, Ordering::SeqCst) {
+        disable_raw_mode()?;
+    }
     Ok(())
 }
 
+#[derive(Clone, Copy, PartialEq)]
+pub enum Style {
+    Text,
+    Gutter,
+    StatusLine,
+    Overlay,
+    OverlaySelected,
+    Selection,
+    MatchingBracket,
+}
+
+fn content_style(theme: Theme, style: Style) -> ContentStyle {
+    let mut content = ContentStyle::new();
+    match (theme, style) {
+        (_, Style::Text) => {}
+        (Theme::Default, Style::Gutter) | (Theme::Dark, Style::Gutter) => content.foreground_color = Some(Color::DarkGrey),
+        (Theme::Light, Style::Gutter) => content.foreground_color = Some(Color::Grey),
+        (Theme::Default, Style::StatusLine) => content.attributes.set(Attribute::Reverse),
+        (Theme::Dark, Style::StatusLine) => {
+            content.foreground_color = Some(Color::White);
+            content.background_color = Some(Color::DarkBlue);
+        }
+        (Theme::Light, Style::StatusLine) => {
+            content.foreground_color = Some(Color::Black);
+            content.background_color = Some(Color::Grey);
+        }
+        (Theme::Default, Style::Overlay) => {
+            content.foreground_color = Some(Color::White);
+            content.background_color = Some(Color::DarkGrey);
+        }
+        (Theme::Default, Style::OverlaySelected) => {
+            content.foreground_color = Some(Color::Black);
+            content.background_color = Some(Color::Cyan);
+        }
+        (Theme::Dark, Style::Overlay) => {
+            content.foreground_color = Some(Color::White);
+            content.background_color = Some(Color::DarkBlue);
+        }
+        (Theme::Dark, Style::OverlaySelected) => {
+            content.foreground_color = Some(Color::DarkBlue);
+            content.background_color = Some(Color::White);
+        }
+        (Theme::Light, Style::Overlay) => {
+            content.foreground_color = Some(Color::Black);
+            content.background_color = Some(Color::Grey);
+        }
+        (Theme::Light, Style::OverlaySelected) => {
+            content.foreground_color = Some(Color::White);
+            content.background_color = Some(Color::DarkBlue);
+        }
+        (Theme::Default, Style::Selection) => content.attributes.set(Attribute::Reverse),
+        (Theme::Dark, Style::Selection) => {
+            content.foreground_color = Some(Color::White);
+            content.background_color = Some(Color::DarkBlue);
+        }
+        (Theme::Light, Style::Selection) => {
+            content.foreground_color = Some(Color::Black);
+            content.background_color = Some(Color::Cyan);
+        }
+        (Theme::Default, Style::MatchingBracket) => {
+            content.attributes.set(Attribute::Bold);
+            content.attributes.set(Attribute::Underlined);
+        }
+        (Theme::Dark, Style::MatchingBracket) => {
+            content.foreground_color = Some(Color::Yellow);
+            content.background_color = Some(Color::DarkGrey);
+        }
+        (Theme::Light, Style::MatchingBracket) => {
+            content.foreground_color = Some(Color::DarkBlue);
+            content.background_color = Some(Color::Grey);
+        }
+    }
+    content
+}
+
+/// Columns taken by the line-number gutter, including the space that separates it from the text.
+pub fn gutter_width(line_count: usize, settings: &Settings) -> usize {
+    if !settings.line_numbers {
+        return 0;
+    }
+    line_count.max(1).to_string().len() + 1
+}
+
+/// Screen column of the character at `x` once tabs are expanded to the next tab stop.
+pub fn display_col(line: &str, x: usize, tab_width: usize) -> usize {
+    line.chars().take(x).fold(0, |col, ch| col + char_width(ch, col, tab_width))
+}
+
+fn char_width(ch: char, col: usize, tab_width: usize) -> usize {
+    if ch == '\t' { tab_width - col % tab_width } else { glyph_width(ch) }
+}
+
+fn text_width(text: &str) -> usize {
+    text.chars().map(glyph_width).sum()
+}
+
+/// Index of the character drawn at display column `target`, or the line length past its end.
+fn char_at_display_col(line: &str, target: usize, tab_width: usize) -> usize {
+    let mut col = 0;
+    for (i, ch) in line.chars().enumerate() {
+        let width = char_width(ch, col, tab_width);
+        if target < col + width {
+            return i;
+        }
+        col += width;
+    }
+    line.chars().count()
+}
 
-pub fn draw_screen(contents: &str, cursor: &CursorPos) -> io::Result<()> {
-    let (_, terminal_rows) = size()?;
-    
-    execute!(stdout(), crossterm::cursor::Hide)?;
-    
-    execute!(
-        stdout(),
-        BeginSynchronizedUpdate,
-        Clear(ClearType::All),
-    )?;
-
-    let lines: Vec<&str> = contents.lines().collect();
-    let start = cursor.vertical_offset;
-    let end = (start + terminal_rows as usize).min(lines.len());
-    
-    for (i, line) in lines[start..end].iter().enumerate() {
-        execute!(stdout(), MoveTo(0, i as u16), Print(line))?;
-    }
-    
-    execute!(
-        stdout(),
-        MoveTo(cursor.x as u16, (cursor.y - cursor.vertical_offset) as u16),
-        Show,
-        EndSynchronizedUpdate
-    )?;
+pub struct StatusLine<'a> {
+    pub left: &'a str,
+    pub right: &'a str,
+}
+
+/// A list drawn over the top of the text, such as the command palette.
+pub struct Overlay<'a> {
+    pub prompt: &'a str,
+    /// Label and right-aligned detail of each entry.
+    pub items: &'a [(String, String)],
+    pub selected: usize,
+}
+
+const OVERLAY_MAX_WIDTH: usize = 60;
+
+/// Everything that goes on screen for one frame.
+pub struct View<'a> {
+    pub cursor: &'a CursorPos,
+    pub settings: &'a Settings,
+    pub status: StatusLine<'a>,
+    pub overlay: Option<Overlay<'a>>,
+    /// Selected text as a `[start, end)` range of logical positions.
+    pub selection: Option<(Position, Position)>,
+    /// The bracket at the cursor and its partner, highlighted together.
+    pub brackets: Option<(Position, Position)>,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+struct Cell {
+    ch: char,
+    style: ContentStyle,
+}
+
+impl Cell {
+    /// Stands in the column taken by the right half of a wide glyph, which the terminal fills by
+    /// itself when the glyph is printed.
+    const CONTINUATION: char = '\0';
+
+    const BLANK: Cell = Cell { ch: ' ', style: ContentStyle {
+        foreground_color: None,
+        background_color: None,
+        underline_color: None,
+        attributes: Attributes::none(),
+    } };
+}
+
+/// Snapshot of what is on the terminal, one cell per column and row.
+#[derive(Clone, PartialEq)]
+struct Frame {
+    cols: usize,
+    rows: usize,
+    cells: Vec<Cell>,
+}
+
+impl Frame {
+    fn new(cols: usize, rows: usize) -> Self {
+        Self {
+            cols,
+            rows,
+            cells: vec![Cell::BLANK; cols * rows],
+        }
+    }
+
+    fn put(&mut self, row: usize, col: usize, ch: char, style: ContentStyle) {
+        if row < self.rows && col < self.cols {
+            let index = row * self.cols + col;
+            // Writing over half of a wide glyph wipes out the other half, as on the terminal
+            if ch != Cell::CONTINUATION && self.cells[index].ch == Cell::CONTINUATION {
+                self.cells[index - 1].ch = ' ';
+            }
+            if col + 1 < self.cols && self.cells[index + 1].ch == Cell::CONTINUATION {
+                self.cells[index + 1].ch = ' ';
+            }
+            self.cells[index] = Cell { ch, style };
+        }
+    }
+
+    /// Puts a character that may be two columns wide, and returns how many columns it took. A wide
+    /// glyph cut off by the right edge leaves a blank instead.
+    fn put_glyph(&mut self, row: usize, col: usize, ch: char, style: ContentStyle) -> usize {
+        let width = glyph_width(ch);
+        if width == 2 && col + 1 >= self.cols {
+            self.put(row, col, ' ', style);
+        } else {
+            self.put(row, col, ch, style);
+            if width == 2 {
+                self.put(row, col + 1, Cell::CONTINUATION, style);
+            }
+        }
+        width
+    }
+
+    fn put_str(&mut self, row: usize, col: usize, text: &str, style: ContentStyle) {
+        let mut col = col;
+        for ch in text.chars() {
+            col += self.put_glyph(row, col, ch, style);
+        }
+    }
+
+    /// Writes a line of text starting at screen column `left`, expanding tabs and hiding
+    /// the first `skip` display columns. `style_for` gets the index of each character in `line`.
+    fn put_text(&mut self, row: usize, left: usize, line: &str, skip: usize, tab_width: usize, style_for: impl Fn(usize) -> ContentStyle) {
+        let mut display = 0;
+        for (index, ch) in line.chars().enumerate() {
+            let style = style_for(index);
+            let width = char_width(ch, display, tab_width);
+            if ch != '\t' && display >= skip {
+                self.put_glyph(row, left + display - skip, ch, style);
+            } else {
+                // Tabs, and wide glyphs half scrolled out of view, show as blanks
+                for col in display.max(skip)..display + width {
+                    self.put(row, left + col - skip, ' ', style);
+                }
+            }
+            display += width;
+            if display >= skip + self.cols {
+                break;
+            }
+        }
+    }
+
+    fn fill_row(&mut self, row: usize, style: ContentStyle) {
+        for col in 0..self.cols {
+            self.put(row, col, ' ', style);
+        }
+    }
+
+    /// Draws a row of `width` cells at `left` with `label` on the left and `detail` on the right.
+    fn put_entry(&mut self, row: usize, left: usize, width: usize, label: &str, detail: &str, style: ContentStyle) {
+        for col in left..left + width {
+            self.put(row, col, ' ', style);
+        }
+        let mut label_len = 0;
+        let label: String = label
+            .chars()
+            .take_while(|&ch| {
+                label_len += glyph_width(ch);
+                label_len <= width.saturating_sub(2)
+            })
+            .collect();
+        self.put_str(row, left + 1, &label, style);
+        let label_len = text_width(&label);
+        let detail_len = text_width(detail);
+        if label_len + detail_len + 3 <= width {
+            self.put_str(row, left + width - detail_len - 1, detail, style);
+        }
+    }
+
+    fn row(&self, row: usize) -> &[Cell] {
+        &self.cells[row * self.cols..(row + 1) * self.cols]
+    }
+}
 
+/// Keeps the last frame sent to the terminal so each redraw only emits the cells that changed.
+#[derive(Default)]
+pub struct Screen {
+    last_frame: Option<Frame>,
+    horizontal_offset: usize,
+}
+
+impl Screen {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Forgets what is on screen, so the next draw clears it and repaints every cell.
+    pub fn invalidate(&mut self) {
+        self.last_frame = None;
+    }
+
+    /// Wrapped row and character index drawn at a screen cell, reversing what `draw_screen` does.
+    /// Cells below the text map to the last row; the status line maps to nothing.
+    pub fn text_position(&self, cursor: &CursorPos, settings: &Settings, col: u16, row: u16) -> Option<(usize, usize)> {
+        let text_rows = self.last_frame.as_ref()?.rows.saturating_sub(1);
+        if row as usize >= text_rows {
+            return None;
+        }
+        let wrap_result = cursor.wrap_result();
+        let y = (cursor.vertical_offset + row as usize).min(wrap_result.row_count().saturating_sub(1));
+        let display = (col as usize).saturating_sub(gutter_width(wrap_result.line_count(), settings)) + self.horizontal_offset;
+        Some((y, char_at_display_col(wrap_result.row(y).unwrap_or(""), display, settings.tab_width)))
+    }
+
+    pub fn draw_screen(&mut self, terminal: &mut impl Terminal, view: &View) -> io::Result<()> {
+        let View { cursor, settings, status, .. } = view;
+        let wrap_result = cursor.wrap_result();
+        let (terminal_cols, terminal_rows) = terminal.size()?;
+        let mut frame = Frame::new(terminal_cols as usize, terminal_rows as usize);
+        let text_rows = frame.rows.saturating_sub(1);
+
+        let gutter = gutter_width(wrap_result.line_count(), settings);
+        let text_cols = frame.cols.saturating_sub(gutter).max(1);
+
+        let cursor_line = wrap_result.row(cursor.y).unwrap_or("");
+        let cursor_col = display_col(cursor_line, cursor.x, settings.tab_width);
+        self.horizontal_offset = match settings.wrap {
+            WrapMode::Soft => 0,
+            WrapMode::None if cursor_col < self.horizontal_offset => cursor_col,
+            WrapMode::None if cursor_col >= self.horizontal_offset + text_cols => cursor_col + 1 - text_cols,
+            WrapMode::None => self.horizontal_offset,
+        };
+
+        let text_style = content_style(settings.theme, Style::Text);
+        let gutter_style = content_style(settings.theme, Style::Gutter);
+        let selection_style = content_style(settings.theme, Style::Selection);
+        let bracket_style = content_style(settings.theme, Style::MatchingBracket);
+        let start = cursor.vertical_offset.min(wrap_result.row_count());
+        let end = (start + text_rows).min(wrap_result.row_count());
+
+        // Logical lines with a row on screen, from the one the top row belongs to
+        let first_line = wrap_result.line_of_row(start).unwrap_or(wrap_result.line_count());
+        for logical_line in first_line..wrap_result.line_count() {
+            let first_row = wrap_result.first_row(logical_line);
+            if first_row >= end {
+                break;
+            }
+            // Column, inside the logical line, of the first character of the row
+            let mut row_col = 0;
+            for (row, line) in (first_row..end).zip(wrap_result.line_rows(logical_line)) {
+                if row >= start {
+                    let i = row - start;
+                    if gutter > 0 && row_col == 0 {
+                        frame.put_str(i, 0, &format!("{:>width$} ", logical_line + 1, width = gutter - 1), gutter_style);
+                    }
+                    let style_for = |index: usize| {
+                        let position = Position::new(logical_line, row_col + index);
+                        match (view.selection, view.brackets) {
+                            (Some((from, to)), _) if from <= position && position < to => selection_style,
+                            (_, Some((a, b))) if position == a || position == b => bracket_style,
+                            _ => text_style,
+                        }
+                    };
+                    frame.put_text(i, gutter, line, self.horizontal_offset, settings.tab_width, style_for);
+                }
+                row_col += line.chars().count();
+            }
+        }
+
+        let status_style = content_style(settings.theme, Style::StatusLine);
+        frame.fill_row(text_rows, status_style);
+        frame.put_str(text_rows, 0, status.left, status_style);
+        let right_len = text_width(status.right);
+        frame.put_str(text_rows, frame.cols.saturating_sub(right_len + 1), status.right, status_style);
+
+        let mut screen_x = gutter + cursor_col - self.horizontal_offset;
+        let mut screen_y = cursor.y.saturating_sub(cursor.vertical_offset);
+        // The mouse wheel can scroll the cursor out of view
+        let mut cursor_shown = (cursor.vertical_offset..cursor.vertical_offset + text_rows).contains(&cursor.y);
+
+        if let Some(overlay) = &view.overlay {
+            let width = frame.cols.min(OVERLAY_MAX_WIDTH);
+            let left = (frame.cols - width) / 2;
+            let overlay_style = content_style(settings.theme, Style::Overlay);
+            let selected_style = content_style(settings.theme, Style::OverlaySelected);
+
+            frame.put_entry(0, left, width, overlay.prompt, "", overlay_style);
+            let max_items = text_rows.saturating_sub(1);
+            let first = (overlay.selected + 1).saturating_sub(max_items);
+            for (i, (label, detail)) in overlay.items.iter().enumerate().skip(first).take(max_items) {
+                let style = if i == overlay.selected { selected_style } else { overlay_style };
+                frame.put_entry(1 + i - first, left, width, label, detail, style);
+            }
+            screen_x = (left + 1 + text_width(overlay.prompt)).min(frame.cols.saturating_sub(1));
+            screen_y = 0;
+            cursor_shown = true;
+        }
+
+        terminal.hide_cursor()?;
+        terminal.begin_update()?;
+
+        match &self.last_frame {
+            Some(last) if last.cols == frame.cols && last.rows == frame.rows => {
+                for row in 0..frame.rows {
+                    queue_row_changes(terminal, row, last.row(row), frame.row(row))?;
+                }
+            }
+            // First frame or the terminal was resized: nothing on screen can be trusted
+            _ => {
+                terminal.clear()?;
+                let blank = vec![Cell::BLANK; frame.cols];
+                for row in 0..frame.rows {
+                    queue_row_changes(terminal, row, &blank, frame.row(row))?;
+                }
+            }
+        }
+
+        terminal.set_style(ContentStyle::default())?;
+        terminal.move_to(screen_x as u16, screen_y as u16)?;
+        if cursor_shown {
+            terminal.show_cursor()?;
+        }
+        terminal.end_update()?;
+        terminal.flush()?;
+
+        self.last_frame = Some(frame);
+        Ok(())
+    }
+}
+
+/// Queues a print for every run of cells that differs between the old and the new row.
+fn queue_row_changes(terminal: &mut impl Terminal, row: usize, old: &[Cell], new: &[Cell]) -> io::Result<()> {
+    let mut col = 0;
+    while col < new.len() {
+        if old[col] == new[col] {
+            col += 1;
+            continue;
+        }
+        let run_start = col;
+        let style = new[col].style;
+        let mut run = String::new();
+        while col < new.len() && old[col] != new[col] && new[col].style == style {
+            // Printing the wide glyph before it already filled this column
+            if new[col].ch != Cell::CONTINUATION {
+                run.push(new[col].ch);
+            }
+            col += 1;
+        }
+        terminal.move_to(run_start as u16, row as u16)?;
+        terminal.set_style(style)?;
+        terminal.print(&run)?;
+    }
     Ok(())
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::terminal::HeadlessTerminal;
+    use crate::utils::content_wrapper::wrap_content;
+    use crate::utils::tokenizer::TokenCache;
+
+    const STATUS: StatusLine = StatusLine { left: "", right: "" };
+
+    fn cursor_for(text: &str, width: usize) -> CursorPos {
+        let wrap_result = wrap_content(text, width);
+        let tokens = TokenCache::new(&wrap_result, "_", false);
+        CursorPos::new(wrap_result, tokens, 3)
+    }
+
+    fn view<'a>(cursor: &'a CursorPos, settings: &'a Settings) -> View<'a> {
+        View { cursor, settings, status: STATUS, overlay: None, selection: None, brackets: None }
+    }
+
+    fn draw(screen: &mut Screen, terminal: &mut HeadlessTerminal, text: &str) {
+        let cursor = cursor_for(text, 20);
+        let settings = Settings::default();
+        screen.draw_screen(terminal, &view(&cursor, &settings)).unwrap();
+    }
+
+    #[test]
+    fn redraw_only_prints_changed_cells() {
+        let mut terminal = HeadlessTerminal::new(20, 3);
+        let mut screen = Screen::new();
+        draw(&mut screen, &mut terminal, "abc\ndef");
+        terminal.take_printed();
+
+        draw(&mut screen, &mut terminal, "abc\ndXf");
+        assert_eq!(terminal.take_printed(), vec!["X"]);
+        assert_eq!(terminal.row_text(1), "dXf");
+    }
+
+    #[test]
+    fn unchanged_frame_prints_nothing() {
+        let mut terminal = HeadlessTerminal::new(20, 3);
+        let mut screen = Screen::new();
+        draw(&mut screen, &mut terminal, "abc");
+        terminal.take_printed();
 
+        draw(&mut screen, &mut terminal, "abc");
+        assert!(terminal.take_printed().is_empty());
+    }
 
+    #[test]
+    fn shorter_line_blanks_the_leftover_cells() {
+        let mut terminal = HeadlessTerminal::new(20, 3);
+        let mut screen = Screen::new();
+        draw(&mut screen, &mut terminal, "abcdef");
+        draw(&mut screen, &mut terminal, "ab");
+        assert_eq!(terminal.row_text(0), "ab");
+    }
 
+    #[test]
+    fn tabs_expand_to_the_next_tab_stop() {
+        let mut terminal = HeadlessTerminal::new(20, 3);
+        let mut screen = Screen::new();
+        draw(&mut screen, &mut terminal, "a\tb");
+        assert_eq!(terminal.row_text(0), "a   b");
+        assert_eq!(display_col("a\tb", 2, 4), 4);
+    }
+
+    #[test]
+    fn wide_glyphs_take_two_columns() {
+        let mut terminal = HeadlessTerminal::new(20, 3);
+        let mut screen = Screen::new();
+        draw(&mut screen, &mut terminal, "日本x");
+        assert_eq!(terminal.row_text(0), "日本x");
+        assert_eq!(display_col("日本x", 2, 4), 4);
+        assert_eq!(char_at_display_col("日本x", 3, 4), 1);
+
+        // The redraw starts from where the glyphs really are on the terminal
+        draw(&mut screen, &mut terminal, "ab本y");
+        assert_eq!(terminal.row_text(0), "ab本y");
+        draw(&mut screen, &mut terminal, "a日y");
+        assert_eq!(terminal.row_text(0), "a日y");
+    }
+
+    #[test]
+    fn wide_glyph_cut_off_by_the_edge_leaves_a_blank() {
+        let mut frame = Frame::new(3, 1);
+        frame.put_str(0, 0, "ab日", ContentStyle::default());
+        assert_eq!(frame.cells.iter().map(|cell| cell.ch).collect::<String>(), "ab ");
+
+        // Writing over half of a glyph wipes out the rest of it
+        frame.put_str(0, 0, "日", ContentStyle::default());
+        frame.put(0, 1, 'c', ContentStyle::default());
+        assert_eq!(frame.cells.iter().map(|cell| cell.ch).collect::<String>(), " c ");
+    }
+
+    #[test]
+    fn status_line_uses_the_theme_style() {
+        let mut terminal = HeadlessTerminal::new(20, 3);
+        let mut screen = Screen::new();
+        let cursor = cursor_for("abc", 20);
+        let settings = Settings::default();
+        let view = View { status: StatusLine { left: "saved", right: "Ln 1" }, ..view(&cursor, &settings) };
+        screen.draw_screen(&mut terminal, &view).unwrap();
+        assert_eq!(terminal.row_text(2), "saved          Ln 1");
+        assert!(terminal.style_at(0, 2).attributes.has(Attribute::Reverse));
+        assert!(!terminal.style_at(0, 0).attributes.has(Attribute::Reverse));
+    }
+
+    #[test]
+    fn overlay_is_drawn_over_the_text() {
+        let mut terminal = HeadlessTerminal::new(20, 4);
+        let mut screen = Screen::new();
+        let cursor = cursor_for("one\ntwo\nthree", 20);
+        let items = vec![("quit".to_string(), "Ctrl+Q".to_string()), ("save".to_string(), String::new())];
+        let settings = Settings::default();
+        let overlay = Overlay { prompt: "> q", items: &items, selected: 1 };
+        let view = View { overlay: Some(overlay), ..view(&cursor, &settings) };
+        screen.draw_screen(&mut terminal, &view).unwrap();
+        assert_eq!(terminal.row_text(0), " > q");
+        assert_eq!(terminal.row_text(1), " quit        Ctrl+Q");
+        assert_eq!(terminal.row_text(2), " save");
+        assert_eq!(terminal.style_at(1, 2).background_color, Some(Color::Cyan));
+        assert_eq!(terminal.cursor(), (4, 0));
+    }
+
+    #[test]
+    fn overlay_measures_wide_labels_by_display_width() {
+        let mut terminal = HeadlessTerminal::new(12, 3);
+        let mut screen = Screen::new();
+        let cursor = cursor_for("", 12);
+        let items = vec![("日本語の名前".to_string(), "F1".to_string())];
+        let settings = Settings::default();
+        let overlay = Overlay { prompt: "> 日本", items: &items, selected: 0 };
+        let view = View { overlay: Some(overlay), ..view(&cursor, &settings) };
+        screen.draw_screen(&mut terminal, &view).unwrap();
+        assert_eq!(terminal.row_text(1), " 日本語の名");
+        assert_eq!(terminal.cursor(), (7, 0));
+    }
+
+    #[test]
+    fn selection_follows_wrapped_rows() {
+        let mut terminal = HeadlessTerminal::new(6, 4);
+        let mut screen = Screen::new();
+        let cursor = cursor_for("abcdefgh", 6);
+        let settings = Settings::default();
+        let view = View { selection: Some((Position::new(0, 3), Position::new(0, 5))), ..view(&cursor, &settings) };
+        screen.draw_screen(&mut terminal, &view).unwrap();
+
+        let selected = |col, row| terminal.style_at(col, row).attributes.has(Attribute::Reverse);
+        assert!(!selected(2, 0));
+        assert!(selected(3, 0));
+        assert!(selected(0, 1));
+        assert!(!selected(1, 1));
+    }
+}
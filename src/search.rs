@@ -0,0 +1,80 @@
+use crate::buffer::{Buffer, Position};
+
+/// An incremental search in progress.
+pub struct Search {
+    pub query: String,
+    pub forward: bool,
+    /// Cursor position before the search, restored when it is cancelled.
+    pub origin: Position,
+    /// Range of the current match, if the query was found.
+    pub found: Option<(Position, Position)>,
+}
+
+impl Search {
+    pub fn new(origin: Position, forward: bool) -> Self {
+        Self { query: String::new(), forward, origin, found: None }
+    }
+
+    /// Text for the status line, like Emacs' isearch prompt.
+    pub fn prompt(&self) -> String {
+        let failing = if self.found.is_none() && !self.query.is_empty() { "Failing " } else { "" };
+        let direction = if self.forward { "I-search" } else { "I-search backward" };
+        format!("{}{}: {}", failing, direction, self.query)
+    }
+}
+
+/// Finds the first match starting at or after `from`, or the last one starting at or before it when
+/// searching backward, wrapping around the text. Case is ignored unless the query has an uppercase letter.
+pub fn find(buffer: &Buffer, query: &str, from: Position, forward: bool) -> Option<(Position, Position)> {
+    let text: Vec<char> = buffer.text().chars().collect();
+    let query: Vec<char> = query.chars().collect();
+    if query.is_empty() || query.len() > text.len() {
+        return None;
+    }
+    let ignore_case = !query.iter().any(|c| c.is_uppercase());
+    let matches_at = |start: usize| {
+        query.iter().zip(&text[start..]).all(|(&q, &t)| q == t || (ignore_case && t.to_lowercase().eq(q.to_lowercase())))
+    };
+
+    let last_start = text.len() - query.len();
+    let from = buffer.offset_of(from).min(last_start);
+    let start = if forward {
+        (from..=last_start).chain(0..from).find(|&start| matches_at(start))
+    } else {
+        (0..=from).rev().chain((from + 1..=last_start).rev()).find(|&start| matches_at(start))
+    }?;
+    Some((buffer.position_of(start), buffer.position_of(start + query.len())))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::PathBuf;
+
+    fn buffer(text: &str) -> Buffer {
+        Buffer::from_text(PathBuf::from("a.txt"), text)
+    }
+
+    #[test]
+    fn finds_forward_and_wraps_around() {
+        let buffer = buffer("foo bar\nfoo baz");
+        assert_eq!(find(&buffer, "foo", Position::new(0, 1), true), Some((Position::new(1, 0), Position::new(1, 3))));
+        assert_eq!(find(&buffer, "bar", Position::new(1, 0), true), Some((Position::new(0, 4), Position::new(0, 7))));
+        assert_eq!(find(&buffer, "qux", Position::new(0, 0), true), None);
+    }
+
+    #[test]
+    fn finds_backward_from_the_cursor() {
+        let buffer = buffer("foo bar\nfoo baz");
+        assert_eq!(find(&buffer, "foo", Position::new(1, 2), false), Some((Position::new(1, 0), Position::new(1, 3))));
+        assert_eq!(find(&buffer, "baz", Position::new(0, 2), false), Some((Position::new(1, 4), Position::new(1, 7))));
+    }
+
+    #[test]
+    fn uppercase_in_the_query_makes_it_case_sensitive() {
+        let buffer = buffer("Foo foo");
+        assert_eq!(find(&buffer, "foo", Position::new(0, 0), true), Some((Position::new(0, 0), Position::new(0, 3))));
+        assert_eq!(find(&buffer, "foo", Position::new(0, 1), true), Some((Position::new(0, 4), Position::new(0, 7))));
+        assert_eq!(find(&buffer, "Foo", Position::new(0, 1), true), Some((Position::new(0, 0), Position::new(0, 3))));
+    }
+}
@@ -0,0 +1,43 @@
+use std::io;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock};
+
+/// Set by SIGTERM and SIGHUP. The main loop checks it between events, so the work of exiting happens
+/// outside the signal handler.
+static TERMINATE: LazyLock<Arc<AtomicBool>> = LazyLock::new(|| Arc::new(AtomicBool::new(false)));
+/// Set by SIGTSTP sent from outside, such as `kill -TSTP`. Ctrl+Z in raw mode comes in as a key instead.
+static SUSPEND: LazyLock<Arc<AtomicBool>> = LazyLock::new(|| Arc::new(AtomicBool::new(false)));
+/// Set by SIGCONT, after the editor was stopped by any means.
+static CONTINUED: LazyLock<Arc<AtomicBool>> = LazyLock::new(|| Arc::new(AtomicBool::new(false)));
+
+/// Makes SIGTERM and SIGHUP ask the editor to exit, so it can save unsaved text and restore the terminal
+/// instead of dying on the spot, and SIGTSTP ask it to hand the terminal back before it stops.
+#[cfg(unix)]
+pub fn register() -> io::Result<()> {
+    use signal_hook::consts::{SIGCONT, SIGHUP, SIGTERM, SIGTSTP};
+    for signal in [SIGTERM, SIGHUP] {
+        signal_hook::flag::register(signal, Arc::clone(&TERMINATE))?;
+    }
+    signal_hook::flag::register(SIGTSTP, Arc::clone(&SUSPEND))?;
+    signal_hook::flag::register(SIGCONT, Arc::clone(&CONTINUED))?;
+    Ok(())
+}
+
+#[cfg(not(unix))]
+pub fn register() -> io::Result<()> {
+    Ok(())
+}
+
+pub fn termination_requested() -> bool {
+    TERMINATE.load(Ordering::Relaxed)
+}
+
+/// Whether SIGTSTP arrived since the last call.
+pub fn take_suspend_request() -> bool {
+    SUSPEND.swap(false, Ordering::Relaxed)
+}
+
+/// Whether SIGCONT arrived since the last call.
+pub fn take_continued() -> bool {
+    CONTINUED.swap(false, Ordering::Relaxed)
+}
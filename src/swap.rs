@@ -0,0 +1,140 @@
+use std::fs;
+use std::io;
+use std::path::{self, Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+
+/// First line of every swap file, followed by the process id of the editor that wrote it.
+const HEADER: &str = "fumaedit-swap pid=";
+
+/// Copy of the text being edited, written while it has unsaved changes so they can be recovered after
+/// a crash or a closed terminal. The file also tells other FumaEdit instances that the file is open.
+pub struct SwapFile {
+    path: PathBuf,
+    /// Buffer version last written. `None` until this editor has taken over the swap file.
+    written: Option<usize>,
+}
+
+/// A swap file found on disk.
+pub struct SwapContents {
+    pub pid: u32,
+    pub text: String,
+    pub modified: Option<SystemTime>,
+}
+
+impl SwapFile {
+    /// The swap file for `file` in the user's state directory, or `None` when there is no such directory.
+    pub fn for_file(file: &Path) -> Option<Self> {
+        let dir = dirs::state_dir().or_else(dirs::data_local_dir)?;
+        Some(Self::in_dir(&dir.join("fumaedit").join("swap"), file))
+    }
+
+    pub fn in_dir(dir: &Path, file: &Path) -> Self {
+        Self { path: dir.join(swap_name(file)), written: None }
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// The swap file left on disk, by another instance or by one that did not exit cleanly.
+    pub fn read(&self) -> Option<SwapContents> {
+        let contents = fs::read_to_string(&self.path).ok()?;
+        let (header, text) = contents.split_once('\n')?;
+        let pid = header.strip_prefix(HEADER)?.parse().ok()?;
+        let modified = fs::metadata(&self.path).and_then(|m| m.modified()).ok();
+        Some(SwapContents { pid, text: text.to_string(), modified })
+    }
+
+    /// Writes `text` unless `version` is already on disk. The text goes to a temporary file first, so a
+    /// crash while writing leaves the previous copy intact.
+    pub fn write(&mut self, text: &str, version: usize) -> io::Result<()> {
+        if self.written == Some(version) {
+            return Ok(());
+        }
+        if let Some(dir) = self.path.parent() {
+            fs::create_dir_all(dir)?;
+        }
+        let temporary = self.path.with_extension("swp.tmp");
+        fs::write(&temporary, format!("{}{}\n{}", HEADER, process::id(), text))?;
+        fs::rename(&temporary, &self.path)?;
+        self.written = Some(version);
+        Ok(())
+    }
+
+    /// Deletes the swap file, if this editor wrote it.
+    pub fn remove(&mut self) {
+        if self.written.take().is_some() {
+            _ = fs::remove_file(&self.path);
+        }
+    }
+}
+
+/// Swap file name for `file`: its absolute path with the separators replaced, so files with the same
+/// name in different directories get different swap files.
+fn swap_name(file: &Path) -> String {
+    let absolute = path::absolute(file).unwrap_or_else(|_| file.to_path_buf());
+    let name: String = absolute.to_string_lossy().chars()
+        .map(|c| if matches!(c, '/' | '\\' | ':') { '%' } else { c })
+        .collect();
+    format!("{}.swp", name)
+}
+
+/// Where unsaved text goes when the editor has to exit at once: next to the file, with a suffix that
+/// does not overwrite an earlier emergency file.
+pub fn emergency_path(file: &Path) -> PathBuf {
+    let name = file.file_name().map_or_else(|| "fumaedit".into(), |name| name.to_string_lossy().into_owned());
+    let candidate = |n: usize| match n {
+        0 => file.with_file_name(format!("{}.emergency", name)),
+        n => file.with_file_name(format!("{}.emergency.{}", name, n)),
+    };
+    (0..).map(candidate).find(|path| !path.exists()).expect("some suffix is free")
+}
+
+/// Whether a process with this id is running, such as the editor that wrote a swap file.
+#[cfg(unix)]
+pub fn is_running(pid: u32) -> bool {
+    let Ok(pid) = libc::pid_t::try_from(pid) else {
+        return false;
+    };
+    // Signal 0 only checks that the process exists; 0 and negative ids would address process groups
+    pid > 0 && (unsafe { libc::kill(pid, 0) } == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM))
+}
+
+#[cfg(not(unix))]
+pub fn is_running(_pid: u32) -> bool {
+    false
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn writes_and_reads_back_the_text_with_the_process_id() {
+        let dir = std::env::temp_dir().join(format!("fumaedit-swap-test-{}", process::id()));
+        let mut swap = SwapFile::in_dir(&dir, Path::new("/home/user/notes.txt"));
+        assert!(swap.path().ends_with("%home%user%notes.txt.swp"));
+        assert!(swap.read().is_none());
+
+        swap.write("one\ntwo", 3).unwrap();
+        let found = swap.read().unwrap();
+        assert_eq!((found.pid, found.text.as_str()), (process::id(), "one\ntwo"));
+        assert!(is_running(found.pid));
+
+        swap.remove();
+        assert!(swap.read().is_none());
+        _ = fs::remove_dir(&dir);
+    }
+
+    #[test]
+    fn emergency_files_do_not_overwrite_earlier_ones() {
+        let dir = std::env::temp_dir().join(format!("fumaedit-emergency-test-{}", process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        let file = dir.join("notes.txt");
+        assert_eq!(emergency_path(&file), dir.join("notes.txt.emergency"));
+        fs::write(dir.join("notes.txt.emergency"), "earlier").unwrap();
+        assert_eq!(emergency_path(&file), dir.join("notes.txt.emergency.1"));
+        _ = fs::remove_dir_all(&dir);
+    }
+}
@@ -0,0 +1,318 @@
+#[cfg(test)]
+use std::collections::VecDeque;
+use std::io;
+use std::io::{stdout, Stdout, Write};
+use std::time::Duration;
+use crossterm::cursor::{Hide, MoveTo, Show};
+use crossterm::event::{EnableMouseCapture, Event};
+use crossterm::style::{Attribute, ContentStyle, Print, SetAttribute, SetStyle};
+use crossterm::terminal::{BeginSynchronizedUpdate, Clear, ClearType, EndSynchronizedUpdate, EnterAlternateScreen};
+use crossterm::{event, queue};
+#[cfg(unix)]
+use crossterm::event::DisableMouseCapture;
+#[cfg(unix)]
+use crossterm::execute;
+#[cfg(unix)]
+use crossterm::terminal::LeaveAlternateScreen;
+use crate::screen::note_alternate_screen;
+#[cfg(test)]
+use crate::utils::content_wrapper::glyph_width;
+#[cfg(unix)]
+use crate::screen::set_raw_mode;
+
+/// Everything the editor needs from a terminal. Drawing calls are buffered until `flush`.
+pub trait Terminal {
+    fn size(&self) -> io::Result<(u16, u16)>;
+    fn poll_event(&mut self, timeout: Duration) -> io::Result<Option<Event>>;
+    fn enter_alternate_screen(&mut self) -> io::Result<()>;
+    fn begin_update(&mut self) -> io::Result<()>;
+    fn end_update(&mut self) -> io::Result<()>;
+    fn clear(&mut self) -> io::Result<()>;
+    fn move_to(&mut self, col: u16, row: u16) -> io::Result<()>;
+    fn print(&mut self, text: &str) -> io::Result<()>;
+    fn set_style(&mut self, style: ContentStyle) -> io::Result<()>;
+    fn show_cursor(&mut self) -> io::Result<()>;
+    fn hide_cursor(&mut self) -> io::Result<()>;
+    fn flush(&mut self) -> io::Result<()>;
+    /// Hands the terminal back to the shell and stops the process until it is continued, then takes
+    /// the terminal over again. Whatever was on screen has to be redrawn afterwards.
+    fn suspend(&mut self) -> io::Result<()>;
+}
+
+pub struct CrosstermTerminal {
+    out: Stdout,
+}
+
+impl CrosstermTerminal {
+    pub fn new() -> Self {
+        Self { out: stdout() }
+    }
+}
+
+impl Default for CrosstermTerminal {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Terminal for CrosstermTerminal {
+    fn size(&self) -> io::Result<(u16, u16)> {
+        crossterm::terminal::size()
+    }
+
+    fn poll_event(&mut self, timeout: Duration) -> io::Result<Option<Event>> {
+        if event::poll(timeout)? {
+            return Ok(Some(event::read()?));
+        }
+        Ok(None)
+    }
+
+    fn enter_alternate_screen(&mut self) -> io::Result<()> {
+        queue!(self.out, EnterAlternateScreen, EnableMouseCapture, Hide)?;
+        note_alternate_screen(true);
+        Ok(())
+    }
+
+    fn begin_update(&mut self) -> io::Result<()> {
+        queue!(self.out, BeginSynchronizedUpdate)
+    }
+
+    fn end_update(&mut self) -> io::Result<()> {
+        queue!(self.out, EndSynchronizedUpdate)
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        queue!(self.out, Clear(ClearType::All))
+    }
+
+    fn move_to(&mut self, col: u16, row: u16) -> io::Result<()> {
+        queue!(self.out, MoveTo(col, row))
+    }
+
+    fn print(&mut self, text: &str) -> io::Result<()> {
+        queue!(self.out, Print(text))
+    }
+
+    fn set_style(&mut self, style: ContentStyle) -> io::Result<()> {
+        queue!(self.out, SetAttribute(Attribute::Reset), SetStyle(style))
+    }
+
+    fn show_cursor(&mut self) -> io::Result<()> {
+        queue!(self.out, Show)
+    }
+
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        queue!(self.out, Hide)
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.out.flush()
+    }
+
+    #[cfg(unix)]
+    fn suspend(&mut self) -> io::Result<()> {
+        execute!(self.out, DisableMouseCapture, Show, LeaveAlternateScreen)?;
+        note_alternate_screen(false);
+        set_raw_mode(false)?;
+        // SIGTSTP only sets a flag once it is registered, so this stops the process the way its default
+        // action would, with SIGSTOP, and returns once the shell continues it with SIGCONT
+        signal_hook::low_level::emulate_default_handler(signal_hook::consts::SIGTSTP)?;
+        set_raw_mode(true)?;
+        execute!(self.out, EnterAlternateScreen, EnableMouseCapture, Hide)?;
+        note_alternate_screen(true);
+        Ok(())
+    }
+
+    #[cfg(not(unix))]
+    fn suspend(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// In-memory terminal for tests: events are fed from a queue and output lands in a character grid.
+#[cfg(test)]
+pub struct HeadlessTerminal {
+    cols: u16,
+    rows: u16,
+    grid: Vec<char>,
+    styles: Vec<ContentStyle>,
+    style: ContentStyle,
+    cursor: (u16, u16),
+    cursor_visible: bool,
+    events: VecDeque<Event>,
+    printed: Vec<String>,
+    suspensions: usize,
+    size_after_suspend: Option<(u16, u16)>,
+}
+
+#[cfg(test)]
+impl HeadlessTerminal {
+    pub fn new(cols: u16, rows: u16) -> Self {
+        Self {
+            cols,
+            rows,
+            grid: vec![' '; cols as usize * rows as usize],
+            styles: vec![ContentStyle::default(); cols as usize * rows as usize],
+            style: ContentStyle::default(),
+            cursor: (0, 0),
+            cursor_visible: true,
+            events: VecDeque::new(),
+            printed: Vec::new(),
+            suspensions: 0,
+            size_after_suspend: None,
+        }
+    }
+
+    pub fn push_event(&mut self, event: Event) {
+        self.events.push_back(event);
+    }
+
+    /// Queues a resize event. Like a real terminal, the size changes when the event is read, after the
+    /// events queued before it.
+    pub fn resize(&mut self, cols: u16, rows: u16) {
+        self.events.push_back(Event::Resize(cols, rows));
+    }
+
+    /// Makes the next suspension come back at a different size without a resize event, like a
+    /// terminal resized while the editor was stopped.
+    pub fn resize_while_suspended(&mut self, cols: u16, rows: u16) {
+        self.size_after_suspend = Some((cols, rows));
+    }
+
+    pub fn suspensions(&self) -> usize {
+        self.suspensions
+    }
+
+    fn set_size(&mut self, cols: u16, rows: u16) {
+        self.cols = cols;
+        self.rows = rows;
+        self.grid = vec![' '; cols as usize * rows as usize];
+        self.styles = vec![ContentStyle::default(); cols as usize * rows as usize];
+    }
+
+    pub fn cursor(&self) -> (u16, u16) {
+        self.cursor
+    }
+
+    pub fn cursor_visible(&self) -> bool {
+        self.cursor_visible
+    }
+
+    /// Returns every string printed since the last call, in order.
+    pub fn take_printed(&mut self) -> Vec<String> {
+        std::mem::take(&mut self.printed)
+    }
+
+    pub fn style_at(&self, col: u16, row: u16) -> ContentStyle {
+        self.styles[row as usize * self.cols as usize + col as usize]
+    }
+
+    /// Text of a row; the right half of a wide glyph is kept as '\0' in the grid and left out here.
+    pub fn row_text(&self, row: u16) -> String {
+        let start = row as usize * self.cols as usize;
+        let cells = &self.grid[start..start + self.cols as usize];
+        cells.iter().filter(|&&ch| ch != '\0').collect::<String>().trim_end().to_string()
+    }
+
+    /// Writes one cell the way a terminal does, blanking the other half of a wide glyph it hits.
+    fn put_cell(&mut self, col: u16, row: u16, ch: char) {
+        if col >= self.cols || row >= self.rows {
+            return;
+        }
+        let index = row as usize * self.cols as usize + col as usize;
+        // A continuation follows its own glyph, which already took the place of the old one
+        if ch != '\0' && self.grid[index] == '\0' {
+            self.grid[index - 1] = ' ';
+        }
+        if col + 1 < self.cols && self.grid[index + 1] == '\0' {
+            self.grid[index + 1] = ' ';
+        }
+        self.grid[index] = ch;
+        self.styles[index] = self.style;
+    }
+}
+
+#[cfg(test)]
+impl Terminal for HeadlessTerminal {
+    fn size(&self) -> io::Result<(u16, u16)> {
+        Ok((self.cols, self.rows))
+    }
+
+    /// Runs out of events with an error so a test that forgets to quit fails instead of hanging.
+    fn poll_event(&mut self, _timeout: Duration) -> io::Result<Option<Event>> {
+        match self.events.pop_front() {
+            Some(Event::Resize(cols, rows)) => {
+                self.set_size(cols, rows);
+                Ok(Some(Event::Resize(cols, rows)))
+            }
+            Some(event) => Ok(Some(event)),
+            None => Err(io::Error::new(io::ErrorKind::UnexpectedEof, "no more events")),
+        }
+    }
+
+    fn enter_alternate_screen(&mut self) -> io::Result<()> {
+        self.cursor_visible = false;
+        Ok(())
+    }
+
+    fn begin_update(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn end_update(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    fn clear(&mut self) -> io::Result<()> {
+        self.grid.fill(' ');
+        self.styles.fill(ContentStyle::default());
+        Ok(())
+    }
+
+    fn move_to(&mut self, col: u16, row: u16) -> io::Result<()> {
+        self.cursor = (col, row);
+        Ok(())
+    }
+
+    fn print(&mut self, text: &str) -> io::Result<()> {
+        let (mut col, row) = self.cursor;
+        for ch in text.chars() {
+            let width = glyph_width(ch) as u16;
+            for (offset, cell) in [ch, '\0'].into_iter().take(width as usize).enumerate() {
+                self.put_cell(col.saturating_add(offset as u16), row, cell);
+            }
+            col = col.saturating_add(width);
+        }
+        self.cursor = (col, row);
+        self.printed.push(text.to_string());
+        Ok(())
+    }
+
+    fn set_style(&mut self, style: ContentStyle) -> io::Result<()> {
+        self.style = style;
+        Ok(())
+    }
+
+    fn show_cursor(&mut self) -> io::Result<()> {
+        self.cursor_visible = true;
+        Ok(())
+    }
+
+    fn hide_cursor(&mut self) -> io::Result<()> {
+        self.cursor_visible = false;
+        Ok(())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+
+    /// Comes back with a blank grid, as the shell's output is gone from the alternate screen.
+    fn suspend(&mut self) -> io::Result<()> {
+        self.suspensions += 1;
+        let (cols, rows) = self.size_after_suspend.take().unwrap_or((self.cols, self.rows));
+        self.set_size(cols, rows);
+        self.cursor_visible = false;
+        Ok(())
+    }
+}
@@ -1,33 +1,164 @@
+use std::cell::RefCell;
+use std::ops::Range;
+use unicode_width::UnicodeWidthChar;
+
+/// The text cut into screen rows, kept per logical line so an edit only rewraps the lines it touched.
+/// The row a line starts on is worked out from the rows of the lines above it when asked for, so an
+/// edit that adds or removes rows only costs the lines someone looks at afterwards.
 pub struct WrapResult {
-    pub wrapped_text: String,
-    pub wrap_ids: Vec<usize>,
+    /// Rows of each logical line; an empty line still has one.
+    lines: Vec<Vec<String>>,
+    row_count: usize,
+    /// First row of the first `starts.len()` lines. Edits that move the lines below them cut it short.
+    starts: RefCell<Vec<usize>>,
 }
-pub fn wrap_content(content: &str, width: usize) -> WrapResult {
-    let effective_width = width.saturating_sub(2).max(1);
 
-    let mut result = Vec::new();
-    let mut wrap_ids = Vec::new();
+impl WrapResult {
+    fn new(lines: Vec<Vec<String>>) -> Self {
+        let row_count = lines.iter().map(Vec::len).sum();
+        WrapResult { lines, row_count, starts: RefCell::new(vec![0]) }
+    }
+
+    pub fn line_count(&self) -> usize {
+        self.lines.len()
+    }
+
+    pub fn row_count(&self) -> usize {
+        self.row_count
+    }
+
+    /// Rows of a logical line.
+    pub fn line_rows(&self, line: usize) -> &[String] {
+        self.lines.get(line).map_or(&[], Vec::as_slice)
+    }
+
+    /// First row of a logical line, or the row count for the line after the last.
+    pub fn first_row(&self, line: usize) -> usize {
+        if line >= self.lines.len() {
+            return self.row_count;
+        }
+        let mut starts = self.starts.borrow_mut();
+        while starts.len() <= line {
+            let last = starts.len() - 1;
+            let next = starts[last] + self.lines[last].len();
+            starts.push(next);
+        }
+        starts[line]
+    }
+
+    /// Rows of the logical lines `first..first + count`.
+    pub fn rows_of(&self, first: usize, count: usize) -> Range<usize> {
+        self.first_row(first)..self.first_row(first + count)
+    }
 
-    for (logical_idx, line) in content.lines().enumerate() {
-        let mut remaining = line;
+    /// Logical line a row belongs to, if there is such a row.
+    pub fn line_of_row(&self, row: usize) -> Option<usize> {
+        if row >= self.row_count {
+            return None;
+        }
+        let mut starts = self.starts.borrow_mut();
+        while starts.len() < self.lines.len() && *starts.last()? <= row {
+            let last = starts.len() - 1;
+            let next = starts[last] + self.lines[last].len();
+            starts.push(next);
+        }
+        Some(starts.partition_point(|&start| start <= row) - 1)
+    }
 
-        while !remaining.is_empty() {
-            let chunk: String = remaining.chars().take(effective_width).collect();
-            let byte_len = chunk.len();
-            remaining = &remaining[byte_len..];
+    /// Text of a row.
+    pub fn row(&self, row: usize) -> Option<&str> {
+        let line = self.line_of_row(row)?;
+        let first = self.first_row(line);
+        self.lines[line].get(row - first).map(String::as_str)
+    }
 
-            result.push(chunk);
-            wrap_ids.push(logical_idx);
+    /// Replaces the logical lines `first..first + count` with `lines`, each already wrapped by
+    /// `wrap_line`. Only the row numbers of later lines are forgotten, and only when they move.
+    pub fn replace_lines(&mut self, first: usize, count: usize, lines: Vec<Vec<String>>) {
+        let old = &self.lines[first..first + count];
+        let old_rows: usize = old.iter().map(Vec::len).sum();
+        let new_rows: usize = lines.iter().map(Vec::len).sum();
+        let same_rows = lines.len() == count && old.iter().zip(&lines).all(|(old, new)| old.len() == new.len());
+        self.lines.splice(first..first + count, lines);
+        self.row_count = self.row_count + new_rows - old_rows;
+        if !same_rows {
+            self.starts.get_mut().truncate(first + 1);
         }
+    }
+}
+
+#[cfg(test)]
+pub fn wrap_content(content: &str, width: usize) -> WrapResult {
+    // Split on '\n' rather than `lines()` so a trailing empty line still gets a row
+    wrap_lines(content.split('\n'), width)
+}
 
-        if line.is_empty() {
-            result.push(String::new());
-            wrap_ids.push(logical_idx);
+/// Columns a character takes on a terminal: two for wide glyphs such as CJK ideographs, one for
+/// the rest. Zero-width and control characters still get a column of their own.
+pub fn glyph_width(ch: char) -> usize {
+    UnicodeWidthChar::width(ch).unwrap_or(1).max(1)
+}
+
+pub fn wrap_lines<'a>(lines: impl Iterator<Item = &'a str>, width: usize) -> WrapResult {
+    WrapResult::new(lines.map(|line| wrap_line(line, width)).collect())
+}
+
+/// Rows one logical line takes at `width`; an empty line still takes one. Rows are measured in
+/// terminal columns, so a wide glyph that would not fit moves to the next row.
+pub fn wrap_line(line: &str, width: usize) -> Vec<String> {
+    let effective_width = width.saturating_sub(2).max(1);
+    let mut rows = Vec::new();
+    let mut row = String::new();
+    let mut row_width = 0;
+    for ch in line.chars() {
+        let ch_width = glyph_width(ch);
+        if row_width + ch_width > effective_width && !row.is_empty() {
+            rows.push(std::mem::take(&mut row));
+            row_width = 0;
         }
+        row.push(ch);
+        row_width += ch_width;
+    }
+    if !row.is_empty() || rows.is_empty() {
+        rows.push(row);
+    }
+    rows
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rows(wrapped: &WrapResult) -> Vec<(usize, String)> {
+        (0..wrapped.row_count()).map(|row| (wrapped.line_of_row(row).unwrap(), wrapped.row(row).unwrap().to_string())).collect()
     }
 
-    WrapResult {
-        wrapped_text: result.join("\n"),
-        wrap_ids,
+    #[test]
+    fn rows_are_found_after_lines_change_size() {
+        // Rows of four characters
+        let mut wrapped = wrap_content("abcdefg\nh\nijklm", 6);
+        assert_eq!(wrapped.rows_of(1, 2), 2..5);
+        wrapped.replace_lines(0, 1, vec![wrap_line("ab", 6)]);
+        wrapped.replace_lines(1, 0, vec![wrap_line("x", 6), wrap_line("yyyyy", 6)]);
+        assert_eq!(rows(&wrapped), rows(&wrap_content("ab\nx\nyyyyy\nh\nijklm", 6)));
+        assert_eq!(wrapped.rows_of(3, 1), 4..5);
+        assert_eq!(wrapped.line_of_row(6), Some(4));
+        assert_eq!(wrapped.line_of_row(7), None);
+
+        // Same number of rows, split differently between the lines
+        wrapped.replace_lines(3, 2, vec![wrap_line("hhhhh", 6), wrap_line("i", 6)]);
+        assert_eq!(rows(&wrapped), rows(&wrap_content("ab\nx\nyyyyy\nhhhhh\ni", 6)));
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn rows_are_cut_by_display_width() {
+        // Rows of four columns
+        assert_eq!(wrap_line("日本語", 6), vec!["日本", "語"]);
+        assert_eq!(wrap_line("a日本b", 6), vec!["a日", "本b"]);
+        assert_eq!(wrap_line("abcdefgh", 6), vec!["abcd", "efgh"]);
+        assert_eq!(wrap_line("", 6), vec![""]);
+
+        // A glyph wider than the row still gets one of its own
+        assert_eq!(wrap_line("日本", 3), vec!["日", "本"]);
+    }
+}
@@ -0,0 +1,76 @@
+/// A line that differs between two texts, with its index in the text it comes from.
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Change<'a> {
+    Removed(usize, &'a str),
+    Added(usize, &'a str),
+}
+
+/// Largest number of cells in the table used to line up the changed middle parts. Bigger changes are
+/// reported as every old line removed and every new line added.
+const MAX_TABLE: usize = 4_000_000;
+
+/// Lines removed from `old` and added in `new`, in order, keeping the longest common subsequence of
+/// lines unchanged.
+pub fn diff_lines<'a>(old: &[&'a str], new: &[&'a str]) -> Vec<Change<'a>> {
+    let prefix = old.iter().zip(new).take_while(|(a, b)| a == b).count();
+    let suffix = old[prefix..].iter().rev().zip(new[prefix..].iter().rev()).take_while(|(a, b)| a == b).count();
+    let old_middle = &old[prefix..old.len() - suffix];
+    let new_middle = &new[prefix..new.len() - suffix];
+    let removed = |i: usize| Change::Removed(prefix + i, old_middle[i]);
+    let added = |j: usize| Change::Added(prefix + j, new_middle[j]);
+
+    let (n, m) = (old_middle.len(), new_middle.len());
+    if (n + 1).saturating_mul(m + 1) > MAX_TABLE {
+        return (0..n).map(removed).chain((0..m).map(added)).collect();
+    }
+
+    // common[i][j]: length of the longest common subsequence of old_middle[i..] and new_middle[j..]
+    let mut common = vec![vec![0u32; m + 1]; n + 1];
+    for i in (0..n).rev() {
+        for j in (0..m).rev() {
+            common[i][j] = match old_middle[i] == new_middle[j] {
+                true => common[i + 1][j + 1] + 1,
+                false => common[i + 1][j].max(common[i][j + 1]),
+            };
+        }
+    }
+
+    let mut changes = Vec::new();
+    let (mut i, mut j) = (0, 0);
+    while i < n || j < m {
+        if i < n && j < m && old_middle[i] == new_middle[j] {
+            i += 1;
+            j += 1;
+        } else if i < n && (j == m || common[i + 1][j] >= common[i][j + 1]) {
+            changes.push(removed(i));
+            i += 1;
+        } else {
+            changes.push(added(j));
+            j += 1;
+        }
+    }
+    changes
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn reports_only_the_changed_lines() {
+        let old = ["a", "b", "c", "d", "e"];
+        let new = ["a", "x", "c", "e", "f"];
+        assert_eq!(diff_lines(&old, &new), vec![
+            Change::Removed(1, "b"),
+            Change::Added(1, "x"),
+            Change::Removed(3, "d"),
+            Change::Added(4, "f"),
+        ]);
+    }
+
+    #[test]
+    fn equal_texts_have_no_changes() {
+        assert!(diff_lines(&["a", "b"], &["a", "b"]).is_empty());
+        assert_eq!(diff_lines(&[], &["a"]), vec![Change::Added(0, "a")]);
+    }
+}
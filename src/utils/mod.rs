@@ -2,5 +2,6 @@
 pub mod files;
 pub mod content_wrapper;
 pub mod tokenizer;
+pub mod prose;
+pub mod diff;
 pub mod direction;
-pub mod debug;
@@ -12,11 +12,10 @@ pub fn get_route() -> String {
 
     let input_path = &args[1];
     
-    if home_regex.is_match(input_path) {
-        if let Some(home_dir) = dirs::home_dir() {
-            let expanded_path = input_path.replacen("~", &home_dir.to_string_lossy(), 1);
-            return expanded_path;
-        }
+    if home_regex.is_match(input_path)
+        && let Some(home_dir) = dirs::home_dir() {
+        let expanded_path = input_path.replacen("~", &home_dir.to_string_lossy(), 1);
+        return expanded_path;
     }
 
     if linux_regex.is_match(input_path) || windows_regex.is_match(input_path) {
@@ -30,7 +29,7 @@ pub fn get_route() -> String {
      env::current_dir().unwrap().to_str().unwrap().to_string() + "/" + &input_path.to_string()
 }
 
-fn valid_arguments(args: &Vec<String>) {
+fn valid_arguments(args: &[String]) {
     if args.len() < 2 {
         disable_raw_mode().unwrap();
         eprintln!("Error! No path specified.");
@@ -0,0 +1,86 @@
+use crate::utils::direction::Direction;
+
+/// Offsets where sentences start: the first character of the text or of a paragraph, and the first
+/// one after a `.`, `!` or `?` that ends a sentence (followed by whitespace, possibly after closing
+/// quotes or brackets).
+fn sentence_starts(text: &[char]) -> Vec<usize> {
+    let mut starts = Vec::new();
+    let mut at_start = true;
+    // A sentence end was seen and only closing quotes or brackets came after it
+    let mut ending = false;
+    let mut line_has_text = false;
+    for (i, &c) in text.iter().enumerate() {
+        if c.is_whitespace() {
+            at_start |= ending;
+            ending = false;
+            if c == '\n' {
+                // A blank line ends the paragraph, and with it the sentence
+                at_start |= !line_has_text;
+                line_has_text = false;
+            }
+            continue;
+        }
+        line_has_text = true;
+        if at_start {
+            starts.push(i);
+            at_start = false;
+        }
+        ending = matches!(c, '.' | '!' | '?') || (ending && matches!(c, ')' | ']' | '"' | '\'' | '’' | '”'));
+    }
+    starts
+}
+
+/// Offset a sentence motion from `offset` goes to: the start of the next sentence, or of the
+/// current or previous one when moving left. Stops at the ends of the text.
+pub fn sentence_stop(text: &[char], offset: usize, direction: Direction) -> usize {
+    let starts = sentence_starts(text);
+    match direction {
+        Direction::Right => starts.into_iter().find(|&start| start > offset).unwrap_or(text.len()),
+        Direction::Left => starts.into_iter().rev().find(|&start| start < offset).unwrap_or(0),
+    }
+}
+
+/// Refills the words of a paragraph into lines of at most `width` characters where words allow.
+/// The first line keeps its indentation and the others take that of the second line.
+pub fn reflow(lines: &[&str], width: usize) -> Vec<String> {
+    let indent = |line: Option<&&str>| line.map_or("", |line| &line[..line.len() - line.trim_start().len()]).to_string();
+    let first_indent = indent(lines.first());
+    let rest_indent = if lines.len() > 1 { indent(lines.get(1)) } else { first_indent.clone() };
+
+    let mut filled: Vec<String> = Vec::new();
+    let mut current = first_indent;
+    let mut empty = true;
+    for word in lines.iter().flat_map(|line| line.split_whitespace()) {
+        if !empty && current.chars().count() + 1 + word.chars().count() > width {
+            filled.push(std::mem::replace(&mut current, rest_indent.clone()));
+            empty = true;
+        }
+        if !empty {
+            current.push(' ');
+        }
+        current.push_str(word);
+        empty = false;
+    }
+    filled.push(current);
+    filled
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sentences_end_at_punctuation_and_blank_lines() {
+        let text: Vec<char> = "One. Two (three!) four? e.g.x\nnext\n\n  Para two".chars().collect();
+        assert_eq!(sentence_starts(&text), vec![0, 5, 18, 24, 38]);
+        assert_eq!(sentence_stop(&text, 5, Direction::Right), 18);
+        assert_eq!(sentence_stop(&text, 7, Direction::Left), 5);
+        assert_eq!(sentence_stop(&text, 40, Direction::Right), text.len());
+    }
+
+    #[test]
+    fn reflow_fills_lines_up_to_the_width() {
+        assert_eq!(reflow(&["  a bb", "    ccc dd e", "    ffff"], 10), vec!["  a bb ccc", "    dd e", "    ffff"]);
+        assert_eq!(reflow(&["averyveryverylongword x"], 5), vec!["averyveryverylongword", "x"]);
+    }
+}
@@ -1,8 +1,7 @@
 use std::fmt;
-use std::io::stdout;
-use crossterm::cursor::MoveTo;
-use crossterm::execute;
-use crossterm::style::Print;
+use unicode_segmentation::UnicodeSegmentation;
+use crate::utils::content_wrapper::WrapResult;
+use crate::utils::direction::Direction;
 
 #[derive(Clone, Debug)]
 pub enum TokenType{
@@ -21,182 +20,425 @@ impl fmt::Display for TokenType {
 
 #[derive(Clone)]
 pub struct Token{
-    pub id: usize,
     pub value: String,
     pub token_type: TokenType
 }
 
-#[derive(Clone)]
+/// A token and where it sits, both on screen (wrapped rows, `row_*` and `col_*`) and in the file
+/// (logical `line` and `line_col_*`). Ends are inclusive. Tokens never cross a logical line.
+#[derive(Clone, Default)]
 pub struct TokenWithPos {
     pub token: Option<Token>,
     pub col_start: Option<usize>,
     pub col_end: Option<usize>,
     pub row_start: Option<usize>,
     pub row_end: Option<usize>,
+    pub line: Option<usize>,
+    pub line_col_start: Option<usize>,
+    pub line_col_end: Option<usize>,
 }
 
+impl TokenWithPos {
+    fn new(value: &str, token_type: TokenType, start: (usize, usize), end: (usize, usize), line_cols: (usize, usize)) -> Self {
+        TokenWithPos {
+            token: Some(Token { value: value.to_string(), token_type }),
+            col_start: Some(start.1),
+            col_end: Some(end.1),
+            row_start: Some(start.0),
+            row_end: Some(end.0),
+            line: Some(0),
+            line_col_start: Some(line_cols.0),
+            line_col_end: Some(line_cols.1),
+        }
+    }
 
-fn generate_token(value: &String, id: usize,  token_type: TokenType) -> Token {
-    Token {
-        id,
-        value: value.clone(),
-        token_type
+    /// Adds text to a word, ending at the given wrapped position and logical column.
+    fn extend(&mut self, value: &str, (row, col): (usize, usize), line_col: usize) {
+        if let Some(token) = &mut self.token {
+            token.value.push_str(value);
+        }
+        self.row_end = Some(row);
+        self.col_end = Some(col);
+        self.line_col_end = Some(line_col);
     }
 }
 
-pub fn tokenize_text(wrapped_content: &String, wrap_ids: &Vec<usize>, print:bool) -> Vec<TokenWithPos>{
-    let mut tokens: Vec<Token> = Vec::new();
-    let mut token_buffer: String = String::new();
-    let mut row_index:usize = 0;
-    
-    for lines in wrapped_content.lines() {
-        if row_index != 0 && wrap_ids[row_index] != wrap_ids[row_index.saturating_sub(1)] && token_buffer.len() > 0 {
-            tokens.push(generate_token(&token_buffer, tokens.len(), TokenType::Word));
-            token_buffer.clear();
+/// What a word-boundary segment becomes.
+#[derive(PartialEq)]
+enum Segment {
+    Blank,
+    Word,
+    /// Made only of the language's extra word characters, so it joins the words around it.
+    Joiner,
+    Symbol,
+}
+
+/// Whether `c` belongs in a word: letters, digits and the language's `word_chars`.
+pub fn is_word_char(c: char, word_chars: &str) -> bool {
+    c.is_alphanumeric() || word_chars.contains(c)
+}
+
+fn segment_kind(segment: &str, word_chars: &str) -> Segment {
+    if segment.chars().all(char::is_whitespace) {
+        Segment::Blank
+    } else if segment.chars().all(|c| word_chars.contains(c)) {
+        Segment::Joiner
+    } else if segment.chars().any(char::is_alphanumeric) {
+        Segment::Word
+    } else {
+        Segment::Symbol
+    }
+}
+
+/// Splits ASCII punctuation off a segment, one character each. Word boundary rules keep `a.b`,
+/// `can't` and `x:y` whole, as in prose, but in code those are separate tokens.
+fn split_punctuation<'a>(segment: &'a str, word_chars: &str) -> Vec<&'a str> {
+    let mut pieces = Vec::new();
+    let mut start = 0;
+    for (i, c) in segment.char_indices() {
+        if c.is_ascii_punctuation() && !word_chars.contains(c) {
+            pieces.extend([&segment[start..i], &segment[i..i + 1]].into_iter().filter(|piece| !piece.is_empty()));
+            start = i + 1;
         }
-        for char in lines.chars() {
-            if char == ' ' {
-                if token_buffer.len() > 0 {
-                    tokens.push(generate_token(&token_buffer, tokens.len(), TokenType::Word));
-                    token_buffer.clear();
+    }
+    if start < segment.len() {
+        pieces.push(&segment[start..]);
+    }
+    pieces
+}
+
+/// Splits one logical line, wrapped to `rows`, into tokens at Unicode word boundaries (UAX #29)
+/// and, unless the text is `prose`, around ASCII punctuation: words, and symbols for anything else
+/// but whitespace. `word_chars` join the words on either side, like `-` in `a-b`. Rows are counted
+/// from the first row of the line, and the line is 0, until `place` puts the tokens where the line is.
+fn tokenize_line(rows: &[String], word_chars: &str, prose: bool) -> Vec<TokenWithPos> {
+    // Wrapped position of every character of the line
+    let places: Vec<(usize, usize)> = rows.iter().enumerate()
+        .flat_map(|(i, row)| (0..row.chars().count()).map(move |col| (i, col)))
+        .collect();
+    let mut tokens = Vec::new();
+    let text = rows.concat();
+    let mut word: Option<TokenWithPos> = None;
+    let mut joining = false;
+    let mut line_col = 0;
+    let segments = text.split_word_bounds().flat_map(|segment| match prose {
+        true => vec![segment],
+        false => split_punctuation(segment, word_chars),
+    });
+    for segment in segments {
+        let last_col = line_col + segment.chars().count() - 1;
+        let (start, end) = (places[line_col], places[last_col]);
+        match segment_kind(segment, word_chars) {
+            Segment::Blank => tokens.extend(word.take()),
+            kind @ (Segment::Word | Segment::Joiner) => {
+                match &mut word {
+                    Some(word) if joining || kind == Segment::Joiner => word.extend(segment, end, last_col),
+                    _ => {
+                        tokens.extend(word.take());
+                        word = Some(TokenWithPos::new(segment, TokenType::Word, start, end, (line_col, last_col)));
+                    }
                 }
-                continue;
+                joining = kind == Segment::Joiner;
             }
-            
-            else if char.is_alphanumeric() {
-                token_buffer.push(char);
+            Segment::Symbol => {
+                tokens.extend(word.take());
+                tokens.push(TokenWithPos::new(segment, TokenType::Symbol, start, end, (line_col, last_col)));
             }
-                
-            else {
-                if !token_buffer.is_empty(){
-                    tokens.push(generate_token(&token_buffer, tokens.len(), TokenType::Word));
-                    token_buffer.clear();
-                }
+        }
+        line_col = last_col + 1;
+    }
+    tokens.extend(word);
+    tokens
+}
 
-                tokens.push(generate_token(&char.to_string(), tokens.len(), TokenType::Symbol));
+/// A token of `tokenize_line` moved to `line`, whose first row is `first_row`.
+fn place(token: &TokenWithPos, first_row: usize, line: usize) -> TokenWithPos {
+    TokenWithPos {
+        row_start: token.row_start.map(|row| first_row + row),
+        row_end: token.row_end.map(|row| first_row + row),
+        line: Some(line),
+        ..token.clone()
+    }
+}
+
+/// Kinds of character a subword motion tells apart.
+#[derive(Clone, Copy, PartialEq)]
+enum Part {
+    Blank,
+    Lower,
+    Upper,
+    /// A word character that is not a letter or digit, such as `_`.
+    Separator,
+    Symbol,
+}
+
+fn part(c: char, word_chars: &str) -> Part {
+    match c {
+        c if c.is_whitespace() => Part::Blank,
+        c if c.is_uppercase() => Part::Upper,
+        c if c.is_alphanumeric() => Part::Lower,
+        c if word_chars.contains(c) => Part::Separator,
+        _ => Part::Symbol,
+    }
+}
+
+/// Column a subword motion from `col` stops at within `line`: the end of the next part when moving
+/// right, the start of the previous one when moving left. Parts split at camelCase humps
+/// (`myVar`, `HTTPServer`), around separators like `_` and between words and symbols.
+pub fn subword_stop(line: &[char], col: usize, direction: Direction, word_chars: &str) -> usize {
+    let parts: Vec<Part> = line.iter().map(|&c| part(c, word_chars)).collect();
+    let boundary = |i: usize| match (parts[i - 1], parts[i]) {
+        (Part::Upper, Part::Upper) => parts.get(i + 1) == Some(&Part::Lower),
+        (Part::Upper, Part::Lower) => false,
+        (before, after) => before != after,
+    };
+    let mut i = col.min(parts.len());
+    match direction {
+        Direction::Right => {
+            while i < parts.len() && parts[i] == Part::Blank {
+                i += 1;
+            }
+            if i < parts.len() {
+                i += 1;
+            }
+            while i < parts.len() && !boundary(i) {
+                i += 1;
             }
         }
-        row_index += 1;
+        Direction::Left => {
+            while i > 0 && parts[i - 1] == Part::Blank {
+                i -= 1;
+            }
+            i = i.saturating_sub(1);
+            while i > 0 && !boundary(i) {
+                i -= 1;
+            }
+        }
+    }
+    i
+}
+
+/// Tokens of every logical line, kept up to date as lines are edited. They are stored with rows
+/// counted from their own line, so an edit only tokenizes the lines it touched again and the tokens
+/// of other lines stay as they are, however far the edit moves them.
+pub struct TokenCache {
+    word_chars: String,
+    prose: bool,
+    lines: Vec<Vec<TokenWithPos>>,
+}
+
+impl TokenCache {
+    pub fn new(wrap_result: &WrapResult, word_chars: &str, prose: bool) -> Self {
+        let lines = (0..wrap_result.line_count())
+            .map(|line| tokenize_line(wrap_result.line_rows(line), word_chars, prose))
+            .collect();
+        TokenCache { word_chars: word_chars.to_string(), prose, lines }
     }
-    if !token_buffer.is_empty() {
-        tokens.push(generate_token(&token_buffer, tokens.len(), TokenType::Word));
+
+    /// Takes in an edit that replaced `removed` logical lines from `first` with `added` lines,
+    /// already rewrapped in `wrap_result`.
+    pub fn replace_lines(&mut self, wrap_result: &WrapResult, first: usize, removed: usize, added: usize) {
+        let fresh: Vec<_> = (first..first + added)
+            .map(|line| tokenize_line(wrap_result.line_rows(line), &self.word_chars, self.prose))
+            .collect();
+        self.lines.splice(first..first + removed, fresh);
     }
-    
-    let raw_word_count = wrapped_content.split_whitespace().count();
-    let mut token_print: String = "".to_string();
-    for token in &tokens {
-        if token_print.len() > 2000 {break;}
-        let token_str = format!("{} {} {} Ø ", token.id, token.value, token.token_type);
-        token_print.push_str(&token_str);
+
+    /// Tokens of `line`, on the rows it is wrapped to in `wrap_result`.
+    pub fn line_tokens<'a>(&'a self, wrap_result: &WrapResult, line: usize) -> impl DoubleEndedIterator<Item = TokenWithPos> + 'a {
+        let first_row = wrap_result.first_row(line);
+        self.lines.get(line).into_iter().flatten().map(move |token| place(token, first_row, line))
     }
 
-    if(print && false){
-        execute!(stdout(), MoveTo(0,55), Print(format!("Longitud: {}", wrapped_content.len()))).unwrap();
-        execute!(stdout(), MoveTo(0,56), Print(format!("Palabras: {}", raw_word_count))).unwrap();
-        execute!(stdout(), MoveTo(0,57), Print(format!("Tokens: {}", token_print ))).unwrap();
+    /// Tokens from the start of `line` to the end of the text, placed one line at a time as they are asked for.
+    pub fn tokens_after<'a>(&'a self, wrap_result: &'a WrapResult, line: usize) -> impl Iterator<Item = TokenWithPos> + 'a {
+        (line..self.lines.len()).flat_map(move |line| self.line_tokens(wrap_result, line))
+    }
+
+    /// Tokens from the end of `line` back to the start of the text, last first.
+    pub fn tokens_before<'a>(&'a self, wrap_result: &'a WrapResult, line: usize) -> impl Iterator<Item = TokenWithPos> + 'a {
+        (0..(line + 1).min(self.lines.len())).rev().flat_map(move |line| self.line_tokens(wrap_result, line).rev())
     }
-    map_tokens(wrapped_content, tokens, print)
 }
 
-pub fn map_tokens(content: &String, tokens: Vec<Token>, print: bool) -> Vec<TokenWithPos> {
-    let mut token_index = 0;
-    let mut tokens_with_pos: Vec<TokenWithPos> = Vec::new();
-    let lines: Vec<&str> = content.lines().collect();
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::utils::content_wrapper::{wrap_content, wrap_line};
+    use proptest::prelude::*;
 
-    while token_index < tokens.len() {
-        let mut row = 0;
-        while row < lines.len() {
-            let line = lines[row];
-            let line_chars: Vec<char> = line.chars().collect();
-            let mut col = 0;
+    /// Every token of the text, tokenized from scratch.
+    fn tokenize(wrapped: &WrapResult, word_chars: &str, prose: bool) -> Vec<TokenWithPos> {
+        TokenCache::new(wrapped, word_chars, prose).tokens_after(wrapped, 0).collect()
+    }
 
-            while col < line_chars.len() {
-                if token_index >= tokens.len() {
-                    break;
-                }
+    type Positions = (String, usize, usize, usize, usize, usize, usize, usize);
 
-                let current_token = &tokens[token_index];
-                let token_chars: Vec<char> = current_token.value.chars().collect();
-                
-                if col + token_chars.len() <= line_chars.len() {
-                    let matches = line_chars[col..].iter()
-                        .zip(token_chars.iter())
-                        .take(token_chars.len())
-                        .all(|(a, b)| a == b);
-
-                    if matches {
-                        tokens_with_pos.push(TokenWithPos {
-                            token: Some(current_token.clone()),
-                            row_start: Some(row),
-                            row_end: Some(row),
-                            col_start: Some(col),
-                            col_end: Some(col + token_chars.len() - 1),
-                        });
-                        col += token_chars.len();
-                        token_index += 1;
-                        continue;
-                    }
-                }
-                if line_chars[col] == token_chars[0] {
-                    let mut current_row = row;
-                    let start_col = col;
-                    let mut chars_processed = 0;
-                    let mut end_col = 0;
-                    
-                    'token_tracking: while current_row < lines.len() && chars_processed < token_chars.len() {
-                        let current_line_chars: Vec<char> = lines[current_row].chars().collect();
-                        let start_pos = if current_row == row { col } else { 0 };
-
-                        for (i, &c) in current_line_chars[start_pos..].iter().enumerate() {
-                            if chars_processed >= token_chars.len() {
-                                break 'token_tracking;
-                            }
-                            if c != token_chars[chars_processed] {
-                                break 'token_tracking;
-                            }
-                            chars_processed += 1;
-                            end_col = start_pos + i;
-                        }
-                        if chars_processed < token_chars.len() {
-                            current_row += 1;
-                        }
-                    }
-                    if chars_processed == token_chars.len() {
-                        tokens_with_pos.push(TokenWithPos {
-                            token: Some(current_token.clone()),
-                            row_start: Some(row),
-                            row_end: Some(current_row),
-                            col_start: Some(start_col),
-                            col_end: Some(end_col),
-                        });
-                        token_index += 1;
-                        break;
-                    }
-                }
+    fn positions(tokens: &[TokenWithPos]) -> Vec<Positions> {
+        tokens.iter()
+            .map(|t| (
+                t.token.as_ref().unwrap().value.clone(),
+                t.row_start.unwrap(), t.col_start.unwrap(), t.row_end.unwrap(), t.col_end.unwrap(),
+                t.line.unwrap(), t.line_col_start.unwrap(), t.line_col_end.unwrap(),
+            ))
+            .collect()
+    }
 
+    /// Writes every token's value at its position, leaving spaces elsewhere.
+    fn rebuild(lines: &[&str], tokens: &[TokenWithPos], logical: bool) -> Vec<String> {
+        let mut grid: Vec<Vec<char>> = lines.iter().map(|line| vec![' '; line.chars().count()]).collect();
+        for token in tokens {
+            let value = &token.token.as_ref().unwrap().value;
+            let (mut row, mut col) = match logical {
+                true => (token.line.unwrap(), token.line_col_start.unwrap()),
+                false => (token.row_start.unwrap(), token.col_start.unwrap()),
+            };
+            for c in value.chars() {
+                if col >= grid[row].len() {
+                    (row, col) = (row + 1, 0);
+                }
+                grid[row][col] = c;
                 col += 1;
             }
-            row += 1;
         }
+        grid.into_iter().map(|row| row.into_iter().collect()).collect()
     }
 
-    if print {
-        let mut tokens_print: String = "".to_string();
-        for token in &tokens_with_pos {
-            if token.row_start < Some(3) {
-                if let Some(t) = &token.token {
-                    let token_str = format!(" {} {} {} || y1: {} y2: {} x1: {} x2:{} Ø ",
-                                            t.id, t.value, t.token_type,
-                                            token.row_start.unwrap(),
-                                            token.row_end.unwrap(),
-                                            token.col_start.unwrap(),
-                                            token.col_end.unwrap());
-                    tokens_print.push_str(&token_str);
-                }
+    #[test]
+    fn positions_are_recorded_in_wrapped_and_logical_form() {
+        // Rows of four characters: "ab c", "defg", "h", "(x)"
+        let wrapped = wrap_content("ab cdefgh\n(x)", 6);
+        let tokens = tokenize(&wrapped, "", false);
+        assert_eq!(positions(&tokens), vec![
+            ("ab".to_string(), 0, 0, 0, 1, 0, 0, 1),
+            ("cdefgh".to_string(), 0, 3, 2, 0, 0, 3, 8),
+            ("(".to_string(), 3, 0, 3, 0, 1, 0, 0),
+            ("x".to_string(), 3, 1, 3, 1, 1, 1, 1),
+            (")".to_string(), 3, 2, 3, 2, 1, 2, 2),
+        ]);
+        assert!(tokenize(&wrap_content("", 6), "", false).is_empty());
+        // Tokens of a single line are placed on its rows
+        let cache = TokenCache::new(&wrapped, "", false);
+        assert_eq!(positions(&cache.line_tokens(&wrapped, 1).collect::<Vec<_>>()), positions(&tokens[2..]));
+        assert_eq!(positions(&cache.tokens_before(&wrapped, 1).collect::<Vec<_>>()), positions(&tokens.iter().rev().cloned().collect::<Vec<_>>()));
+    }
+
+    #[test]
+    fn subword_stops_at_humps_and_separators() {
+        let line: Vec<char> = "myVar_name  HTTPServer".chars().collect();
+        let mut stops = vec![0];
+        while *stops.last().unwrap() < line.len() {
+            stops.push(subword_stop(&line, *stops.last().unwrap(), Direction::Right, "_"));
+        }
+        assert_eq!(stops, vec![0, 2, 5, 6, 10, 16, 22]);
+
+        let mut stops = vec![line.len()];
+        while *stops.last().unwrap() > 0 {
+            stops.push(subword_stop(&line, *stops.last().unwrap(), Direction::Left, "_"));
+        }
+        assert_eq!(stops, vec![22, 16, 12, 6, 5, 2, 0]);
+    }
+
+    #[test]
+    fn word_chars_join_words() {
+        let wrapped = wrap_content("my_var a-b", 40);
+        let values = |word_chars| -> Vec<String> {
+            tokenize(&wrapped, word_chars, false).iter()
+                .map(|t| t.token.as_ref().unwrap().value.clone())
+                .collect()
+        };
+        assert_eq!(values(""), ["my", "_", "var", "a", "-", "b"]);
+        assert_eq!(values("_-"), ["my_var", "a-b"]);
+    }
+
+    #[test]
+    fn words_follow_unicode_boundaries() {
+        let wrapped = wrap_content("a\tb\u{a0}c\u{3000}e\u{301}t\u{e9} \u{65e5}\u{672c}\u{8a9e} self.x", 80);
+        let values: Vec<String> = tokenize(&wrapped, "_", false).iter()
+            .map(|t| t.token.as_ref().unwrap().value.clone())
+            .collect();
+        assert_eq!(values, ["a", "b", "c", "e\u{301}t\u{e9}", "\u{65e5}", "\u{672c}", "\u{8a9e}", "self", ".", "x"]);
+    }
+
+    fn values(text: &str, prose: bool) -> Vec<String> {
+        let wrapped = wrap_content(text, 80);
+        tokenize(&wrapped, "_", prose).iter()
+            .map(|t| t.token.as_ref().unwrap().value.clone())
+            .collect()
+    }
+
+    #[test]
+    fn prose_keeps_punctuation_inside_words() {
+        assert_eq!(values("don't 3.14 e.g. x", true), ["don't", "3.14", "e.g", ".", "x"]);
+        assert_eq!(values("don't 3.14 a.b", false), ["don", "'", "t", "3", ".", "14", "a", ".", "b"]);
+    }
+
+    #[test]
+    fn japanese_splits_at_each_kanji_and_between_scripts() {
+        assert_eq!(values("日本語のテキストです。", true), ["日", "本", "語", "の", "テキスト", "で", "す", "。"]);
+    }
+
+    #[test]
+    fn thai_splits_at_each_character() {
+        // Word boundaries in Thai need a dictionary, so each character, with its marks, is a word
+        assert_eq!(values("ภาษาไทย ง่าย", true), ["ภ", "า", "ษ", "า", "ไ", "ท", "ย", "ง\u{e48}", "า", "ย"]);
+    }
+
+    proptest! {
+        #[test]
+        fn tokens_rebuild_the_text(text in "[a-zé1 \t\u{a0}\u{3000}日本のテキภาษไทย_(){};.'\n-]{0,60}", width in 1usize..12, prose: bool) {
+            let wrapped = wrap_content(&text, width);
+            let tokens = tokenize(&wrapped, "-", prose);
+            // Whitespace is not part of any token, so it comes back as spaces
+            let blanked = |text: &str| -> String { text.chars().map(|c| if c.is_whitespace() { ' ' } else { c }).collect() };
+            let lines: Vec<String> = text.split('\n').map(blanked).collect();
+            prop_assert_eq!(rebuild(&text.split('\n').collect::<Vec<_>>(), &tokens, true), lines);
+            let rows: Vec<&str> = (0..wrapped.row_count()).filter_map(|row| wrapped.row(row)).collect();
+            prop_assert_eq!(rebuild(&rows, &tokens, false), rows.iter().map(|row| blanked(row)).collect::<Vec<_>>());
+        }
+
+        #[test]
+        fn cached_tokens_match_a_full_tokenization(text in "[ab (\n]{0,30}", edits in proptest::collection::vec((0usize..8, 0usize..3, "[ab (\n]{0,12}"), 1..5)) {
+            let mut lines: Vec<String> = text.split('\n').map(str::to_string).collect();
+            let mut wrapped = wrap_content(&text, 5);
+            let mut cache = TokenCache::new(&wrapped, "", false);
+            for (first, removed, replacement) in edits {
+                let first = first.min(lines.len() - 1);
+                let removed = removed.min(lines.len() - first);
+                edit(&mut lines, &mut wrapped, &mut cache, first, removed, &replacement);
+                let full = wrap_content(&lines.join("\n"), 5);
+                prop_assert_eq!(rows(&wrapped), rows(&full));
+                let cached: Vec<TokenWithPos> = cache.tokens_after(&wrapped, 0).collect();
+                prop_assert_eq!(positions(&cached), positions(&tokenize(&full, "", false)));
             }
         }
-        execute!(stdout(), MoveTo(0,57), Print(format!("Tokens: {}", tokens_print ))).unwrap();
     }
 
-    tokens_with_pos
-}
\ No newline at end of file
+    /// Every row with the line it belongs to.
+    fn rows(wrapped: &WrapResult) -> Vec<(Option<usize>, Option<&str>)> {
+        (0..wrapped.row_count()).map(|row| (wrapped.line_of_row(row), wrapped.row(row))).collect()
+    }
+
+    /// Replaces `removed` lines from `first` with the lines of `replacement`, in the text and its view.
+    fn edit(lines: &mut Vec<String>, wrapped: &mut WrapResult, cache: &mut TokenCache, first: usize, removed: usize, replacement: &str) {
+        let new_lines: Vec<String> = replacement.split('\n').map(str::to_string).collect();
+        let added = new_lines.len();
+        wrapped.replace_lines(first, removed, new_lines.iter().map(|line| wrap_line(line, 5)).collect());
+        lines.splice(first..first + removed, new_lines);
+        cache.replace_lines(wrapped, first, removed, added);
+    }
+
+    #[test]
+    fn cached_tokens_match_a_full_tokenization_after_edits() {
+        let text = "let a = 1;\nfoo(bar)\n\nbaz";
+        let mut lines: Vec<String> = text.split('\n').map(str::to_string).collect();
+        let mut wrapped = wrap_content(text, 5);
+        let mut cache = TokenCache::new(&wrapped, "", false);
+        for (first, removed, replacement) in [(1, 1, "foo(bar, qux)"), (1, 0, "new"), (0, 4, "x"), (1, 1, "baz")] {
+            edit(&mut lines, &mut wrapped, &mut cache, first, removed, replacement);
+            let full = wrap_content(&lines.join("\n"), 5);
+            let cached: Vec<TokenWithPos> = cache.tokens_after(&wrapped, 0).collect();
+            assert_eq!(positions(&cached), positions(&tokenize(&full, "", false)), "{:?}", lines);
+        }
+    }
+}
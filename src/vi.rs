@@ -0,0 +1,524 @@
+use crossterm::event::{KeyCode, KeyModifiers};
+use crate::utils::tokenizer::is_word_char;
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ViMode {
+    Normal,
+    Insert,
+    Visual,
+    VisualLine,
+}
+
+impl ViMode {
+    pub fn name(self) -> &'static str {
+        match self {
+            ViMode::Normal => "NORMAL",
+            ViMode::Insert => "INSERT",
+            ViMode::Visual => "VISUAL",
+            ViMode::VisualLine => "VISUAL LINE",
+        }
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Motion {
+    Left,
+    Right,
+    Up,
+    Down,
+    WordStart,
+    WordBackward,
+    WordEnd,
+    LineStart,
+    FirstNonBlank,
+    LineEnd,
+    FileStart,
+    FileEnd,
+    /// `G` with a count: go to that line, counted from 1.
+    GotoLine(usize),
+    MatchingBracket,
+    SentenceBackward,
+    SentenceForward,
+    ParagraphBackward,
+    ParagraphForward,
+}
+
+impl Motion {
+    /// Operators on these motions work on whole lines.
+    pub fn is_linewise(self) -> bool {
+        matches!(self, Motion::Up | Motion::Down | Motion::FileStart | Motion::FileEnd | Motion::GotoLine(_))
+    }
+
+    /// Operators on these motions include the character the motion lands on.
+    pub fn is_inclusive(self) -> bool {
+        matches!(self, Motion::WordEnd | Motion::LineEnd | Motion::MatchingBracket)
+    }
+
+    /// Moving with these records the starting position in the jump list.
+    pub fn is_jump(self) -> bool {
+        matches!(self, Motion::FileStart | Motion::FileEnd | Motion::GotoLine(_) | Motion::MatchingBracket)
+    }
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Operator {
+    Delete,
+    Change,
+    Yank,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum TextObject {
+    Word { around: bool },
+    Bracket { open: char, close: char, around: bool },
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum Target {
+    Motion(Motion),
+    Object(TextObject),
+    /// A doubled operator such as `dd`: the current line and `count - 1` below it.
+    Lines,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum InsertAt {
+    Before,
+    After,
+    LineStart,
+    LineEnd,
+    LineBelow,
+    LineAbove,
+}
+
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum ViCommand {
+    Move(Motion, usize),
+    Operate(Operator, Target, usize),
+    OperateSelection(Operator),
+    SelectObject(TextObject),
+    Insert(InsertAt),
+    DeleteChar(usize),
+    Put { before: bool, count: usize },
+    Undo(usize),
+    Redo(usize),
+    Visual { line: bool },
+    /// `zz`: scroll so the cursor line is in the middle of the screen.
+    Center,
+    /// `m{name}`.
+    SetMark(char),
+    /// `'{name}` goes to the first non-blank of the mark's line, `` `{name} `` to the mark itself.
+    GotoMark { name: char, linewise: bool },
+    Escape,
+}
+
+pub enum ViKey {
+    /// More keys are needed to complete the command.
+    Pending,
+    Command(ViCommand),
+    /// Not a vi key: the editor's keymap handles it.
+    Unhandled,
+}
+
+/// Parses vi keys into commands. The editor owns the mode and executes the commands.
+pub struct Vi {
+    pub mode: ViMode,
+    count: Option<usize>,
+    operator: Option<(Operator, usize)>,
+    prefix: Option<char>,
+}
+
+impl Default for Vi {
+    fn default() -> Self {
+        Self { mode: ViMode::Normal, count: None, operator: None, prefix: None }
+    }
+}
+
+impl Vi {
+    /// Keys typed so far for an unfinished command, for the status line.
+    pub fn pending(&self) -> String {
+        let mut text = String::new();
+        if let Some((operator, count)) = self.operator {
+            if count > 1 {
+                text.push_str(&count.to_string());
+            }
+            text.push(operator_key(operator));
+        }
+        if let Some(count) = self.count {
+            text.push_str(&count.to_string());
+        }
+        if let Some(prefix) = self.prefix {
+            text.push(prefix);
+        }
+        text
+    }
+
+    fn is_pending(&self) -> bool {
+        self.count.is_some() || self.operator.is_some() || self.prefix.is_some()
+    }
+
+    fn reset(&mut self) {
+        self.count = None;
+        self.operator = None;
+        self.prefix = None;
+    }
+
+    pub fn handle_key(&mut self, code: KeyCode, modifiers: KeyModifiers) -> ViKey {
+        if code == KeyCode::Esc {
+            self.reset();
+            return ViKey::Command(ViCommand::Escape);
+        }
+        if self.mode == ViMode::Insert {
+            return ViKey::Unhandled;
+        }
+        if (code, modifiers) == (KeyCode::Char('r'), KeyModifiers::CONTROL) {
+            let count = self.take_count();
+            self.reset();
+            return ViKey::Command(ViCommand::Redo(count));
+        }
+        let key = match code {
+            KeyCode::Char(c) if !modifiers.intersects(KeyModifiers::CONTROL | KeyModifiers::ALT) => c,
+            _ if self.is_pending() => {
+                self.reset();
+                return ViKey::Pending;
+            }
+            _ => return ViKey::Unhandled,
+        };
+
+        match self.parse(key) {
+            Some(command) => {
+                self.reset();
+                ViKey::Command(command)
+            }
+            None if self.is_pending() => ViKey::Pending,
+            None => ViKey::Unhandled,
+        }
+    }
+
+    /// Returns a command once `key` completes one; keeps state in `self` otherwise.
+    fn parse(&mut self, key: char) -> Option<ViCommand> {
+        let visual = matches!(self.mode, ViMode::Visual | ViMode::VisualLine);
+
+        if let Some(prefix) = self.prefix.take() {
+            return match (prefix, key) {
+                ('g', 'g') => self.motion(Motion::FileStart),
+                ('z', 'z') if self.operator.is_none() => {
+                    self.reset();
+                    Some(ViCommand::Center)
+                }
+                ('m', _) => Some(ViCommand::SetMark(key)),
+                ('\'' | '`', _) => Some(ViCommand::GotoMark { name: key, linewise: prefix == '\'' }),
+                ('i' | 'a', _) => {
+                    let object = text_object(key, prefix == 'a')?;
+                    match self.operator.take() {
+                        Some((operator, count)) => Some(ViCommand::Operate(operator, Target::Object(object), count)),
+                        None => Some(ViCommand::SelectObject(object)),
+                    }
+                }
+                _ => {
+                    self.reset();
+                    None
+                }
+            };
+        }
+
+        if let Some(digit) = key.to_digit(10)
+            && (digit != 0 || self.count.is_some()) {
+            self.count = Some(self.count.unwrap_or(0).saturating_mul(10).saturating_add(digit as usize));
+            return None;
+        }
+
+        let motion = match key {
+            'h' => Some(Motion::Left),
+            'l' | ' ' => Some(Motion::Right),
+            'k' => Some(Motion::Up),
+            'j' => Some(Motion::Down),
+            'w' => Some(Motion::WordStart),
+            'b' => Some(Motion::WordBackward),
+            'e' => Some(Motion::WordEnd),
+            '0' => Some(Motion::LineStart),
+            '^' => Some(Motion::FirstNonBlank),
+            '$' => Some(Motion::LineEnd),
+            '%' => Some(Motion::MatchingBracket),
+            '(' => Some(Motion::SentenceBackward),
+            ')' => Some(Motion::SentenceForward),
+            '{' => Some(Motion::ParagraphBackward),
+            '}' => Some(Motion::ParagraphForward),
+            'G' => Some(match self.count.take() {
+                Some(line) => Motion::GotoLine(line),
+                None => Motion::FileEnd,
+            }),
+            _ => None,
+        };
+        if let Some(motion) = motion {
+            return self.motion(motion);
+        }
+
+        if let Some(operator) = operator_for(key) {
+            if visual {
+                return Some(ViCommand::OperateSelection(operator));
+            }
+            let count = self.take_count();
+            return match self.operator.take() {
+                Some((pending, pending_count)) if pending == operator => {
+                    Some(ViCommand::Operate(operator, Target::Lines, pending_count * count))
+                }
+                Some(_) => None,
+                None => {
+                    self.operator = Some((operator, count));
+                    None
+                }
+            };
+        }
+
+        if (self.operator.is_some() || visual) && (key == 'i' || key == 'a') {
+            self.prefix = Some(key);
+            return None;
+        }
+        if key == 'g' || (matches!(key, 'z' | 'm' | '\'' | '`') && self.operator.is_none()) {
+            self.prefix = Some(key);
+            return None;
+        }
+        if self.operator.is_some() {
+            self.reset();
+            return None;
+        }
+
+        let count = self.take_count();
+        match key {
+            'x' if visual => Some(ViCommand::OperateSelection(Operator::Delete)),
+            'x' => Some(ViCommand::DeleteChar(count)),
+            'p' => Some(ViCommand::Put { before: false, count }),
+            'P' => Some(ViCommand::Put { before: true, count }),
+            'u' => Some(ViCommand::Undo(count)),
+            'v' => Some(ViCommand::Visual { line: false }),
+            'V' => Some(ViCommand::Visual { line: true }),
+            _ if visual => None,
+            'i' => Some(ViCommand::Insert(InsertAt::Before)),
+            'a' => Some(ViCommand::Insert(InsertAt::After)),
+            'I' => Some(ViCommand::Insert(InsertAt::LineStart)),
+            'A' => Some(ViCommand::Insert(InsertAt::LineEnd)),
+            'o' => Some(ViCommand::Insert(InsertAt::LineBelow)),
+            'O' => Some(ViCommand::Insert(InsertAt::LineAbove)),
+            _ => None,
+        }
+    }
+
+    fn motion(&mut self, motion: Motion) -> Option<ViCommand> {
+        let count = self.take_count();
+        match self.operator.take() {
+            Some((operator, operator_count)) => Some(ViCommand::Operate(operator, Target::Motion(motion), operator_count * count)),
+            None => Some(ViCommand::Move(motion, count)),
+        }
+    }
+
+    fn take_count(&mut self) -> usize {
+        self.count.take().unwrap_or(1).max(1)
+    }
+}
+
+fn operator_for(key: char) -> Option<Operator> {
+    match key {
+        'd' => Some(Operator::Delete),
+        'c' => Some(Operator::Change),
+        'y' => Some(Operator::Yank),
+        _ => None,
+    }
+}
+
+fn operator_key(operator: Operator) -> char {
+    match operator {
+        Operator::Delete => 'd',
+        Operator::Change => 'c',
+        Operator::Yank => 'y',
+    }
+}
+
+fn text_object(key: char, around: bool) -> Option<TextObject> {
+    let (open, close) = match key {
+        'w' => return Some(TextObject::Word { around }),
+        '(' | ')' | 'b' => ('(', ')'),
+        '[' | ']' => ('[', ']'),
+        '{' | '}' | 'B' => ('{', '}'),
+        '<' | '>' => ('<', '>'),
+        _ => return None,
+    };
+    Some(TextObject::Bracket { open, close, around })
+}
+
+#[derive(PartialEq)]
+enum CharClass {
+    Blank,
+    Word,
+    Symbol,
+}
+
+fn char_class(c: char, word_chars: &str) -> CharClass {
+    if c.is_whitespace() {
+        CharClass::Blank
+    } else if is_word_char(c, word_chars) {
+        CharClass::Word
+    } else {
+        CharClass::Symbol
+    }
+}
+
+/// Column range `[start, end)` of the word (`iw`) or word plus surrounding blanks (`aw`) at `col`.
+/// Words take in `word_chars` as well as letters and digits, as in the tokenizer.
+pub fn word_object(line: &str, col: usize, around: bool, word_chars: &str) -> Option<(usize, usize)> {
+    let chars: Vec<char> = line.chars().collect();
+    let col = col.min(chars.len().checked_sub(1)?);
+    let class = char_class(chars[col], word_chars);
+
+    let mut start = col;
+    while start > 0 && char_class(chars[start - 1], word_chars) == class {
+        start -= 1;
+    }
+    let mut end = col + 1;
+    while end < chars.len() && char_class(chars[end], word_chars) == class {
+        end += 1;
+    }
+    if !around || class == CharClass::Blank {
+        return Some((start, end));
+    }
+
+    let mut around_end = end;
+    while around_end < chars.len() && chars[around_end].is_whitespace() {
+        around_end += 1;
+    }
+    if around_end > end {
+        return Some((start, around_end));
+    }
+    // No trailing blanks: take the leading ones instead, like vi
+    while start > 0 && chars[start - 1].is_whitespace() {
+        start -= 1;
+    }
+    Some((start, end))
+}
+
+/// Offset range `[start, end)` inside (`i(`) or around (`a(`) the closest bracket pair enclosing `offset`.
+pub fn bracket_object(chars: &[char], offset: usize, open: char, close: char, around: bool) -> Option<(usize, usize)> {
+    let offset = offset.min(chars.len().checked_sub(1)?);
+
+    let open_at = if chars[offset] == open {
+        offset
+    } else {
+        let mut depth = 0;
+        let mut found = None;
+        for i in (0..offset).rev() {
+            if chars[i] == close {
+                depth += 1;
+            } else if chars[i] == open {
+                if depth == 0 {
+                    found = Some(i);
+                    break;
+                }
+                depth -= 1;
+            }
+        }
+        found?
+    };
+
+    let mut depth = 0;
+    let close_at = (open_at + 1..chars.len()).find(|&i| {
+        if chars[i] == open {
+            depth += 1;
+        } else if chars[i] == close {
+            if depth == 0 {
+                return true;
+            }
+            depth -= 1;
+        }
+        false
+    })?;
+
+    Some(if around { (open_at, close_at + 1) } else { (open_at + 1, close_at) })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn keys(vi: &mut Vi, text: &str) -> Vec<ViCommand> {
+        text.chars()
+            .filter_map(|c| match vi.handle_key(KeyCode::Char(c), KeyModifiers::NONE) {
+                ViKey::Command(command) => Some(command),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn counts_multiply_across_operator_and_motion() {
+        let mut vi = Vi::default();
+        assert_eq!(keys(&mut vi, "2d3w"), vec![ViCommand::Operate(Operator::Delete, Target::Motion(Motion::WordStart), 6)]);
+    }
+
+    #[test]
+    fn doubled_operator_works_on_lines() {
+        let mut vi = Vi::default();
+        assert_eq!(keys(&mut vi, "3yy"), vec![ViCommand::Operate(Operator::Yank, Target::Lines, 3)]);
+    }
+
+    #[test]
+    fn zero_is_a_motion_unless_it_continues_a_count() {
+        let mut vi = Vi::default();
+        assert_eq!(keys(&mut vi, "0"), vec![ViCommand::Move(Motion::LineStart, 1)]);
+        assert_eq!(keys(&mut vi, "10j"), vec![ViCommand::Move(Motion::Down, 10)]);
+    }
+
+    #[test]
+    fn text_objects_follow_operators() {
+        let mut vi = Vi::default();
+        assert_eq!(keys(&mut vi, "ci("), vec![ViCommand::Operate(
+            Operator::Change,
+            Target::Object(TextObject::Bracket { open: '(', close: ')', around: false }),
+            1,
+        )]);
+        assert_eq!(keys(&mut vi, "daw"), vec![ViCommand::Operate(Operator::Delete, Target::Object(TextObject::Word { around: true }), 1)]);
+    }
+
+    #[test]
+    fn gg_and_counted_g() {
+        let mut vi = Vi::default();
+        assert_eq!(keys(&mut vi, "gg5G"), vec![ViCommand::Move(Motion::FileStart, 1), ViCommand::Move(Motion::GotoLine(5), 1)]);
+        assert_eq!(keys(&mut vi, "zz"), vec![ViCommand::Center]);
+    }
+
+    #[test]
+    fn marks_take_a_name() {
+        let mut vi = Vi::default();
+        assert_eq!(keys(&mut vi, "ma'a`b"), vec![
+            ViCommand::SetMark('a'),
+            ViCommand::GotoMark { name: 'a', linewise: true },
+            ViCommand::GotoMark { name: 'b', linewise: false },
+        ]);
+        assert_eq!(keys(&mut vi, "dm"), vec![]);
+    }
+
+    #[test]
+    fn insert_mode_leaves_keys_to_the_editor() {
+        let mut vi = Vi { mode: ViMode::Insert, ..Vi::default() };
+        assert!(matches!(vi.handle_key(KeyCode::Char('d'), KeyModifiers::NONE), ViKey::Unhandled));
+        assert!(matches!(vi.handle_key(KeyCode::Esc, KeyModifiers::NONE), ViKey::Command(ViCommand::Escape)));
+    }
+
+    #[test]
+    fn word_object_ranges() {
+        assert_eq!(word_object("foo bar  baz", 5, false, ""), Some((4, 7)));
+        assert_eq!(word_object("foo bar  baz", 5, true, ""), Some((4, 9)));
+        assert_eq!(word_object("foo bar", 5, true, ""), Some((3, 7)));
+        assert_eq!(word_object("", 0, false, ""), None);
+        assert_eq!(word_object("a my_var", 4, false, "_"), Some((2, 8)));
+        assert_eq!(word_object("a my_var", 4, false, ""), Some((4, 5)));
+    }
+
+    #[test]
+    fn bracket_object_skips_nested_pairs() {
+        let chars: Vec<char> = "f(a, (b), c)".chars().collect();
+        assert_eq!(bracket_object(&chars, 3, '(', ')', false), Some((2, 11)));
+        assert_eq!(bracket_object(&chars, 6, '(', ')', true), Some((5, 8)));
+        assert_eq!(bracket_object(&chars, 11, '(', ')', false), Some((2, 11)));
+        assert_eq!(bracket_object(&chars, 0, '[', ']', false), None);
+    }
+}